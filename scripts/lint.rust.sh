@@ -18,5 +18,9 @@ for canister in "${CANISTERS[@]}"; do
     --manifest-path "$manifest_path" \
     --locked \
     --target wasm32-unknown-unknown \
-    --all-features
+    --all-features \
+    -- -D warnings
 done
+
+# Host build of the whole workspace, including tests and the integration test crate.
+cargo clippy --workspace --all-targets --locked --all-features -- -D warnings
@@ -2,17 +2,22 @@ use candid::Principal;
 use ic_cdk::{api::time, call, eprintln, id, query, spawn, update};
 use ic_papi_api::PaymentType;
 use shared::{
-    auth::{verify_download_token, verify_token},
-    types::{DownloadToken, FileId, UploadToken},
+    auth::{verify_delete_token, verify_download_token, verify_token},
+    constants::MAX_COPY_BATCH_CHUNKS,
+    integrity::chunk_sha256,
+    types::{ChunkInfo, DeleteToken, DownloadToken, FileId, UploadToken},
     CanisterStatus,
 };
 
 use crate::{
     errors::BucketError,
-    memory::CHUNKS,
+    memory::{CHUNKS, CHUNK_DIGESTS, SEALED_FILES, UNREPORTED_CHUNKS},
     payments::{SignerMethods, PAYMENT_GUARD},
-    results::{AdminWithdrawResult, DeleteFileResult, GetChunkResult, PutChunkResult},
-    types::{ChunkKey, ChunkValue},
+    results::{
+        AdminWithdrawResult, CopyChunksResult, DeleteFileResult, GetChunkManifestResult,
+        GetChunkResult, PutChunkResult,
+    },
+    types::{ChunkDigest, ChunkKey, ChunkValue},
     AdminSetReadOnlyResult,
 };
 
@@ -24,180 +29,461 @@ pub async fn put_chunk(
     payment: Option<PaymentType>,
 ) -> PutChunkResult {
     let result: Result<u32, BucketError> = async {
-        let ptype = payment.unwrap_or(PaymentType::AttachedCycles);
-        // 1. PAPI Payment Deduction
-        PAYMENT_GUARD
-            .deduct(ptype.clone(), SignerMethods::PutChunk.fee(&ptype))
-            .await
-            .map_err(|e| BucketError::PaymentFailed(format!("Payment failed: {:?}", e)))?;
-
-        // 2. Check Read-Only Mode
+        // 1. Check Read-Only Mode
         if crate::memory::read_config(|c| c.read_only.unwrap_or(false)) {
             return Err(BucketError::ReadOnly);
         }
 
-        // 3. Verify Token Signature
+        // 2. Verify Token Signature
         let secret = crate::memory::read_config(|c| c.shared_secret.clone().unwrap_or_default());
         if !verify_token(&token, &secret) {
             return Err(BucketError::InvalidSignature);
         }
 
-        // 2. Verify Expiry
+        // 3. Verify Expiry
         if token.expires_at < time() {
             return Err(BucketError::TokenExpired);
         }
 
-        // 3. Verify Bucket ID (token must be for THIS bucket)
+        // 4. Verify Bucket ID (token must be for THIS bucket)
         if token.bucket_id != id() {
             return Err(BucketError::WrongBucket);
         }
+        check_directory(token.directory_id)?;
 
-        // 4. Verify Chunk Index
+        // 5. Verify Chunk Index
         if !token.allowed_chunks.contains(&chunk_index) {
             return Err(BucketError::ChunkNotAllowed(chunk_index));
         }
 
-        let file_id = &token.file_id;
-        let owner_bytes = file_id.owner.as_slice();
-        let mut owner = [0u8; 29];
-        owner[..owner_bytes.len()].copy_from_slice(owner_bytes);
+        let key = chunk_key(&token.file_id, chunk_index)?;
+        let size = bytes.len() as u32;
+        let digest = digest_of(&bytes);
+
+        // 6. A retry of a stored chunk succeeds without being charged or stored again
+        if let Some(stored) = chunk_digest(&key) {
+            if stored == digest {
+                if UNREPORTED_CHUNKS.with(|u| u.borrow().contains_key(&key)) {
+                    report_chunk(&token, chunk_index, key);
+                }
+                return Ok(size);
+            }
+            ensure_unsealed(&token.file_id, chunk_index)?;
+        }
+
+        // 7. PAPI Payment Deduction
+        let ptype = payment.unwrap_or(PaymentType::AttachedCycles);
+        PAYMENT_GUARD
+            .deduct(ptype.clone(), SignerMethods::PutChunk.fee(&ptype))
+            .await
+            .map_err(|e| BucketError::PaymentFailed(format!("Payment failed: {:?}", e)))?;
 
-        let mut fid = [0u8; 16];
-        if file_id.id.len() != 16 {
-            return Err(BucketError::InvalidFileId);
+        // The file may have been committed while the payment was processed
+        if CHUNKS.with(|c| c.borrow().contains_key(&key)) {
+            ensure_unsealed(&token.file_id, chunk_index)?;
         }
-        fid.copy_from_slice(&file_id.id);
+        store_chunk(key.clone(), bytes);
 
-        let key = ChunkKey {
-            owner,
-            owner_len: owner_bytes.len() as u8,
-            file_id: fid,
-            chunk_index,
-        };
+        // 8. Notify Directory (Async)
+        report_chunk(&token, chunk_index, key);
 
-        let size = bytes.len() as u32;
-        CHUNKS.with(|c| {
-            c.borrow_mut().insert(key, ChunkValue(bytes));
-        });
+        Ok(size)
+    }
+    .await;
+
+    result.into()
+}
 
-        // 5. Notify Directory (Async)
-        let directory_id = token.directory_id;
-        let upload_id = token.upload_id.clone();
-
-        spawn(async move {
-            // Ignore the response type using candid::Reserved
-            let res: Result<(candid::Reserved,), _> = call(
-                directory_id,
-                "report_chunk_uploaded",
-                (upload_id, chunk_index),
-            )
-            .await;
-            if let Err((code, msg)) = res {
+/// Rejects replacing chunk `chunk_index` of a committed file.
+fn ensure_unsealed(file_id: &FileId, chunk_index: u32) -> Result<(), BucketError> {
+    let file_key = chunk_key(file_id, 0)?;
+    if SEALED_FILES.with(|s| s.borrow().contains_key(&file_key)) {
+        return Err(BucketError::ChunkConflict(chunk_index));
+    }
+    Ok(())
+}
+
+/// Tells the directory that a chunk of the token's upload is stored. The chunk stays in
+/// `UNREPORTED_CHUNKS` until the directory answers.
+fn report_chunk(token: &UploadToken, chunk_index: u32, key: ChunkKey) {
+    UNREPORTED_CHUNKS.with(|u| u.borrow_mut().insert(key.clone(), ()));
+
+    let directory_id = token.directory_id;
+    let upload_id = token.upload_id.clone();
+    spawn(async move {
+        // Ignore the response type using candid::Reserved
+        let res: Result<(candid::Reserved,), _> = call(
+            directory_id,
+            "report_chunk_uploaded",
+            (upload_id, chunk_index),
+        )
+        .await;
+        match res {
+            Ok(_) => {
+                UNREPORTED_CHUNKS.with(|u| u.borrow_mut().remove(&key));
+            }
+            Err((code, msg)) => {
                 eprintln!(
                     "Failed to report chunk upload to directory: {:?} {}",
                     code, msg
                 );
             }
-        });
+        }
+    });
+}
 
-        Ok(size)
-    }
-    .await;
+#[query]
+pub fn get_chunk(token: DownloadToken, chunk_index: u32) -> GetChunkResult {
+    let result: Result<Vec<u8>, BucketError> = (|| {
+        check_download_token(&token)?;
+
+        let key = chunk_key(&token.file_id, chunk_index)?;
+        CHUNKS.with(|c| {
+            c.borrow()
+                .get(&key)
+                .map(|v| v.0.clone())
+                .ok_or(BucketError::ChunkNotFound)
+        })
+    })();
 
     result.into()
 }
 
+/// Lists the length and SHA-256 digest of the chunks of the token's file, from chunk 0 up to
+/// `chunk_count`. Missing chunks are left out.
 #[query]
-pub fn get_chunk(token: DownloadToken, chunk_index: u32) -> GetChunkResult {
-    let result: Result<Vec<u8>, BucketError> = (|| {
-        // 1. Verify Token Signature
-        let secret = crate::memory::read_config(|c| c.shared_secret.clone().unwrap_or_default());
-        if !verify_download_token(&token, &secret) {
-            return Err(BucketError::InvalidSignature);
+pub fn get_chunk_manifest(token: DownloadToken, chunk_count: u32) -> GetChunkManifestResult {
+    let result: Result<Vec<ChunkInfo>, BucketError> = (|| {
+        check_download_token(&token)?;
+        chunk_manifest(&token.file_id, chunk_count)
+    })();
+
+    result.into()
+}
+
+/// Seals the token's file: its stored chunks can no longer be replaced through `put_chunk`. Only
+/// the directory can seal, once it commits the upload. Returns the manifest of the sealed chunks
+/// like `get_chunk_manifest`.
+#[update]
+pub fn seal_chunks(token: DownloadToken, chunk_count: u32) -> GetChunkManifestResult {
+    let result: Result<Vec<ChunkInfo>, BucketError> = (|| {
+        if ic_cdk::caller() != token.directory_id {
+            return Err(BucketError::Unauthorized);
         }
+        check_download_token(&token)?;
 
-        // 2. Verify Expiry
-        if token.expires_at < time() {
-            return Err(BucketError::TokenExpired);
+        let file_key = chunk_key(&token.file_id, 0)?;
+        SEALED_FILES.with(|s| s.borrow_mut().insert(file_key, ()));
+        chunk_manifest(&token.file_id, chunk_count)
+    })();
+
+    result.into()
+}
+
+/// Lists the length and digest of the stored chunks of `file_id` below `chunk_count`.
+fn chunk_manifest(file_id: &FileId, chunk_count: u32) -> Result<Vec<ChunkInfo>, BucketError> {
+    let mut chunks = Vec::new();
+    for chunk_index in 0..chunk_count {
+        let key = chunk_key(file_id, chunk_index)?;
+        if let Some(digest) = chunk_digest(&key) {
+            chunks.push(ChunkInfo {
+                chunk_index,
+                size_bytes: digest.size_bytes,
+                sha256: digest.sha256.to_vec(),
+            });
         }
+    }
+    Ok(chunks)
+}
 
-        // 3. Verify Bucket ID (token must be for THIS bucket)
-        if token.bucket_id != id() {
-            return Err(BucketError::WrongBucket);
+/// Checks that a download token was signed by the directory, is still valid and is for this
+/// bucket.
+fn check_download_token(token: &DownloadToken) -> Result<(), BucketError> {
+    // 1. Verify Token Signature
+    let secret = crate::memory::read_config(|c| c.shared_secret.clone().unwrap_or_default());
+    if !verify_download_token(token, &secret) {
+        return Err(BucketError::InvalidSignature);
+    }
+
+    // 2. Verify Expiry
+    if token.expires_at < time() {
+        return Err(BucketError::TokenExpired);
+    }
+
+    // 3. Verify Bucket ID (token must be for THIS bucket)
+    if token.bucket_id != id() {
+        return Err(BucketError::WrongBucket);
+    }
+    check_directory(token.directory_id)
+}
+
+/// Copies the chunks of another file into the file of `target`. Only the directory can copy.
+///
+/// `target` authorizes writing its `allowed_chunks` to this bucket, and each of them is filled
+/// with the same chunk of `source`. The source may live on this bucket or on another one, in
+/// which case its chunks are fetched from there. At most `MAX_COPY_BATCH_CHUNKS` chunks are copied
+/// per call. Returns the number of copied chunks.
+#[update]
+pub async fn copy_chunks(source: DownloadToken, target: UploadToken) -> CopyChunksResult {
+    let result: Result<u32, BucketError> = async {
+        if ic_cdk::caller() != target.directory_id {
+            return Err(BucketError::Unauthorized);
         }
+        check_directory(target.directory_id)?;
 
-        let file_id = &token.file_id;
-        let owner_bytes = file_id.owner.as_slice();
-        let mut owner = [0u8; 29];
-        owner[..owner_bytes.len()].copy_from_slice(owner_bytes);
+        if crate::memory::read_config(|c| c.read_only.unwrap_or(false)) {
+            return Err(BucketError::ReadOnly);
+        }
 
-        let mut fid = [0u8; 16];
-        if file_id.id.len() != 16 {
-            return Err(BucketError::InvalidFileId);
+        let secret = crate::memory::read_config(|c| c.shared_secret.clone().unwrap_or_default());
+        if !verify_token(&target, &secret) {
+            return Err(BucketError::InvalidSignature);
+        }
+        if target.expires_at < time() {
+            return Err(BucketError::TokenExpired);
+        }
+        if target.bucket_id != id() {
+            return Err(BucketError::WrongBucket);
+        }
+        if target.allowed_chunks.len() > MAX_COPY_BATCH_CHUNKS as usize {
+            return Err(BucketError::Other(format!(
+                "At most {} chunks can be copied per call",
+                MAX_COPY_BATCH_CHUNKS
+            )));
         }
-        fid.copy_from_slice(&file_id.id);
 
-        let key = ChunkKey {
-            owner,
-            owner_len: owner_bytes.len() as u8,
-            file_id: fid,
-            chunk_index,
-        };
+        for &chunk_index in &target.allowed_chunks {
+            let bytes = if source.bucket_id == id() {
+                match get_chunk(source.clone(), chunk_index) {
+                    GetChunkResult::Ok(bytes) => bytes,
+                    GetChunkResult::Err(e) => return Err(e),
+                }
+            } else {
+                let res: Result<(GetChunkResult,), _> =
+                    call(source.bucket_id, "get_chunk", (source.clone(), chunk_index)).await;
+                match res {
+                    Ok((GetChunkResult::Ok(bytes),)) => bytes,
+                    Ok((GetChunkResult::Err(e),)) => return Err(e),
+                    Err((code, msg)) => {
+                        return Err(BucketError::Other(format!(
+                            "Failed to fetch chunk {} from source bucket: {:?} {}",
+                            chunk_index, code, msg
+                        )))
+                    }
+                }
+            };
+
+            store_chunk(chunk_key(&target.file_id, chunk_index)?, bytes);
+        }
 
-        CHUNKS.with(|c| {
-            c.borrow()
-                .get(&key)
-                .map(|v| v.0.clone())
-                .ok_or(BucketError::ChunkNotFound)
-        })
-    })();
+        Ok(target.allowed_chunks.len() as u32)
+    }
+    .await;
 
     result.into()
 }
 
+/// Moves staged chunks over the chunks of the file of `target`. Only the directory can promote.
+///
+/// Chunk `i` of `staging` replaces chunk `i` of the target file for every `i` in
+/// `target.allowed_chunks`. Target chunks from `chunk_count` on are dropped, and so is whatever is
+/// left of `staging`. Nothing changes unless every listed chunk is staged. Returns the manifest of
+/// the target file below `chunk_count` once promoted, like `get_chunk_manifest`.
 #[update]
-pub fn delete_file(file_id: FileId) -> DeleteFileResult {
-    let result: Result<(), BucketError> = (|| {
-        let owner_bytes = file_id.owner.as_slice();
-        let mut owner_fixed = [0u8; 29];
-        owner_fixed[..owner_bytes.len()].copy_from_slice(owner_bytes);
+pub fn promote_chunks(
+    staging: FileId,
+    target: UploadToken,
+    chunk_count: u32,
+) -> GetChunkManifestResult {
+    let result: Result<Vec<ChunkInfo>, BucketError> = (|| {
+        if ic_cdk::caller() != target.directory_id {
+            return Err(BucketError::Unauthorized);
+        }
+        check_directory(target.directory_id)?;
 
-        let mut fid = [0u8; 16];
-        if file_id.id.len() != 16 {
-            return Err(BucketError::InvalidFileId);
+        if crate::memory::read_config(|c| c.read_only.unwrap_or(false)) {
+            return Err(BucketError::ReadOnly);
         }
-        fid.copy_from_slice(&file_id.id);
 
-        let start_key = ChunkKey {
-            owner: owner_fixed,
-            owner_len: owner_bytes.len() as u8,
-            file_id: fid,
-            chunk_index: 0,
-        };
+        let secret = crate::memory::read_config(|c| c.shared_secret.clone().unwrap_or_default());
+        if !verify_token(&target, &secret) {
+            return Err(BucketError::InvalidSignature);
+        }
+        if target.expires_at < time() {
+            return Err(BucketError::TokenExpired);
+        }
+        if target.bucket_id != id() {
+            return Err(BucketError::WrongBucket);
+        }
 
         CHUNKS.with(|c| {
             let mut chunk_map = c.borrow_mut();
-            let keys_to_delete: Vec<ChunkKey> = chunk_map
-                .range(start_key..)
-                .take_while(|(k, _)| {
-                    k.owner == owner_fixed
-                        && k.owner_len == owner_bytes.len() as u8
-                        && k.file_id == fid
-                })
-                .map(|(k, _)| k.clone())
-                .collect();
-
-            for k in keys_to_delete {
-                chunk_map.remove(&k);
+
+            let mut moves = Vec::with_capacity(target.allowed_chunks.len());
+            for &chunk_index in &target.allowed_chunks {
+                let from = chunk_key(&staging, chunk_index)?;
+                if !chunk_map.contains_key(&from) {
+                    return Err(BucketError::ChunkNotFound);
+                }
+                moves.push((from, chunk_key(&target.file_id, chunk_index)?));
             }
-        });
 
+            for (from, to) in moves {
+                if let Some(value) = chunk_map.remove(&from) {
+                    chunk_map.insert(to.clone(), value);
+                }
+                CHUNK_DIGESTS.with(|d| {
+                    let mut digests = d.borrow_mut();
+                    match digests.remove(&from) {
+                        Some(digest) => digests.insert(to, digest),
+                        None => digests.remove(&to),
+                    };
+                });
+            }
+            Ok(())
+        })?;
+
+        remove_chunks(&target.file_id, chunk_count)?;
+        remove_chunks(&staging, 0)?;
+
+        chunk_manifest(&target.file_id, chunk_count)
+    })();
+
+    result.into()
+}
+
+/// Checks that a token was issued by the directory this bucket serves.
+///
+/// Buckets deployed before they recorded their directory reject every token until an upgrade
+/// sets it.
+fn check_directory(directory_id: Principal) -> Result<(), BucketError> {
+    match crate::memory::read_config(|c| c.directory_id) {
+        Some(expected) if expected == directory_id => Ok(()),
+        _ => Err(BucketError::Unauthorized),
+    }
+}
+
+/// Stores a chunk along with its length and digest.
+fn store_chunk(key: ChunkKey, bytes: Vec<u8>) {
+    CHUNK_DIGESTS.with(|d| d.borrow_mut().insert(key.clone(), digest_of(&bytes)));
+    CHUNKS.with(|c| {
+        c.borrow_mut().insert(key, ChunkValue(bytes));
+    });
+}
+
+/// Returns the length and digest of a stored chunk, computing them for chunks stored before
+/// digests were recorded.
+fn chunk_digest(key: &ChunkKey) -> Option<ChunkDigest> {
+    if let Some(digest) = CHUNK_DIGESTS.with(|d| d.borrow().get(key)) {
+        return Some(digest);
+    }
+    CHUNKS
+        .with(|c| c.borrow().get(key))
+        .map(|value| digest_of(&value.0))
+}
+
+fn digest_of(bytes: &[u8]) -> ChunkDigest {
+    ChunkDigest {
+        size_bytes: bytes.len() as u32,
+        sha256: chunk_sha256(bytes)
+            .try_into()
+            .expect("SHA-256 digests are 32 bytes"),
+    }
+}
+
+/// Builds the storage key of one chunk of `file_id`.
+fn chunk_key(file_id: &FileId, chunk_index: u32) -> Result<ChunkKey, BucketError> {
+    let owner_bytes = file_id.owner.as_slice();
+    let mut owner = [0u8; 29];
+    owner[..owner_bytes.len()].copy_from_slice(owner_bytes);
+
+    let mut fid = [0u8; 16];
+    if file_id.id.len() != 16 {
+        return Err(BucketError::InvalidFileId);
+    }
+    fid.copy_from_slice(&file_id.id);
+
+    Ok(ChunkKey {
+        owner,
+        owner_len: owner_bytes.len() as u8,
+        file_id: fid,
+        chunk_index,
+    })
+}
+
+/// Deletes every chunk of `file_id`.
+///
+/// Allowed for the configured directory, or for any caller holding a delete token the directory
+/// issued for this file on this bucket.
+#[update]
+pub fn delete_file(file_id: FileId, token: Option<DeleteToken>) -> DeleteFileResult {
+    let result: Result<(), BucketError> = (|| {
+        let directory_id = crate::memory::read_config(|c| c.directory_id);
+        if directory_id != Some(ic_cdk::caller()) {
+            let token = token.ok_or(BucketError::Unauthorized)?;
+
+            let secret =
+                crate::memory::read_config(|c| c.shared_secret.clone().unwrap_or_default());
+            if !verify_delete_token(&token, &secret) {
+                return Err(BucketError::InvalidSignature);
+            }
+            if token.expires_at < time() {
+                return Err(BucketError::TokenExpired);
+            }
+            if token.bucket_id != id() {
+                return Err(BucketError::WrongBucket);
+            }
+            if token.file_id != file_id {
+                return Err(BucketError::Unauthorized);
+            }
+            check_directory(token.directory_id)?;
+        }
+
+        remove_chunks(&file_id, 0)?;
+        let file_key = chunk_key(&file_id, 0)?;
+        SEALED_FILES.with(|s| s.borrow_mut().remove(&file_key));
         Ok(())
     })();
 
     result.into()
 }
 
+/// Removes the chunks of `file_id` from `from_index` on.
+fn remove_chunks(file_id: &FileId, from_index: u32) -> Result<(), BucketError> {
+    let start_key = chunk_key(file_id, from_index)?;
+
+    CHUNKS.with(|c| {
+        let mut chunk_map = c.borrow_mut();
+        let keys_to_delete: Vec<ChunkKey> = chunk_map
+            .range(start_key.clone()..)
+            .take_while(|(k, _)| {
+                k.owner == start_key.owner
+                    && k.owner_len == start_key.owner_len
+                    && k.file_id == start_key.file_id
+            })
+            .map(|(k, _)| k.clone())
+            .collect();
+
+        CHUNK_DIGESTS.with(|d| {
+            let mut digests = d.borrow_mut();
+            for k in &keys_to_delete {
+                digests.remove(k);
+            }
+        });
+        UNREPORTED_CHUNKS.with(|u| {
+            let mut unreported = u.borrow_mut();
+            for k in &keys_to_delete {
+                unreported.remove(k);
+            }
+        });
+        for k in keys_to_delete {
+            chunk_map.remove(&k);
+        }
+    });
+
+    Ok(())
+}
+
 #[query]
 pub fn stat() -> String {
     format!("Chunks stored: {}", CHUNKS.with(|c| c.borrow().len()))
@@ -11,6 +11,8 @@ pub struct Config {
     pub read_only: Option<bool>,
     /// Secret used to verify the authenticity of tokens issued by the directory.
     pub shared_secret: Option<Vec<u8>>,
+    /// Directory canister this bucket serves. It can delete chunks without a delete token.
+    pub directory_id: Option<Principal>,
 }
 
 /// Arguments for initializing the bucket canister.
@@ -20,6 +22,8 @@ pub struct InitArgs {
     pub admins: Vec<Principal>,
     /// Initial shared secret used to authenticate directory requests.
     pub shared_secret: Vec<u8>,
+    /// Directory canister this bucket serves.
+    pub directory_id: Principal,
 }
 
 /// Arguments for upgrading the bucket canister.
@@ -29,6 +33,8 @@ pub struct UpgradeArgs {
     pub admins: Option<Vec<Principal>>,
     /// Optional update for the shared secret.
     pub shared_secret: Option<Vec<u8>>,
+    /// Optional update for the directory canister.
+    pub directory_id: Option<Principal>,
 }
 
 #[derive(CandidType, Deserialize)]
@@ -43,6 +49,7 @@ impl From<InitArgs> for Config {
             admins: Some(args.admins),
             read_only: Some(false),
             shared_secret: Some(args.shared_secret),
+            directory_id: Some(args.directory_id),
         }
     }
 }
@@ -8,6 +8,7 @@ pub enum BucketError {
     TokenExpired,
     WrongBucket,
     ChunkNotAllowed(u32),
+    ChunkConflict(u32),
     InvalidFileId,
     ChunkNotFound,
     Unauthorized,
@@ -7,14 +7,15 @@ pub mod results;
 pub mod types;
 
 pub use api::{
-    admin_set_read_only, admin_withdraw, delete_file, get_chunk, get_status, put_chunk, stat,
+    admin_set_read_only, admin_withdraw, copy_chunks, delete_file, get_chunk, get_chunk_manifest,
+    get_status, promote_chunks, put_chunk, seal_chunks, stat,
 };
 use candid::Principal;
 use ic_cdk::export_candid;
 use ic_cdk_macros::{init, post_upgrade};
 pub use ic_papi_api::PaymentType;
 use shared::{
-    types::{DownloadToken, FileId, UploadToken},
+    types::{DeleteToken, DownloadToken, FileId, UploadToken},
     CanisterStatus,
 };
 
@@ -22,8 +23,8 @@ use crate::{
     config::Args,
     memory::{mutate_config, set_config},
     results::{
-        AdminSetReadOnlyResult, AdminWithdrawResult, DeleteFileResult, GetChunkResult,
-        PutChunkResult,
+        AdminSetReadOnlyResult, AdminWithdrawResult, CopyChunksResult, DeleteFileResult,
+        GetChunkManifestResult, GetChunkResult, PutChunkResult,
     },
 };
 
@@ -47,6 +48,9 @@ fn post_upgrade(args: Option<Args>) {
                     if let Some(secret) = upgrade_args.shared_secret {
                         config.shared_secret = Some(secret);
                     }
+                    if let Some(directory_id) = upgrade_args.directory_id {
+                        config.directory_id = Some(directory_id);
+                    }
                 });
             }
             Args::Upgrade(None) => {}
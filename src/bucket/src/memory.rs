@@ -8,7 +8,7 @@ use ic_stable_structures::{
 
 use crate::{
     config::Config,
-    types::{ChunkKey, ChunkValue},
+    types::{ChunkDigest, ChunkKey, ChunkValue},
 };
 
 // Wrapper for Principal to make it Storable
@@ -58,6 +58,24 @@ thread_local! {
     pub static CONFIG: RefCell<ConfigCell> = RefCell::new(
         StableCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))), None).expect("failed to init ConfigCell")
     );
+
+    /// Length and digest of every chunk in `CHUNKS`. Chunks written before digests were recorded
+    /// have no entry.
+    pub static CHUNK_DIGESTS: RefCell<StableBTreeMap<ChunkKey, ChunkDigest, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))))
+    );
+
+    /// Stored chunks the directory has not acknowledged yet. A retried `put_chunk` reports them
+    /// again.
+    pub static UNREPORTED_CHUNKS: RefCell<StableBTreeMap<ChunkKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))))
+    );
+
+    /// Files whose upload the directory committed, keyed by the key of their first chunk. Their
+    /// stored chunks cannot be replaced through `put_chunk`.
+    pub static SEALED_FILES: RefCell<StableBTreeMap<ChunkKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))))
+    );
 }
 
 pub fn read_config<R>(f: impl FnOnce(&Config) -> R) -> R {
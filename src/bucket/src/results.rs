@@ -1,5 +1,6 @@
 use candid::{CandidType, Deserialize};
 use serde::Serialize;
+use shared::types::ChunkInfo;
 
 use crate::errors::BucketError;
 
@@ -31,6 +32,20 @@ impl From<Result<Vec<u8>, BucketError>> for GetChunkResult {
     }
 }
 
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum CopyChunksResult {
+    Ok(u32),
+    Err(BucketError),
+}
+impl From<Result<u32, BucketError>> for CopyChunksResult {
+    fn from(value: Result<u32, BucketError>) -> Self {
+        match value {
+            Ok(v) => CopyChunksResult::Ok(v),
+            Err(e) => CopyChunksResult::Err(e),
+        }
+    }
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub enum DeleteFileResult {
     Ok,
@@ -72,3 +87,17 @@ impl From<Result<(), BucketError>> for AdminSetReadOnlyResult {
         }
     }
 }
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum GetChunkManifestResult {
+    Ok(Vec<ChunkInfo>),
+    Err(BucketError),
+}
+impl From<Result<Vec<ChunkInfo>, BucketError>> for GetChunkManifestResult {
+    fn from(value: Result<Vec<ChunkInfo>, BucketError>) -> Self {
+        match value {
+            Ok(v) => GetChunkManifestResult::Ok(v),
+            Err(e) => GetChunkManifestResult::Err(e),
+        }
+    }
+}
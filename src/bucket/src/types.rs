@@ -43,6 +43,38 @@ impl Storable for ChunkKey {
     }
 }
 
+/// Length and SHA-256 digest of a stored chunk, recorded when the chunk is written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkDigest {
+    pub size_bytes: u32,
+    pub sha256: [u8; 32],
+}
+
+impl Storable for ChunkDigest {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 4 + 32,
+        is_fixed_size: true,
+    };
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(4 + 32);
+        bytes.extend_from_slice(&self.size_bytes.to_be_bytes());
+        bytes.extend_from_slice(&self.sha256);
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        let mut size_bytes = [0u8; 4];
+        size_bytes.copy_from_slice(&bytes[0..4]);
+        let mut sha256 = [0u8; 32];
+        sha256.copy_from_slice(&bytes[4..36]);
+        Self {
+            size_bytes: u32::from_be_bytes(size_bytes),
+            sha256,
+        }
+    }
+}
+
 // Wrapper for blob because Vec<u8> doesn't implement Storable
 pub struct ChunkValue(pub Vec<u8>);
 
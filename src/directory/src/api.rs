@@ -1,13 +1,27 @@
-use candid::Principal;
-use ic_cdk::{api::time, id, println};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet},
+};
+
+use candid::{decode_one, encode_one, Principal};
+use ic_cdk::{api::time, eprintln, id, println};
 use ic_cdk_macros::{query, update};
 use ic_papi_api::PaymentType;
 use shared::{
-    auth::{sign_download_token, sign_token},
-    constants::{GIB, HOUR_NS, MONTH_NS},
+    auth::{hash_link_password, sign_download_token, sign_token},
+    constants::{
+        DEFAULT_PAGE_SIZE, DEFAULT_TRASH_RETENTION_NS, DELETE_RETRY_BASE_NS, GIB, HOUR_NS,
+        MAX_ATTRIBUTES, MAX_ATTRIBUTE_KEY_BYTES, MAX_ATTRIBUTE_VALUE_BYTES, MAX_COPY_BATCH_CHUNKS,
+        MAX_DELETE_RETRY_DELAY_NS, MAX_GROUP_MEMBERS, MAX_MIME_BYTES, MAX_NAME_BYTES,
+        MAX_ORG_MEMBERS, MAX_PAGE_SIZE, MAX_SORTED_FILES, MAX_TAGS, MAX_TAG_BYTES,
+        MAX_TAG_QUERY_TERMS, MAX_UPLOAD_TTL_NS, MONTH_NS,
+    },
+    integrity::content_sha256,
+    merkle,
     types::{
-        BucketAuth, DownloadPlan, DownloadToken, FileId, FileMeta, FileRole, FileStatus, LinkInfo,
-        PricingConfig, UploadSession, UploadToken, UserId,
+        BucketAuth, ChunkInfo, DeleteToken, DownloadPlan, DownloadToken, FileId, FileMeta,
+        FileRole, FileStatus, FolderId, FolderMeta, GroupId, LinkInfo, PricingConfig,
+        UploadSession, UploadToken, UserId, Visibility,
     },
     CanisterStatus,
 };
@@ -15,16 +29,36 @@ use shared::{
 use crate::{
     errors::DirectoryError,
     memory::{
-        read_config, StorablePrincipal, BUCKETS, FILES, FILE_TO_BUCKET, LINKS, UPLOADS, USERS,
+        expired_grants, file_link_tokens, file_versions, folder_entries, folder_entries_after,
+        get_file, get_folder, get_group as get_group_meta, get_org, get_version, group_file_ids,
+        group_file_ids_after, is_group_member, lookup_entry, member_group_ids, member_org_ids,
+        owned_file_ids, owned_file_ids_after, owned_groups, public_file_ids, put_file, put_folder,
+        put_group, put_link, put_org, put_version, read_config, remove_file, remove_folder,
+        remove_group, remove_link, remove_version, shared_file_ids_after, shared_folder_ids,
+        tagged_file_ids, trashed_before, unique_name, StorablePrincipal, BUCKETS,
+        CONTENT_MANIFESTS, FILE_TO_BUCKET, FOLDERS, LINKS, PENDING_DELETES, TRANSFERS, UPLOADS,
+        UPLOAD_REQUESTS, USERS,
     },
     payments::{SignerMethods, PAYMENT_GUARD},
     results::{
-        AbortUploadResult, AdminWithdrawResult, CommitUploadResult, CreateShareLinkResult,
-        DeleteFileResult, GetDownloadPlanResult, GetFileMetaResult, GetUploadTokensResult,
-        ListBucketResult, ProvisionBucketResult, ReportChunkUploadedResult, ResolveShareLinkResult,
-        StartUploadResult, TopUpBalanceResult,
+        AbortUploadResult, AdminWithdrawResult, BrowseShareLinkResult, CommitUploadResult,
+        CreateFolderResult, CreateGroupResult, CreateOrganizationResult, CreateShareLinkResult,
+        DeleteFileResult, DeleteFolderResult, GetDownloadPlanResult, GetFileMetaResult,
+        GetFolderResult, GetGroupResult, GetMissingChunksResult, GetOrganizationResult,
+        GetUploadTokensResult, ListBucketResult, ListFilesPageResult, ListFolderResult,
+        ListPendingDeletesResult, ListShareLinksResult, ListVersionsResult, ProvisionBucketResult,
+        PruneVersionsResult, ReportChunkUploadedResult, ResolvePathResult, ResolveShareLinkResult,
+        StartUploadResult, TopUpBalanceResult, UpdateFileResult, UpdateFolderResult,
+        UpdateGroupResult,
+    },
+    types::{
+        BrowseShareLinkRequest, BucketChunksResult, BucketInfo, BucketManifestResult, BucketResult,
+        ContentManifest, Entry, FileFilter, FileOwnership, FilePage, FileSortKey, FileVersion,
+        FolderListing, Group, ListCursor, ListFilesRequest, OrgMember, OrgRole, Organization,
+        PendingDelete, SearchFilesRequest, ShareLink, ShareLinkOptions, SharedFolderPage,
+        SortValue, TagExpr, TransferOffer, UpdateFileMetaRequest, UpdateGroupRequest,
+        UploadRequest, UploadRequestOptions, UserState,
     },
-    types::{BucketInfo, UserState},
 };
 
 // Local constants removed in favor of shared::constants
@@ -59,20 +93,608 @@ pub fn get_usage(user: Option<Principal>) -> UserState {
     })
 }
 
+/// Lists the caller's accessible files, or only the files inside `parent` when given.
+///
+/// Returns at most `MAX_PAGE_SIZE` files, in the default order of `list_files_page`; use that to
+/// see the rest.
 #[query]
-pub fn list_files() -> Vec<FileMeta> {
+pub fn list_files(parent: Option<FolderId>) -> Vec<FileMeta> {
     let caller = ic_cdk::caller();
-    FILES.with(|f| {
-        f.borrow()
+    let filter = FileFilter::default();
+    let limit = MAX_PAGE_SIZE as usize;
+    match parent {
+        Some(folder_id) => match readable_folder(&folder_id, caller) {
+            Ok(_) => folder_page(caller, &folder_id, &filter, limit, None).files,
+            Err(_) => vec![],
+        },
+        None => accessible_page(caller, &filter, limit, None).files,
+    }
+}
+
+/// Lists the caller's files one page at a time, filtered and sorted.
+///
+/// Pass the returned `next_cursor` with otherwise identical arguments to fetch the next page.
+#[query]
+pub fn list_files_page(request: ListFilesRequest) -> ListFilesPageResult {
+    let result: Result<FilePage, DirectoryError> = (|| {
+        let caller = ic_cdk::caller();
+        let sort_by = request.sort_by.unwrap_or(match request.parent {
+            Some(_) => FileSortKey::Name,
+            None => FileSortKey::Id,
+        });
+        let descending = request.descending.unwrap_or(false);
+        let limit = request
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE) as usize;
+        let filter = request.filter.unwrap_or_default();
+        let after = request
+            .cursor
+            .map(|bytes| decode_cursor(&bytes, &sort_by, descending))
+            .transpose()?;
+
+        if let Some(folder_id) = &request.parent {
+            readable_folder(folder_id, caller)?;
+        }
+
+        // Index orders are read straight off the indexes, from the cursor on
+        match (&request.parent, &sort_by, descending) {
+            (Some(folder_id), FileSortKey::Name, false) => {
+                return Ok(folder_page(caller, folder_id, &filter, limit, after));
+            }
+            (None, FileSortKey::Id, false) => {
+                return Ok(accessible_page(caller, &filter, limit, after));
+            }
+            _ => {}
+        }
+
+        // Other orders need the whole listing in memory
+        let candidates = match request.parent {
+            Some(folder_id) => folder_file_ids(folder_id),
+            None => accessible_file_ids(caller),
+        };
+        if candidates.len() > MAX_SORTED_FILES {
+            return Err(DirectoryError::InvalidRequest(format!(
+                "Listings of more than {} files can only be paged in the default order",
+                MAX_SORTED_FILES
+            )));
+        }
+        let files = candidates
             .iter()
-            .filter(|(fid, meta)| {
-                fid.owner == caller
-                    || meta.readers.contains(&caller)
-                    || meta.writers.contains(&caller)
-            })
-            .map(|(_, meta)| meta)
-            .collect()
-    })
+            .filter_map(get_file)
+            .filter(|meta| readable_match(meta, &filter, caller));
+        Ok(paginate(files, sort_by, descending, limit, after))
+    })();
+
+    result.into()
+}
+
+/// Finds files by tag expression and name prefix, one page at a time, ordered by name.
+///
+/// Searches the files of `request.owner` (the caller by default) and returns only those the
+/// caller can read. Pass the returned `next_cursor` with otherwise identical arguments to fetch
+/// the next page.
+#[query]
+pub fn search_files(request: SearchFilesRequest) -> ListFilesPageResult {
+    let result: Result<FilePage, DirectoryError> = (|| {
+        let caller = ic_cdk::caller();
+        let owner = request.owner.unwrap_or(caller);
+        let limit = request
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE) as usize;
+        let after = request
+            .cursor
+            .map(|bytes| decode_cursor(&bytes, &FileSortKey::Name, false))
+            .transpose()?;
+
+        let candidates: Vec<FileId> = match &request.tags {
+            Some(expr) => {
+                validate_tag_expr(expr, &mut 0)?;
+                eval_tag_expr(owner, expr).into_iter().collect()
+            }
+            None => owned_file_ids(owner),
+        };
+
+        let files = candidates
+            .iter()
+            .filter_map(get_file)
+            .filter(|meta| meta.status != FileStatus::Deleted && can_read(meta, caller))
+            .filter(|meta| {
+                request
+                    .name_prefix
+                    .as_ref()
+                    .is_none_or(|prefix| meta.name.starts_with(prefix.as_str()))
+            });
+        Ok(paginate(files, FileSortKey::Name, false, limit, after))
+    })();
+
+    result.into()
+}
+
+/// Checks that a tag expression is well formed and small enough to evaluate.
+fn validate_tag_expr(expr: &TagExpr, terms: &mut usize) -> Result<(), DirectoryError> {
+    match expr {
+        TagExpr::Tag(tag) => {
+            validate_tag(tag)?;
+            *terms += 1;
+            if *terms > MAX_TAG_QUERY_TERMS {
+                return Err(DirectoryError::InvalidRequest(format!(
+                    "At most {} tags are allowed in a search",
+                    MAX_TAG_QUERY_TERMS
+                )));
+            }
+            Ok(())
+        }
+        TagExpr::And(exprs) | TagExpr::Or(exprs) => {
+            if exprs.is_empty() {
+                return Err(DirectoryError::InvalidRequest(
+                    "Empty tag expression".to_string(),
+                ));
+            }
+            exprs
+                .iter()
+                .try_for_each(|expr| validate_tag_expr(expr, terms))
+        }
+    }
+}
+
+fn eval_tag_expr(owner: UserId, expr: &TagExpr) -> BTreeSet<FileId> {
+    match expr {
+        TagExpr::Tag(tag) => tagged_file_ids(owner, tag).into_iter().collect(),
+        TagExpr::And(exprs) => exprs
+            .iter()
+            .map(|expr| eval_tag_expr(owner, expr))
+            .reduce(|acc, set| acc.intersection(&set).cloned().collect())
+            .unwrap_or_default(),
+        TagExpr::Or(exprs) => exprs
+            .iter()
+            .flat_map(|expr| eval_tag_expr(owner, expr))
+            .collect(),
+    }
+}
+
+/// Sorts `files` and returns the page following `after`.
+fn paginate(
+    files: impl Iterator<Item = FileMeta>,
+    sort_by: FileSortKey,
+    descending: bool,
+    limit: usize,
+    after: Option<ListCursor>,
+) -> FilePage {
+    let mut keyed: Vec<(SortValue, FileMeta)> = files
+        .map(|meta| (sort_value(&meta, &sort_by), meta))
+        .filter(|(value, meta)| match &after {
+            Some(cursor) => {
+                let ord = (value, &meta.file_id).cmp(&(&cursor.value, &cursor.file_id));
+                if descending {
+                    ord.is_lt()
+                } else {
+                    ord.is_gt()
+                }
+            }
+            None => true,
+        })
+        .collect();
+    keyed.sort_by(|(va, a), (vb, b)| {
+        let ord = (va, &a.file_id).cmp(&(vb, &b.file_id));
+        if descending {
+            ord.reverse()
+        } else {
+            ord
+        }
+    });
+    keyed.truncate(limit + 1);
+
+    file_page(
+        keyed.into_iter().map(|(_, meta)| meta).collect(),
+        sort_by,
+        descending,
+        limit,
+    )
+}
+
+/// Cuts `files`, already in page order, down to `limit` and makes the cursor of the next page.
+fn file_page(
+    mut files: Vec<FileMeta>,
+    sort_by: FileSortKey,
+    descending: bool,
+    limit: usize,
+) -> FilePage {
+    let has_more = files.len() > limit;
+    files.truncate(limit);
+    let next_cursor = files.last().filter(|_| has_more).map(|meta| {
+        let value = sort_value(meta, &sort_by);
+        encode_one(ListCursor {
+            sort_by,
+            descending,
+            value,
+            file_id: meta.file_id.clone(),
+        })
+        .expect("failed to encode ListCursor")
+    });
+
+    FilePage { files, next_cursor }
+}
+
+/// Whether a listed file is live, readable by `caller` and matches `filter`.
+fn readable_match(meta: &FileMeta, filter: &FileFilter, caller: Principal) -> bool {
+    meta.status != FileStatus::Deleted
+        && can_read(meta, caller)
+        && matches_filter(meta, filter, caller)
+}
+
+/// An ordered source of the ids of files a caller can access, read a batch at a time.
+enum FileIdSource {
+    Owned(UserId),
+    Shared(UserId),
+    Group(GroupId),
+    /// Files under the folders shared with the caller. Folders have no file id index, so their
+    /// ids are gathered and sorted up front.
+    Sorted(Vec<FileId>),
+}
+
+impl FileIdSource {
+    /// Up to `limit` ids following `after`, in file id order.
+    fn after(&self, after: Option<&FileId>, limit: usize) -> Vec<FileId> {
+        match self {
+            FileIdSource::Owned(owner) => owned_file_ids_after(*owner, after, limit),
+            FileIdSource::Shared(grantee) => shared_file_ids_after(*grantee, after, limit),
+            FileIdSource::Group(group_id) => group_file_ids_after(group_id, after, limit),
+            FileIdSource::Sorted(file_ids) => {
+                let start = after.map_or(0, |after| file_ids.partition_point(|id| id <= after));
+                file_ids[start..].iter().take(limit).cloned().collect()
+            }
+        }
+    }
+}
+
+fn accessible_sources(caller: Principal) -> Vec<FileIdSource> {
+    let mut sources = vec![FileIdSource::Owned(caller), FileIdSource::Shared(caller)];
+    sources.extend(member_org_ids(caller).into_iter().map(FileIdSource::Owned));
+    sources.extend(
+        member_group_ids(caller)
+            .into_iter()
+            .map(FileIdSource::Group),
+    );
+    let mut under_folders: Vec<FileId> = shared_folder_ids(caller)
+        .into_iter()
+        .flat_map(files_under)
+        .collect();
+    under_folders.sort();
+    under_folders.dedup();
+    sources.push(FileIdSource::Sorted(under_folders));
+    sources
+}
+
+fn accessible_file_ids(caller: Principal) -> Vec<FileId> {
+    let mut file_ids: Vec<FileId> = accessible_sources(caller)
+        .iter()
+        .flat_map(|source| source.after(None, usize::MAX))
+        .collect();
+    file_ids.sort();
+    file_ids.dedup();
+    file_ids
+}
+
+/// The page of the caller's accessible files following `after`, in file id order.
+///
+/// Walks the owner and grantee indexes from the cursor in batches, so only the files of the page
+/// and those filtered out on the way are loaded.
+fn accessible_page(
+    caller: Principal,
+    filter: &FileFilter,
+    limit: usize,
+    after: Option<ListCursor>,
+) -> FilePage {
+    let sources = accessible_sources(caller);
+    let mut position = after.map(|cursor| cursor.file_id);
+    let mut files = vec![];
+    while files.len() <= limit {
+        // Ids up to `frontier` are complete: a source that stopped short of it has no more
+        let mut batch = BTreeSet::new();
+        let mut frontier: Option<FileId> = None;
+        for source in &sources {
+            let file_ids = source.after(position.as_ref(), limit + 1);
+            if let Some(last) = file_ids.get(limit) {
+                if frontier.as_ref().is_none_or(|frontier| last < frontier) {
+                    frontier = Some(last.clone());
+                }
+            }
+            batch.extend(file_ids);
+        }
+
+        let exhausted = frontier.is_none();
+        for file_id in batch {
+            if frontier
+                .as_ref()
+                .is_some_and(|frontier| &file_id > frontier)
+            {
+                break;
+            }
+            if let Some(meta) =
+                get_file(&file_id).filter(|meta| readable_match(meta, filter, caller))
+            {
+                files.push(meta);
+                if files.len() > limit {
+                    break;
+                }
+            }
+        }
+        if exhausted {
+            break;
+        }
+        position = frontier;
+    }
+
+    file_page(files, FileSortKey::Id, false, limit)
+}
+
+/// The page of files in `folder_id` following `after`, in name order, read off the folder's
+/// name index.
+fn folder_page(
+    caller: Principal,
+    folder_id: &FolderId,
+    filter: &FileFilter,
+    limit: usize,
+    after: Option<ListCursor>,
+) -> FilePage {
+    let mut position = after.and_then(|cursor| match cursor.value {
+        SortValue::Text(name) => Some(name),
+        SortValue::Number(_) => None,
+    });
+    let mut files = vec![];
+    while files.len() <= limit {
+        let entries = folder_entries_after(
+            folder_id.owner,
+            Some(folder_id.clone()),
+            position.as_deref(),
+            limit + 1,
+        );
+        let exhausted = entries.len() <= limit;
+        for (name, entry) in entries {
+            if let Entry::File(file_id) = entry {
+                if let Some(meta) =
+                    get_file(&file_id).filter(|meta| readable_match(meta, filter, caller))
+                {
+                    files.push(meta);
+                    if files.len() > limit {
+                        break;
+                    }
+                }
+            }
+            position = Some(name);
+        }
+        if exhausted {
+            break;
+        }
+    }
+
+    file_page(files, FileSortKey::Name, false, limit)
+}
+
+/// Files anywhere under `folder_id`, subfolders included.
+fn files_under(folder_id: FolderId) -> Vec<FileId> {
+    let mut file_ids = vec![];
+    let mut pending = vec![folder_id];
+    while let Some(folder_id) = pending.pop() {
+        for (_, entry) in folder_entries(folder_id.owner, Some(folder_id)) {
+            match entry {
+                Entry::File(file_id) => file_ids.push(file_id),
+                Entry::Folder(subfolder_id) => pending.push(subfolder_id),
+            }
+        }
+    }
+    file_ids
+}
+
+fn folder_file_ids(folder_id: FolderId) -> Vec<FileId> {
+    folder_entries(folder_id.owner, Some(folder_id))
+        .into_iter()
+        .filter_map(|(_, entry)| match entry {
+            Entry::File(file_id) => Some(file_id),
+            Entry::Folder(_) => None,
+        })
+        .collect()
+}
+
+fn matches_filter(meta: &FileMeta, filter: &FileFilter, caller: Principal) -> bool {
+    if let Some(prefix) = &filter.mime_prefix {
+        if !meta.mime.starts_with(prefix.as_str()) {
+            return false;
+        }
+    }
+    if let Some(status) = &filter.status {
+        if &meta.status != status {
+            return false;
+        }
+    }
+    if let Some(attributes) = &filter.attributes {
+        let matches_all = attributes.iter().all(|(key, value)| {
+            meta.attributes
+                .as_ref()
+                .and_then(|a| a.get(key))
+                .is_some_and(|v| v == value)
+        });
+        if !matches_all {
+            return false;
+        }
+    }
+    match filter.role {
+        Some(FileOwnership::Owned) => meta.file_id.owner == caller,
+        Some(FileOwnership::Shared) => meta.file_id.owner != caller,
+        None => true,
+    }
+}
+
+fn sort_value(meta: &FileMeta, sort_by: &FileSortKey) -> SortValue {
+    match sort_by {
+        // Ties are broken by file id
+        FileSortKey::Id => SortValue::Number(0),
+        FileSortKey::Name => SortValue::Text(meta.name.clone()),
+        FileSortKey::Size => SortValue::Number(meta.size_bytes),
+        FileSortKey::CreatedAt => SortValue::Number(meta.created_at_ns),
+        FileSortKey::UpdatedAt => SortValue::Number(meta.updated_at_ns),
+    }
+}
+
+fn decode_cursor(
+    bytes: &[u8],
+    sort_by: &FileSortKey,
+    descending: bool,
+) -> Result<ListCursor, DirectoryError> {
+    let cursor: ListCursor = decode_one(bytes)
+        .map_err(|_| DirectoryError::InvalidRequest("Invalid cursor".to_string()))?;
+    if &cursor.sort_by != sort_by || cursor.descending != descending {
+        return Err(DirectoryError::InvalidRequest(
+            "Cursor does not match the requested order".to_string(),
+        ));
+    }
+    Ok(cursor)
+}
+
+fn can_read(meta: &FileMeta, caller: Principal) -> bool {
+    can_write(meta, caller)
+        || granted_role(meta, caller).is_some()
+        || meta
+            .reader_groups
+            .iter()
+            .flatten()
+            .any(|group_id| is_group_member(group_id, caller))
+}
+
+/// Looks up a file that is not in the trash.
+fn live_file(file_id: &FileId) -> Result<FileMeta, DirectoryError> {
+    get_file(file_id)
+        .filter(|meta| meta.status != FileStatus::Deleted)
+        .ok_or(DirectoryError::FileNotFound)
+}
+
+fn can_write(meta: &FileMeta, caller: Principal) -> bool {
+    can_upload_for(meta.file_id.owner, caller)
+        || granted_role(meta, caller) == Some(FileRole::Writer)
+        || meta
+            .writer_groups
+            .iter()
+            .flatten()
+            .any(|group_id| is_group_member(group_id, caller))
+}
+
+/// Whether `caller` can fetch the file: anyone can fetch public and unlisted files, including
+/// anonymous callers.
+fn can_download(meta: &FileMeta, caller: Principal) -> bool {
+    meta.visibility() != Visibility::Private || can_read(meta, caller)
+}
+
+/// The file as `caller` may see it: in full when they can read it, redacted otherwise.
+fn visible_file(meta: FileMeta, caller: Principal) -> FileMeta {
+    if can_read(&meta, caller) {
+        meta
+    } else {
+        meta.redacted()
+    }
+}
+
+/// The folder as `caller` may see it: in full when they can read it, redacted otherwise.
+fn visible_folder(meta: FolderMeta, caller: Principal) -> FolderMeta {
+    if readable_folder(&meta.folder_id, caller).is_ok() {
+        meta
+    } else {
+        meta.redacted()
+    }
+}
+
+/// Role `principal` was granted on the file: its own unexpired grant or, failing that, the grant
+/// of the nearest folder above it that names them.
+fn granted_role(meta: &FileMeta, principal: Principal) -> Option<FileRole> {
+    if grant_active(meta, principal) {
+        if meta.writers.contains(&principal) {
+            return Some(FileRole::Writer);
+        }
+        if meta.readers.contains(&principal) {
+            return Some(FileRole::Reader);
+        }
+    }
+    folder_role(meta.parent.clone(), principal)
+}
+
+/// Role `principal` was granted on `folder` or, failing that, on the nearest folder above it.
+fn folder_role(mut folder: Option<FolderId>, principal: Principal) -> Option<FileRole> {
+    while let Some(folder_id) = folder {
+        let meta = get_folder(&folder_id)?;
+        if meta.writers.iter().flatten().any(|p| *p == principal) {
+            return Some(FileRole::Writer);
+        }
+        if meta.readers.iter().flatten().any(|p| *p == principal) {
+            return Some(FileRole::Reader);
+        }
+        folder = meta.parent;
+    }
+    None
+}
+
+/// Whether `principal`'s reader or writer grant on the file has not expired.
+fn grant_active(meta: &FileMeta, principal: Principal) -> bool {
+    meta.access_expires_at_ns
+        .as_ref()
+        .and_then(|expirations| expirations.get(&principal))
+        .is_none_or(|expires_at_ns| *expires_at_ns > time())
+}
+
+/// Describes the current version of a file as a history entry.
+fn current_version(meta: &FileMeta) -> FileVersion {
+    FileVersion {
+        version: meta.version(),
+        content_id: meta.content_id(),
+        mime: meta.mime.clone(),
+        size_bytes: meta.size_bytes,
+        chunk_size: meta.chunk_size,
+        chunk_count: meta.chunk_count,
+        sha256: meta.sha256.clone(),
+        created_at_ns: meta.updated_at_ns,
+        merkle_root: meta.merkle_root.clone(),
+    }
+}
+
+/// Resolves `version` (`None` is the current version) to the content to serve.
+fn select_version(meta: &FileMeta, version: Option<u32>) -> Result<FileVersion, DirectoryError> {
+    match version {
+        Some(v) if v != meta.version() => {
+            get_version(&meta.file_id, v).ok_or(DirectoryError::VersionNotFound(v))
+        }
+        _ => Ok(current_version(meta)),
+    }
+}
+
+/// Removes the version history of a file, returning the removed versions.
+fn remove_versions(file_id: &FileId) -> Vec<FileVersion> {
+    let versions = file_versions(file_id);
+    for version in &versions {
+        remove_version(file_id, version.version);
+    }
+    versions
+}
+
+fn add_used_bytes(owner: UserId, bytes: u64) {
+    let key = StorablePrincipal(owner);
+    USERS.with(|u| {
+        let mut map = u.borrow_mut();
+        if let Some(mut state) = map.get(&key) {
+            state.used_bytes += bytes;
+            map.insert(key, state);
+        }
+    });
+}
+
+fn release_used_bytes(owner: UserId, bytes: u64) {
+    let key = StorablePrincipal(owner);
+    USERS.with(|u| {
+        let mut map = u.borrow_mut();
+        if let Some(mut state) = map.get(&key) {
+            state.used_bytes = state.used_bytes.saturating_sub(bytes);
+            map.insert(key, state);
+        }
+    });
 }
 
 #[update]
@@ -81,55 +703,59 @@ pub async fn start_upload(
     mime: String,
     size_bytes: u64,
     payment: Option<PaymentType>,
+    parent: Option<FolderId>,
+    owner: Option<UserId>,
+    upload_request: Option<Vec<u8>>,
 ) -> StartUploadResult {
     let result: Result<UploadSession, DirectoryError> = async {
         let caller = ic_cdk::caller();
-        let key = StorablePrincipal(caller);
-
-        // 1. PAPI Payment Deduction
-        let payment_type = payment.unwrap_or(PaymentType::AttachedCycles);
-        PAYMENT_GUARD
-            .deduct(
-                payment_type.clone(),
-                SignerMethods::StartUpload.fee(&payment_type),
-            )
-            .await
-            .map_err(|e| DirectoryError::PaymentFailed(format!("Payment failed: {:?}", e)))?;
-
-        // 2. Check Quota and Expiration
-        let user_state = USERS.with(|u| {
-            u.borrow().get(&key).unwrap_or(UserState {
-                used_bytes: 0,
-                quota_bytes: 10 * 1024 * 1024 * 1024, // 10GiB default
-                expires_at_ns: None,
-                prepaid_balance: 0,
-            })
-        });
-
-        if let Some(expires_at) = user_state.expires_at_ns {
-            if expires_at < time() {
-                return Err(DirectoryError::AccountExpired);
+        let (owner, parent, random_id) = match &upload_request {
+            Some(token) => {
+                if owner.is_some() || parent.is_some() {
+                    return Err(DirectoryError::InvalidRequest(
+                        "The upload request sets the owner and folder".to_string(),
+                    ));
+                }
+                let request = open_upload_request(token)?;
+                check_upload_request(&request, &mime, size_bytes)?;
+
+                // Uploaders through a request are often anonymous and then share a principal, so
+                // only an unguessable upload id keeps their sessions apart
+                let (upload_id,): (Vec<u8>,) = ic_cdk::api::management_canister::main::raw_rand()
+                    .await
+                    .map_err(|(_, msg)| DirectoryError::InvalidRequest(msg))?;
+                (request.owner, request.parent, Some(upload_id))
             }
-        }
+            None => {
+                let owner = owner.unwrap_or(caller);
+                if !can_upload_for(owner, caller) {
+                    return Err(DirectoryError::Unauthorized);
+                }
+                (owner, parent, None)
+            }
+        };
 
-        if user_state.used_bytes + size_bytes > user_state.quota_bytes {
-            return Err(DirectoryError::QuotaExceeded {
-                used: user_state.used_bytes,
-                requested: size_bytes,
-                quota: user_state.quota_bytes,
-            });
+        // 0. Validate the destination before charging anything. Uploads through a request get a
+        //    free name on commit, so they do not reveal which names are taken.
+        validate_name(&name)?;
+        if let Some(folder_id) = &parent {
+            owned_folder(folder_id, owner)?;
         }
+        if upload_request.is_none() {
+            ensure_name_free(owner, parent.clone(), &name)?;
+        }
+
+        // 1. Payment, quota and expiration
+        charge_upload(owner, size_bytes, payment).await?;
 
-        // 3. Create Session
-        let mut id = vec![0u8; 16];
-        let time_bytes = time().to_be_bytes();
-        id[..8].copy_from_slice(&time_bytes);
+        // 2. Create Session
+        let id = new_id();
 
         let file_id = FileId {
-            owner: caller,
+            owner,
             id: id.clone(),
         };
-        let upload_id = id;
+        let upload_id = random_id.unwrap_or(id);
 
         let session = UploadSession {
             upload_id: upload_id.clone(),
@@ -140,7 +766,13 @@ pub async fn start_upload(
             expected_size_bytes: size_bytes,
             expected_chunk_count: (size_bytes.div_ceil(1024 * 1024)) as u32,
             uploaded_chunks: vec![],
-            expires_at_ns: time() + 3600 * 1_000_000_000,
+            expires_at_ns: time() + HOUR_NS,
+            parent,
+            version_of: None,
+            uploader: (owner != caller).then_some(caller),
+            edit_of: None,
+            upload_request,
+            started_at_ns: Some(time()),
         };
 
         UPLOADS.with(|u| u.borrow_mut().insert(upload_id, session.clone()));
@@ -154,79 +786,620 @@ pub async fn start_upload(
     result.into()
 }
 
+/// Starts uploading new content for an existing file. Allowed for the owner and writers.
+///
+/// The upload proceeds like a regular one; committing it makes the content the file's current
+/// version and keeps the previous one in the version history. The new bytes count towards the
+/// owner's quota.
 #[update]
-pub fn report_chunk_uploaded(upload_id: Vec<u8>, chunk_index: u32) -> ReportChunkUploadedResult {
-    let result: Result<(), DirectoryError> = {
-        UPLOADS.with(|u| {
-            let mut map = u.borrow_mut();
-            if let Some(mut session) = map.get(&upload_id) {
-                if !session.uploaded_chunks.contains(&chunk_index) {
-                    session.uploaded_chunks.push(chunk_index);
-                    map.insert(upload_id, session);
-                }
-                Ok(())
-            } else {
-                Err(DirectoryError::UploadSessionNotFound)
-            }
-        })
-    };
-
-    result.into()
-}
-
-#[update]
-pub fn commit_upload(upload_id: Vec<u8>) -> CommitUploadResult {
-    let result: Result<FileMeta, DirectoryError> = (|| {
-        let session = UPLOADS
-            .with(|u| u.borrow().get(&upload_id))
-            .ok_or(DirectoryError::UploadSessionNotFound)?;
-
-        // 1. Verify Completion
-        if session.uploaded_chunks.len() < session.expected_chunk_count as usize {
-            return Err(DirectoryError::UploadIncomplete {
-                uploaded: session.uploaded_chunks.len() as u32,
-                expected: session.expected_chunk_count,
-            });
+pub async fn start_version_upload(
+    file_id: FileId,
+    mime: String,
+    size_bytes: u64,
+    payment: Option<PaymentType>,
+) -> StartUploadResult {
+    let result: Result<UploadSession, DirectoryError> = async {
+        let caller = ic_cdk::caller();
+        let meta = live_file(&file_id)?;
+        if !can_write(&meta, caller) {
+            return Err(DirectoryError::Unauthorized);
         }
 
-        // Success - remove session
-        UPLOADS.with(|u| u.borrow_mut().remove(&upload_id));
-
-        // 2. Update User Usage
-        let caller = session.file_id.owner;
-        let key = StorablePrincipal(caller);
-        USERS.with(|u| {
-            let mut map = u.borrow_mut();
-            if let Some(mut state) = map.get(&key) {
-                state.used_bytes += session.expected_size_bytes;
-                map.insert(key, state);
-            }
-        });
+        charge_upload(file_id.owner, size_bytes, payment).await?;
 
-        // 3. Create File Meta
-        let meta = FileMeta {
-            file_id: session.file_id.clone(),
-            name: session.name,
-            mime: session.mime,
-            size_bytes: session.expected_size_bytes,
-            chunk_size: session.chunk_size,
-            chunk_count: session.expected_chunk_count,
-            created_at_ns: time(),
-            updated_at_ns: time(),
-            status: FileStatus::Ready,
-            sha256: None,
-            readers: vec![],
-            writers: vec![],
+        let id = new_id();
+        let session = UploadSession {
+            upload_id: id.clone(),
+            file_id: FileId {
+                owner: file_id.owner,
+                id,
+            },
+            name: meta.name,
+            mime,
+            chunk_size: 1024 * 1024,
+            expected_size_bytes: size_bytes,
+            expected_chunk_count: (size_bytes.div_ceil(1024 * 1024)) as u32,
+            uploaded_chunks: vec![],
+            expires_at_ns: time() + HOUR_NS,
+            parent: meta.parent,
+            version_of: Some(file_id),
+            uploader: (caller != meta.file_id.owner).then_some(caller),
+            edit_of: None,
+            upload_request: None,
+            started_at_ns: Some(time()),
         };
 
-        FILES.with(|f| f.borrow_mut().insert(session.file_id, meta.clone()));
+        UPLOADS.with(|u| {
+            u.borrow_mut()
+                .insert(session.upload_id.clone(), session.clone())
+        });
 
-        Ok(meta)
-    })();
+        Ok(session)
+    }
+    .await;
 
     result.into()
 }
 
+/// Starts editing a file in place. Allowed for the owner and writers.
+///
+/// `size_bytes` is the size of the file once edited. Only the chunks that change need uploading,
+/// plus any appended chunks and the chunk holding the new end of the file. Readers keep getting
+/// the current content until `commit_edit` swaps the uploaded chunks in. Growth counts towards
+/// the owner's quota.
+#[update]
+pub async fn start_edit(
+    file_id: FileId,
+    size_bytes: u64,
+    payment: Option<PaymentType>,
+) -> StartUploadResult {
+    let result: Result<UploadSession, DirectoryError> = async {
+        let caller = ic_cdk::caller();
+        let meta = live_file(&file_id)?;
+        if !can_write(&meta, caller) {
+            return Err(DirectoryError::Unauthorized);
+        }
+        if meta.status != FileStatus::Ready {
+            return Err(DirectoryError::InvalidRequest(
+                "File is not ready".to_string(),
+            ));
+        }
+        ensure_content_unshared(&meta)?;
+
+        charge_upload(
+            file_id.owner,
+            size_bytes.saturating_sub(meta.size_bytes),
+            payment,
+        )
+        .await?;
+
+        let id = new_id();
+        let session = UploadSession {
+            upload_id: id.clone(),
+            file_id: FileId {
+                owner: file_id.owner,
+                id,
+            },
+            name: meta.name,
+            mime: meta.mime,
+            chunk_size: meta.chunk_size,
+            expected_size_bytes: size_bytes,
+            expected_chunk_count: size_bytes.div_ceil(meta.chunk_size as u64) as u32,
+            uploaded_chunks: vec![],
+            expires_at_ns: time() + HOUR_NS,
+            parent: meta.parent,
+            version_of: None,
+            uploader: (caller != meta.file_id.owner).then_some(caller),
+            edit_of: Some(file_id),
+            upload_request: None,
+            started_at_ns: Some(time()),
+        };
+
+        UPLOADS.with(|u| {
+            u.borrow_mut()
+                .insert(session.upload_id.clone(), session.clone())
+        });
+
+        Ok(session)
+    }
+    .await;
+
+    result.into()
+}
+
+/// Fails if a past version shares the file's current content, which an edit would alter.
+fn ensure_content_unshared(meta: &FileMeta) -> Result<(), DirectoryError> {
+    let content_id = meta.content_id();
+    if let Some(version) = file_versions(&meta.file_id)
+        .iter()
+        .find(|v| v.content_id == content_id)
+    {
+        return Err(DirectoryError::InvalidRequest(format!(
+            "Version {} shares the current content; upload a new version instead",
+            version.version
+        )));
+    }
+    Ok(())
+}
+
+/// Chunks an edit must upload for a file to go from `old_size` to `new_size` bytes: the appended
+/// chunks, and the chunks whose length changes at the old and new end of the file.
+fn required_edit_chunks(old_size: u64, new_size: u64, chunk_size: u32) -> Vec<u32> {
+    let chunk_size = chunk_size as u64;
+    let old_count = old_size.div_ceil(chunk_size);
+    let new_count = new_size.div_ceil(chunk_size);
+
+    let mut required: BTreeSet<u32> = (old_count..new_count).map(|i| i as u32).collect();
+    if new_size != old_size && new_size % chunk_size != 0 {
+        required.insert((new_count - 1) as u32);
+    }
+    if new_size > old_size && old_size % chunk_size != 0 {
+        required.insert((old_count - 1) as u32);
+    }
+    required.into_iter().collect()
+}
+
+/// Charges the upload fee and checks that `owner` can store `size_bytes` more.
+async fn charge_upload(
+    owner: UserId,
+    size_bytes: u64,
+    payment: Option<PaymentType>,
+) -> Result<(), DirectoryError> {
+    // 1. PAPI Payment Deduction
+    let payment_type = payment.unwrap_or(PaymentType::AttachedCycles);
+    PAYMENT_GUARD
+        .deduct(
+            payment_type.clone(),
+            SignerMethods::StartUpload.fee(&payment_type),
+        )
+        .await
+        .map_err(|e| DirectoryError::PaymentFailed(format!("Payment failed: {:?}", e)))?;
+
+    // 2. Check Quota and Expiration
+    check_account(owner, size_bytes)
+}
+
+/// Checks that `owner`'s account is active and has room for `size_bytes` more.
+fn check_account(owner: UserId, size_bytes: u64) -> Result<(), DirectoryError> {
+    let user_state = USERS.with(|u| {
+        u.borrow()
+            .get(&StorablePrincipal(owner))
+            .unwrap_or(UserState {
+                used_bytes: 0,
+                quota_bytes: 10 * 1024 * 1024 * 1024, // 10GiB default
+                expires_at_ns: None,
+                prepaid_balance: 0,
+            })
+    });
+
+    if let Some(expires_at) = user_state.expires_at_ns {
+        if expires_at < time() {
+            return Err(DirectoryError::AccountExpired);
+        }
+    }
+
+    check_quota(&user_state, size_bytes)
+}
+
+fn check_quota(user_state: &UserState, size_bytes: u64) -> Result<(), DirectoryError> {
+    if user_state.used_bytes + size_bytes > user_state.quota_bytes {
+        return Err(DirectoryError::QuotaExceeded {
+            used: user_state.used_bytes,
+            requested: size_bytes,
+            quota: user_state.quota_bytes,
+        });
+    }
+    Ok(())
+}
+
+/// Records that a chunk of an upload is stored. Only the bucket assigned to the upload can report.
+#[update]
+pub fn report_chunk_uploaded(upload_id: Vec<u8>, chunk_index: u32) -> ReportChunkUploadedResult {
+    let result: Result<(), DirectoryError> = (|| {
+        let caller = ic_cdk::caller();
+        if !BUCKETS.with(|b| b.borrow().contains_key(&StorablePrincipal(caller))) {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        let mut session = UPLOADS
+            .with(|u| u.borrow().get(&upload_id))
+            .ok_or(DirectoryError::UploadSessionNotFound)?;
+        let assigned = FILE_TO_BUCKET.with(|ftb| ftb.borrow().get(&session.file_id));
+        if assigned.map(|b| b.0) != Some(caller) {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        if !session.uploaded_chunks.contains(&chunk_index) {
+            session.uploaded_chunks.push(chunk_index);
+            UPLOADS.with(|u| u.borrow_mut().insert(upload_id, session));
+        }
+        Ok(())
+    })();
+
+    result.into()
+}
+
+/// Commits an upload: the file, or the new version of an existing one, becomes `Ready`.
+///
+/// The stored chunks are checked against the bucket's manifest first: every chunk must be there,
+/// all but the last must be full, they must add up to the announced size, and their hash must
+/// match `sha256` when the client declares one. The computed hash is recorded as the file's
+/// `sha256`. Once checked, the chunks are sealed in the bucket so that they can no longer be
+/// replaced. Only the uploader can commit.
+#[update]
+pub async fn commit_upload(upload_id: Vec<u8>, sha256: Option<Vec<u8>>) -> CommitUploadResult {
+    let result: Result<FileMeta, DirectoryError> = async {
+        let session = UPLOADS
+            .with(|u| u.borrow().get(&upload_id))
+            .ok_or(DirectoryError::UploadSessionNotFound)?;
+        if session.uploader() != ic_cdk::caller() {
+            return Err(DirectoryError::Unauthorized);
+        }
+        if session.edit_of.is_some() {
+            return Err(DirectoryError::InvalidRequest(
+                "Edit sessions are committed with commit_edit".to_string(),
+            ));
+        }
+
+        // 1. Verify Completion
+        if session.uploaded_chunks.len() < session.expected_chunk_count as usize {
+            return Err(DirectoryError::UploadIncomplete {
+                uploaded: session.uploaded_chunks.len() as u32,
+                expected: session.expected_chunk_count,
+            });
+        }
+        let chunks = verify_content(&session, sha256).await?;
+
+        // Seal the chunks so they can no longer be replaced, and make sure they were not since
+        // they were checked
+        if session.expected_chunk_count > 0 {
+            let bucket_id = content_bucket(&session.file_id)?;
+            let sealed = fetch_manifest(
+                &session.file_id,
+                bucket_id,
+                session.expected_chunk_count,
+                true,
+            )
+            .await?;
+            if sealed != chunks {
+                return Err(DirectoryError::IntegrityCheckFailed(
+                    "Chunks changed during the commit".to_string(),
+                ));
+            }
+        }
+
+        // The session may have been committed or aborted while the bucket answered
+        let session = UPLOADS
+            .with(|u| u.borrow().get(&upload_id))
+            .ok_or(DirectoryError::UploadSessionNotFound)?;
+
+        // 2. Build the new file, or the new version of an existing one
+        let (sha256, merkle_root) = record_manifest(&session.file_id, chunks);
+        let meta = match &session.version_of {
+            Some(file_id) => {
+                let mut meta = live_file(file_id)?;
+                put_version(file_id, current_version(&meta));
+
+                meta.version = Some(meta.version() + 1);
+                meta.content_id = Some(session.file_id.clone());
+                meta.mime = session.mime;
+                meta.size_bytes = session.expected_size_bytes;
+                meta.chunk_size = session.chunk_size;
+                meta.chunk_count = session.expected_chunk_count;
+                meta.sha256 = Some(sha256);
+                meta.merkle_root = Some(merkle_root);
+                meta.updated_at_ns = time();
+                meta
+            }
+            None => {
+                // The destination may have changed since the session started
+                if let Some(folder_id) = &session.parent {
+                    if get_folder(folder_id).is_none() {
+                        return Err(DirectoryError::FolderNotFound);
+                    }
+                }
+                let owner = session.file_id.owner;
+                let name = match &session.upload_request {
+                    Some(token) => {
+                        record_request_upload(token, &session)?;
+                        unique_name(owner, session.parent.clone(), &session.name)
+                    }
+                    None => {
+                        ensure_name_free(owner, session.parent.clone(), &session.name)?;
+                        session.name
+                    }
+                };
+
+                FileMeta {
+                    sha256: Some(sha256),
+                    merkle_root: Some(merkle_root),
+                    ..FileMeta::new_ready(
+                        session.file_id.clone(),
+                        name,
+                        session.mime,
+                        session.parent,
+                        session.expected_size_bytes,
+                        session.chunk_size,
+                        session.expected_chunk_count,
+                    )
+                }
+            }
+        };
+
+        // Success - remove session
+        UPLOADS.with(|u| u.borrow_mut().remove(&upload_id));
+
+        // 3. Update User Usage
+        add_used_bytes(session.file_id.owner, session.expected_size_bytes);
+        if let Some(bucket) = FILE_TO_BUCKET.with(|ftb| ftb.borrow().get(&session.file_id)) {
+            add_bucket_bytes(bucket.0, session.expected_size_bytes);
+        }
+
+        put_file(meta.clone());
+
+        Ok(meta)
+    }
+    .await;
+
+    result.into()
+}
+
+/// Checks the chunks stored for an upload session against what it announced, and returns them.
+async fn verify_content(
+    session: &UploadSession,
+    expected_sha256: Option<Vec<u8>>,
+) -> Result<Vec<ChunkInfo>, DirectoryError> {
+    let chunks = if session.expected_chunk_count == 0 {
+        vec![]
+    } else {
+        let bucket_id = content_bucket(&session.file_id)?;
+        fetch_manifest(
+            &session.file_id,
+            bucket_id,
+            session.expected_chunk_count,
+            false,
+        )
+        .await?
+    };
+
+    // The manifest lists the stored chunks below the expected count, in order
+    if chunks.len() < session.expected_chunk_count as usize {
+        return Err(DirectoryError::UploadIncomplete {
+            uploaded: chunks.len() as u32,
+            expected: session.expected_chunk_count,
+        });
+    }
+    // Chunks sit at fixed offsets: all but the last one are full, and the last one is not larger
+    let last = chunks.len().saturating_sub(1);
+    if let Some((_, chunk)) = chunks.iter().enumerate().find(|(i, c)| {
+        c.size_bytes > session.chunk_size || (*i < last && c.size_bytes < session.chunk_size)
+    }) {
+        return Err(DirectoryError::IntegrityCheckFailed(format!(
+            "Chunk {} holds {} bytes, expected {}",
+            chunk.chunk_index, chunk.size_bytes, session.chunk_size
+        )));
+    }
+    let stored_bytes: u64 = chunks.iter().map(|c| c.size_bytes as u64).sum();
+    if stored_bytes != session.expected_size_bytes {
+        return Err(DirectoryError::IntegrityCheckFailed(format!(
+            "Stored {} bytes, expected {}",
+            stored_bytes, session.expected_size_bytes
+        )));
+    }
+
+    let sha256 = content_sha256(chunks.iter().map(|c| c.sha256.as_slice()));
+    if expected_sha256.is_some_and(|expected| expected != sha256) {
+        return Err(DirectoryError::IntegrityCheckFailed(
+            "File hash does not match".to_string(),
+        ));
+    }
+    Ok(chunks)
+}
+
+/// Asks `bucket_id` for the length and digest of the chunks of `content_id` below `chunk_count`.
+/// With `seal`, the bucket also stops accepting replacements for the stored chunks.
+async fn fetch_manifest(
+    content_id: &FileId,
+    bucket_id: Principal,
+    chunk_count: u32,
+    seal: bool,
+) -> Result<Vec<ChunkInfo>, DirectoryError> {
+    let mut token = DownloadToken {
+        file_id: content_id.clone(),
+        bucket_id,
+        directory_id: id(),
+        expires_at: time() + HOUR_NS,
+        sig: vec![],
+    };
+    let secret = read_config(|c| c.shared_secret.clone().unwrap_or_default());
+    sign_download_token(&mut token, &secret);
+
+    let method = if seal {
+        "seal_chunks"
+    } else {
+        "get_chunk_manifest"
+    };
+    let res: Result<(BucketManifestResult,), _> =
+        ic_cdk::call(bucket_id, method, (token, chunk_count)).await;
+    match res {
+        Ok((BucketManifestResult::Ok(chunks),)) => Ok(chunks),
+        Ok((BucketManifestResult::Err(_),)) => Err(DirectoryError::BucketCallFailed(
+            "Bucket refused to list the chunks".to_string(),
+        )),
+        Err((code, msg)) => Err(DirectoryError::BucketCallFailed(format!(
+            "Call error: {:?} {}",
+            code, msg
+        ))),
+    }
+}
+
+/// Records the chunks of `content_id` and returns the content's hash and Merkle root.
+fn record_manifest(content_id: &FileId, chunks: Vec<ChunkInfo>) -> (Vec<u8>, Vec<u8>) {
+    let sha256 = content_sha256(chunks.iter().map(|c| c.sha256.as_slice()));
+    let merkle_root = merkle::root(&chunks);
+    CONTENT_MANIFESTS.with(|m| {
+        m.borrow_mut()
+            .insert(content_id.clone(), ContentManifest { chunks })
+    });
+    (sha256, merkle_root)
+}
+
+/// Commits an edit session: the uploaded chunks replace the file's chunks, and chunks past the new
+/// end of the file are dropped. Only the uploader can commit.
+#[update]
+pub async fn commit_edit(upload_id: Vec<u8>) -> CommitUploadResult {
+    let result: Result<FileMeta, DirectoryError> = async {
+        let session = UPLOADS
+            .with(|u| u.borrow().get(&upload_id))
+            .ok_or(DirectoryError::UploadSessionNotFound)?;
+        let Some(file_id) = session.edit_of.clone() else {
+            return Err(DirectoryError::InvalidRequest(
+                "Not an edit session".to_string(),
+            ));
+        };
+        if session.uploader() != ic_cdk::caller() {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        // 1. Verify Completion
+        let meta = live_file(&file_id)?;
+        ensure_content_unshared(&meta)?;
+        let required = required_edit_chunks(
+            meta.size_bytes,
+            session.expected_size_bytes,
+            session.chunk_size,
+        );
+        let uploaded = required
+            .iter()
+            .filter(|i| session.uploaded_chunks.contains(*i))
+            .count();
+        if uploaded < required.len() {
+            return Err(DirectoryError::UploadIncomplete {
+                uploaded: uploaded as u32,
+                expected: required.len() as u32,
+            });
+        }
+
+        // 2. Swap the chunks in. The session is taken so that it cannot be committed twice.
+        let content_id = meta.content_id();
+        let bucket_id = content_bucket(&content_id)?;
+        UPLOADS.with(|u| u.borrow_mut().remove(&upload_id));
+        EDITS_IN_FLIGHT.with(|e| e.borrow_mut().insert(file_id.clone()));
+
+        let mut token = UploadToken {
+            upload_id: session.upload_id.clone(),
+            file_id: content_id.clone(),
+            bucket_id,
+            directory_id: id(),
+            expires_at: time() + HOUR_NS,
+            allowed_chunks: session
+                .uploaded_chunks
+                .iter()
+                .copied()
+                .filter(|i| *i < session.expected_chunk_count)
+                .collect(),
+            sig: vec![],
+        };
+        let secret = read_config(|c| c.shared_secret.clone().unwrap_or_default());
+        sign_token(&mut token, &secret);
+
+        let res: Result<(BucketManifestResult,), _> = ic_cdk::call(
+            bucket_id,
+            "promote_chunks",
+            (session.file_id.clone(), token, session.expected_chunk_count),
+        )
+        .await;
+        let promoted = match res {
+            Ok((BucketManifestResult::Ok(chunks),)) => Ok(chunks),
+            Ok((BucketManifestResult::Err(_),)) => {
+                Err("Bucket refused to promote the chunks".to_string())
+            }
+            // The bucket may have promoted the chunks before the reply got lost
+            Err((code, msg)) => promoted_manifest(&session, &content_id, bucket_id)
+                .await
+                .ok_or(format!("Call error: {:?} {}", code, msg)),
+        };
+        EDITS_IN_FLIGHT.with(|e| e.borrow_mut().remove(&file_id));
+        let chunks = match promoted {
+            Ok(chunks) => chunks,
+            Err(reason) => {
+                UPLOADS.with(|u| u.borrow_mut().insert(upload_id, session));
+                return Err(DirectoryError::BucketCallFailed(reason));
+            }
+        };
+        FILE_TO_BUCKET.with(|ftb| ftb.borrow_mut().remove(&session.file_id));
+
+        // 3. Record the new content right away, before anything can read the file. Without a full
+        //    manifest its hashes are unknown.
+        let hashes = if chunks.len() == session.expected_chunk_count as usize {
+            Some(record_manifest(&content_id, chunks))
+        } else {
+            CONTENT_MANIFESTS.with(|m| m.borrow_mut().remove(&content_id));
+            None
+        };
+
+        // 4. Update the file and the owner's usage, unless it moved on while the bucket worked
+        let mut meta = live_file(&file_id)?;
+        ensure_content_unshared(&meta)?;
+        if meta.content_id() != content_id {
+            return Err(DirectoryError::InvalidRequest(
+                "The file got new content during the commit".to_string(),
+            ));
+        }
+        let old_size = meta.size_bytes;
+        meta.size_bytes = session.expected_size_bytes;
+        meta.chunk_count = session.expected_chunk_count;
+        meta.sha256 = hashes.as_ref().map(|(sha256, _)| sha256.clone());
+        meta.merkle_root = hashes.map(|(_, merkle_root)| merkle_root);
+        meta.updated_at_ns = time();
+        put_file(meta.clone());
+
+        if meta.size_bytes > old_size {
+            add_used_bytes(file_id.owner, meta.size_bytes - old_size);
+            add_bucket_bytes(bucket_id, meta.size_bytes - old_size);
+        } else {
+            release_used_bytes(file_id.owner, old_size - meta.size_bytes);
+            release_bucket_bytes(bucket_id, old_size - meta.size_bytes);
+        }
+
+        Ok(meta)
+    }
+    .await;
+
+    result.into()
+}
+
+/// Finds out whether an edit session's chunks were promoted when the bucket's reply was lost, and
+/// returns the promoted manifest if they were. Staged chunks are gone once promoted. An edit that
+/// staged nothing is left to be committed again, which is harmless.
+async fn promoted_manifest(
+    session: &UploadSession,
+    content_id: &FileId,
+    bucket_id: Principal,
+) -> Option<Vec<ChunkInfo>> {
+    if !session
+        .uploaded_chunks
+        .iter()
+        .any(|i| *i < session.expected_chunk_count)
+    {
+        return None;
+    }
+    let staged = fetch_manifest(
+        &session.file_id,
+        bucket_id,
+        session.expected_chunk_count,
+        false,
+    )
+    .await
+    .ok()?;
+    if !staged.is_empty() {
+        return None;
+    }
+    fetch_manifest(content_id, bucket_id, session.expected_chunk_count, false)
+        .await
+        .ok()
+}
+
 #[update]
 pub fn abort_upload(upload_id: Vec<u8>) -> AbortUploadResult {
     let result: Result<(), DirectoryError> = (|| {
@@ -234,7 +1407,7 @@ pub fn abort_upload(upload_id: Vec<u8>) -> AbortUploadResult {
             .with(|u| u.borrow().get(&upload_id))
             .ok_or(DirectoryError::UploadSessionNotFound)?;
 
-        if session.file_id.owner != ic_cdk::caller() {
+        if session.uploader() != ic_cdk::caller() {
             return Err(DirectoryError::Unauthorized);
         }
 
@@ -245,233 +1418,2105 @@ pub fn abort_upload(upload_id: Vec<u8>) -> AbortUploadResult {
     result.into()
 }
 
+/// Returns a file's metadata. Callers that see the file only because it is public or unlisted get
+/// it without its grants, attributes and tags.
 #[query]
 pub fn get_file_meta(file_id: FileId) -> GetFileMetaResult {
     let result: Result<FileMeta, DirectoryError> = (|| {
-        let meta = FILES.with(|f| f.borrow().get(&file_id).ok_or(DirectoryError::FileNotFound))?;
+        let meta = get_file(&file_id).ok_or(DirectoryError::FileNotFound)?;
+
+        if !can_download(&meta, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+        // Only the owner sees files in their trash
+        if meta.status == FileStatus::Deleted && !is_owner(meta.file_id.owner, ic_cdk::caller()) {
+            return Err(DirectoryError::FileNotFound);
+        }
+
+        Ok(visible_file(meta, ic_cdk::caller()))
+    })();
+
+    result.into()
+}
+
+/// Returns the download plan of the current version, or of `version` when given.
+#[query]
+pub fn get_download_plan(file_id: FileId, version: Option<u32>) -> GetDownloadPlanResult {
+    let result: Result<DownloadPlan, DirectoryError> = (|| {
+        let meta = live_file(&file_id)?;
+
+        if !can_download(&meta, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        generate_download_plan(file_id, version)
+    })();
+
+    result.into()
+}
+
+fn generate_download_plan(
+    file_id: FileId,
+    version: Option<u32>,
+) -> Result<DownloadPlan, DirectoryError> {
+    let meta = live_file(&file_id)?;
+    if meta.status == FileStatus::Pending {
+        return Err(DirectoryError::InvalidRequest(
+            "File is not ready".to_string(),
+        ));
+    }
+    let content = select_version(&meta, version)?;
+
+    let bucket_id = content_bucket(&content.content_id)?;
+
+    let chunk_count = content.chunk_count;
+    let chunk_size = content.chunk_size;
+    let mut locations = Vec::with_capacity(chunk_count as usize);
+
+    // Chunk hashes are only known for content committed with a full manifest
+    let chunks = CONTENT_MANIFESTS
+        .with(|m| m.borrow().get(&content.content_id))
+        .map(|manifest| manifest.chunks)
+        .filter(|chunks| chunks.len() == chunk_count as usize);
+    for i in 0..chunk_count {
+        let chunk = chunks.as_ref().map(|chunks| &chunks[i as usize]);
+        locations.push(shared::types::ChunkLocation {
+            chunk_index: i,
+            bucket: bucket_id,
+            size_bytes: chunk.map(|c| c.size_bytes),
+            sha256: chunk.map(|c| c.sha256.clone()),
+        });
+    }
+    let merkle_root = chunks.and(content.merkle_root);
+
+    let expires_at = ic_cdk::api::time() + HOUR_NS;
+    let mut auth = Vec::with_capacity(1);
+
+    let mut token = DownloadToken {
+        file_id: content.content_id,
+        bucket_id,
+        directory_id: ic_cdk::id(),
+        expires_at,
+        sig: vec![],
+    };
+    let secret = read_config(|c| c.shared_secret.clone().unwrap_or_default());
+    sign_download_token(&mut token, &secret);
+    auth.push(BucketAuth { bucket_id, token });
+
+    Ok(DownloadPlan {
+        chunk_count,
+        chunk_size,
+        locations,
+        auth,
+        merkle_root,
+    })
+}
+
+/// Creates a link to the file's current version, or pinned to `version` when given.
+///
+/// `options` can label the link, cap how many times it can be resolved, and protect it with a
+/// password.
+#[update]
+pub async fn create_share_link(
+    file_id: FileId,
+    ttl_ns: u64,
+    version: Option<u32>,
+    options: Option<ShareLinkOptions>,
+) -> CreateShareLinkResult {
+    let caller = ic_cdk::caller();
+    let res: Result<Vec<u8>, DirectoryError> = async {
+        let meta = live_file(&file_id)?;
+        if !can_write(&meta, caller) {
+            return Err(DirectoryError::Unauthorized);
+        }
+        select_version(&meta, version)?;
+
+        insert_link(Some(file_id), None, ttl_ns, version, options).await
+    }
+    .await;
+    res.into()
+}
+
+/// Creates a link to a folder. Holders can browse the folder and its subfolders with
+/// `browse_share_link` and download any file under it, but no file outside it. Allowed for the
+/// owner and writers of the folder.
+#[update]
+pub async fn create_folder_share_link(
+    folder_id: FolderId,
+    ttl_ns: u64,
+    options: Option<ShareLinkOptions>,
+) -> CreateShareLinkResult {
+    let res: Result<Vec<u8>, DirectoryError> = async {
+        let meta = get_folder(&folder_id).ok_or(DirectoryError::FolderNotFound)?;
+        if !can_write_folder(&meta, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        insert_link(None, Some(folder_id), ttl_ns, None, options).await
+    }
+    .await;
+    res.into()
+}
+
+async fn insert_link(
+    file_id: Option<FileId>,
+    folder_id: Option<FolderId>,
+    ttl_ns: u64,
+    version: Option<u32>,
+    options: Option<ShareLinkOptions>,
+) -> Result<Vec<u8>, DirectoryError> {
+    let options = options.unwrap_or_default();
+    if options.max_uses == Some(0) {
+        return Err(DirectoryError::InvalidRequest(
+            "max_uses must be at least 1".to_string(),
+        ));
+    }
+    if options
+        .label
+        .as_ref()
+        .is_some_and(|l| l.len() > MAX_NAME_BYTES)
+    {
+        return Err(DirectoryError::InvalidRequest(
+            "Label is too long".to_string(),
+        ));
+    }
+
+    let (token,): (Vec<u8>,) = ic_cdk::api::management_canister::main::raw_rand()
+        .await
+        .map_err(|(_, msg)| DirectoryError::InvalidRequest(msg))?;
+
+    let now = time();
+    let password_sha256 = options
+        .password
+        .map(|password| hash_link_password(&token, &password));
+    put_link(
+        token.clone(),
+        LinkInfo {
+            file_id,
+            expires_at: now + ttl_ns,
+            version,
+            label: options.label,
+            created_by: Some(ic_cdk::caller()),
+            created_at_ns: Some(now),
+            max_uses: options.max_uses,
+            password_sha256,
+            use_count: None,
+            last_used_at_ns: None,
+            folder_id,
+        },
+    );
+    Ok(token)
+}
+
+#[update]
+pub fn revoke_share_link(token: Vec<u8>) -> Result<(), DirectoryError> {
+    let info = LINKS
+        .with(|l| l.borrow().get(&token))
+        .ok_or(DirectoryError::LinkNotFound)?;
+    if !can_manage_link(&info, ic_cdk::caller())? {
+        return Err(DirectoryError::Unauthorized);
+    }
+    remove_link(&token);
+    Ok(())
+}
+
+/// Whether `caller` can list and revoke the link: writers of the linked file or folder can.
+fn can_manage_link(info: &LinkInfo, caller: Principal) -> Result<bool, DirectoryError> {
+    match (&info.file_id, &info.folder_id) {
+        (Some(file_id), _) => {
+            let meta = get_file(file_id).ok_or(DirectoryError::FileNotFound)?;
+            Ok(can_write(&meta, caller))
+        }
+        (None, Some(folder_id)) => {
+            let meta = get_folder(folder_id).ok_or(DirectoryError::FolderNotFound)?;
+            Ok(can_write_folder(&meta, caller))
+        }
+        (None, None) => Err(DirectoryError::LinkNotFound),
+    }
+}
+
+/// Lists the share links of a file. Allowed for the owner and writers.
+#[query]
+pub fn list_share_links(file_id: FileId) -> ListShareLinksResult {
+    let res: Result<Vec<ShareLink>, DirectoryError> = (|| {
+        let meta = live_file(&file_id)?;
+        if !can_write(&meta, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        Ok(file_link_tokens(&file_id)
+            .into_iter()
+            .filter_map(|token| {
+                let info = LINKS.with(|l| l.borrow().get(&token))?;
+                Some(ShareLink::new(token, info))
+            })
+            .collect())
+    })();
+    res.into()
+}
+
+/// Lists the links to a folder. Allowed for the owner and writers of the folder.
+#[query]
+pub fn list_folder_share_links(folder_id: FolderId) -> ListShareLinksResult {
+    let res: Result<Vec<ShareLink>, DirectoryError> = (|| {
+        let meta = get_folder(&folder_id).ok_or(DirectoryError::FolderNotFound)?;
+        if !can_write_folder(&meta, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        Ok(share_links(|info| {
+            info.folder_id.as_ref() == Some(&folder_id)
+        }))
+    })();
+    res.into()
+}
+
+fn share_links(filter: impl Fn(&LinkInfo) -> bool) -> Vec<ShareLink> {
+    LINKS.with(|l| {
+        l.borrow()
+            .iter()
+            .filter(|(_, info)| filter(info))
+            .map(|(token, info)| ShareLink::new(token, info))
+            .collect()
+    })
+}
+
+fn remove_links(filter: impl Fn(&LinkInfo) -> bool) {
+    let tokens: Vec<Vec<u8>> = LINKS.with(|l| {
+        l.borrow()
+            .iter()
+            .filter(|(_, info)| filter(info))
+            .map(|(token, _)| token)
+            .collect()
+    });
+    for token in tokens {
+        remove_link(&token);
+    }
+}
+
+/// Resolves a link and counts the use, failing once the link's use limit is reached. An update, so
+/// that every use is counted and passwords cannot be guessed through free queries.
+///
+/// Folder links resolve to `file_id`, which must lie under the linked folder.
+#[update]
+pub fn resolve_share_link(
+    token: Vec<u8>,
+    password: Option<String>,
+    file_id: Option<FileId>,
+) -> ResolveShareLinkResult {
+    let res: Result<DownloadPlan, DirectoryError> = (|| {
+        let mut info = open_link(&token, password)?;
+        let uses = info.use_count.unwrap_or(0);
+        if info.max_uses.is_some_and(|max| uses >= max) {
+            return Err(DirectoryError::LinkExhausted);
+        }
+
+        let plan = generate_download_plan(link_file(&info, file_id)?, info.version)?;
+        info.use_count = Some(uses + 1);
+        info.last_used_at_ns = Some(time());
+        put_link(token, info);
+        Ok(plan)
+    })();
+    res.into()
+}
+
+/// Lists a folder reached through a folder link, one page at a time in name order: the linked
+/// folder itself or, with `request.folder`, one of its subfolders. Browsing does not count as a
+/// use of the link. Entries the caller was not granted are redacted. An update like
+/// `resolve_share_link`, as it checks the link's password.
+#[update]
+pub fn browse_share_link(token: Vec<u8>, request: BrowseShareLinkRequest) -> BrowseShareLinkResult {
+    let res: Result<SharedFolderPage, DirectoryError> = (|| {
+        let info = open_link(&token, request.password)?;
+        let root = info.folder_id.ok_or(DirectoryError::InvalidRequest(
+            "Not a folder link".to_string(),
+        ))?;
+        let folder_id = request.folder.unwrap_or(root.clone());
+        if !folder_within(Some(folder_id.clone()), &root) {
+            return Err(DirectoryError::FolderNotFound);
+        }
+        let folder = get_folder(&folder_id).ok_or(DirectoryError::FolderNotFound)?;
+        let limit = request
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE) as usize;
+        let after = request
+            .cursor
+            .map(|bytes| {
+                String::from_utf8(bytes)
+                    .map_err(|_| DirectoryError::InvalidRequest("Invalid cursor".to_string()))
+            })
+            .transpose()?;
+
+        let mut entries: Vec<(String, Entry)> =
+            folder_entries(folder_id.owner, Some(folder_id.clone()))
+                .into_iter()
+                .filter(|(name, _)| after.as_ref().is_none_or(|after| name > after))
+                .take(limit + 1)
+                .collect();
+        let has_more = entries.len() > limit;
+        entries.truncate(limit);
+
+        let caller = ic_cdk::caller();
+        let mut page = SharedFolderPage {
+            folder: visible_folder(folder, caller),
+            folders: vec![],
+            files: vec![],
+            next_cursor: entries
+                .last()
+                .filter(|_| has_more)
+                .map(|(name, _)| name.clone().into_bytes()),
+        };
+        for (_, entry) in entries {
+            match entry {
+                Entry::Folder(folder_id) => page
+                    .folders
+                    .extend(get_folder(&folder_id).map(|meta| visible_folder(meta, caller))),
+                Entry::File(file_id) => page
+                    .files
+                    .extend(get_file(&file_id).map(|meta| visible_file(meta, caller))),
+            }
+        }
+        Ok(page)
+    })();
+    res.into()
+}
+
+/// The file a link serves: the linked file, or `file_id` when it lies under the linked folder.
+fn link_file(info: &LinkInfo, file_id: Option<FileId>) -> Result<FileId, DirectoryError> {
+    match (&info.folder_id, file_id) {
+        (Some(folder_id), Some(file_id)) => {
+            let meta = live_file(&file_id)?;
+            if !folder_within(meta.parent, folder_id) {
+                return Err(DirectoryError::FileNotFound);
+            }
+            Ok(file_id)
+        }
+        (Some(_), None) => Err(DirectoryError::InvalidRequest(
+            "Folder links need the id of the file to download".to_string(),
+        )),
+        (None, Some(file_id)) if info.file_id.as_ref() != Some(&file_id) => {
+            Err(DirectoryError::FileNotFound)
+        }
+        (None, _) => info.file_id.clone().ok_or(DirectoryError::LinkNotFound),
+    }
+}
+
+/// Creates an upload request: a link that lets anyone holding it, including anonymous callers,
+/// upload files into `parent`. The files are owned by the caller and count towards their quota.
+///
+/// Pass the returned token as the last argument of `start_upload`.
+#[update]
+pub async fn create_upload_request(
+    parent: Option<FolderId>,
+    ttl_ns: u64,
+    options: Option<UploadRequestOptions>,
+) -> CreateShareLinkResult {
+    let caller = ic_cdk::caller();
+    let res: Result<Vec<u8>, DirectoryError> = async {
+        if let Some(folder_id) = &parent {
+            owned_folder(folder_id, caller)?;
+        }
+        let options = options.unwrap_or_default();
+        if options
+            .label
+            .as_ref()
+            .is_some_and(|l| l.len() > MAX_NAME_BYTES)
+        {
+            return Err(DirectoryError::InvalidRequest(
+                "Label is too long".to_string(),
+            ));
+        }
+
+        let (token,): (Vec<u8>,) = ic_cdk::api::management_canister::main::raw_rand()
+            .await
+            .map_err(|(_, msg)| DirectoryError::InvalidRequest(msg))?;
+
+        let now = time();
+        let request = UploadRequest {
+            token: token.clone(),
+            owner: caller,
+            parent,
+            options,
+            created_at_ns: now,
+            expires_at_ns: now + ttl_ns,
+            uploaded_files: 0,
+            uploaded_bytes: 0,
+        };
+        UPLOAD_REQUESTS.with(|r| r.borrow_mut().insert(token.clone(), request));
+        Ok(token)
+    }
+    .await;
+    res.into()
+}
+
+/// Lists the caller's upload requests.
+#[query]
+pub fn list_upload_requests() -> Vec<UploadRequest> {
+    let caller = ic_cdk::caller();
+    UPLOAD_REQUESTS.with(|r| {
+        r.borrow()
+            .iter()
+            .map(|(_, request)| request)
+            .filter(|request| request.owner == caller)
+            .collect()
+    })
+}
+
+/// Revokes an upload request. Uploads started through it can no longer be committed.
+#[update]
+pub fn revoke_upload_request(token: Vec<u8>) -> Result<(), DirectoryError> {
+    let request = UPLOAD_REQUESTS
+        .with(|r| r.borrow().get(&token))
+        .ok_or(DirectoryError::LinkNotFound)?;
+    if request.owner != ic_cdk::caller() {
+        return Err(DirectoryError::Unauthorized);
+    }
+
+    UPLOAD_REQUESTS.with(|r| r.borrow_mut().remove(&token));
+    Ok(())
+}
+
+fn open_upload_request(token: &[u8]) -> Result<UploadRequest, DirectoryError> {
+    let request = UPLOAD_REQUESTS
+        .with(|r| r.borrow().get(&token.to_vec()))
+        .ok_or(DirectoryError::LinkNotFound)?;
+    if request.expires_at_ns < time() {
+        return Err(DirectoryError::LinkExpired);
+    }
+    Ok(request)
+}
+
+/// Checks that one more file of type `mime` and `size_bytes` fits the request's limits.
+fn check_upload_request(
+    request: &UploadRequest,
+    mime: &str,
+    size_bytes: u64,
+) -> Result<(), DirectoryError> {
+    let options = &request.options;
+    if let Some(allowed) = &options.allowed_mime_types {
+        if !allowed.iter().any(|pattern| mime_matches(pattern, mime)) {
+            return Err(DirectoryError::InvalidRequest(format!(
+                "MIME type {:?} is not allowed",
+                mime
+            )));
+        }
+    }
+    if options
+        .max_files
+        .is_some_and(|max| request.uploaded_files >= max)
+        || options
+            .max_total_bytes
+            .is_some_and(|max| request.uploaded_bytes + size_bytes > max)
+    {
+        return Err(DirectoryError::LinkExhausted);
+    }
+    Ok(())
+}
+
+/// Counts a committed upload against its request. The expiry and limits are checked again here,
+/// as the request may have run out, or had room for only some of several started uploads.
+fn record_request_upload(token: &[u8], session: &UploadSession) -> Result<(), DirectoryError> {
+    let mut request = open_upload_request(token)?;
+    check_upload_request(&request, &session.mime, session.expected_size_bytes)?;
+
+    request.uploaded_files += 1;
+    request.uploaded_bytes += session.expected_size_bytes;
+    UPLOAD_REQUESTS.with(|r| r.borrow_mut().insert(token.to_vec(), request));
+    Ok(())
+}
+
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(top_level) => mime
+            .split_once('/')
+            .is_some_and(|(kind, _)| kind == top_level),
+        None => pattern == mime,
+    }
+}
+
+/// Looks up a link, checking that it has not expired and that `password` matches.
+fn open_link(token: &[u8], password: Option<String>) -> Result<LinkInfo, DirectoryError> {
+    let info = LINKS
+        .with(|l| l.borrow().get(&token.to_vec()))
+        .ok_or(DirectoryError::LinkNotFound)?;
+    if info.expires_at < time() {
+        return Err(DirectoryError::LinkExpired);
+    }
+    if let Some(expected) = &info.password_sha256 {
+        let given = password.map(|password| hash_link_password(token, &password));
+        if given.as_ref() != Some(expected) {
+            return Err(DirectoryError::InvalidLinkPassword);
+        }
+    }
+    Ok(info)
+}
+
+/// Moves a file to the trash. Allowed for the owner and writers.
+///
+/// Trashed files keep counting towards the owner's quota until they are purged, either by
+/// `empty_trash` or once the trash retention period has passed.
+#[update]
+pub fn delete_file(file_id: FileId) -> DeleteFileResult {
+    let result: Result<(), DirectoryError> = (|| {
+        let mut meta = live_file(&file_id)?;
+
+        if !can_write(&meta, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        meta.status = FileStatus::Deleted;
+        meta.deleted_at_ns = Some(time());
+        put_file(meta);
+
+        Ok(())
+    })();
+
+    result.into()
+}
+
+/// Lists the caller's trashed files, most recently deleted first.
+#[query]
+pub fn list_trash() -> Vec<FileMeta> {
+    let mut files: Vec<FileMeta> = owned_file_ids(ic_cdk::caller())
+        .iter()
+        .filter_map(get_file)
+        .filter(|meta| meta.status == FileStatus::Deleted)
+        .collect();
+    files.sort_by_key(|meta| std::cmp::Reverse(meta.deleted_at_ns));
+    files
+}
+
+/// Moves a file out of the trash. Only the owner can restore files.
+///
+/// The file returns to its folder, or to the root folder if that folder is gone. If its name was
+/// taken in the meantime, it gets a numbered name like `report (1).pdf`.
+#[update]
+pub fn restore_file(file_id: FileId) -> UpdateFileResult {
+    let result: Result<FileMeta, DirectoryError> = (|| {
+        let mut meta = get_file(&file_id).ok_or(DirectoryError::FileNotFound)?;
+        if !is_owner(meta.file_id.owner, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+        if meta.status != FileStatus::Deleted {
+            return Err(DirectoryError::InvalidRequest(
+                "File is not in the trash".to_string(),
+            ));
+        }
+
+        if meta
+            .parent
+            .as_ref()
+            .is_some_and(|p| get_folder(p).is_none())
+        {
+            meta.parent = None;
+        }
+        meta.name = unique_name(meta.file_id.owner, meta.parent.clone(), &meta.name);
+        meta.status = FileStatus::Ready;
+        meta.deleted_at_ns = None;
+        meta.updated_at_ns = time();
+        put_file(meta.clone());
+
+        Ok(meta)
+    })();
+
+    result.into()
+}
+
+/// Permanently deletes every file in the caller's trash, including their stored chunks.
+///
+/// Returns the number of purged files.
+#[update]
+pub async fn empty_trash() -> u32 {
+    let trashed: Vec<FileId> = owned_file_ids(ic_cdk::caller())
+        .into_iter()
+        .filter(|file_id| get_file(file_id).is_some_and(|m| m.status == FileStatus::Deleted))
+        .collect();
+
+    for file_id in &trashed {
+        purge_file(file_id);
+    }
+    process_pending_deletes().await;
+
+    trashed.len() as u32
+}
+
+/// Permanently deletes files that have been in the trash for longer than the retention period.
+#[update]
+pub async fn purge_trash() {
+    let retention = read_config(|c| c.trash_retention_ns.unwrap_or(DEFAULT_TRASH_RETENTION_NS));
+    let cutoff = time().saturating_sub(retention);
+
+    for file_id in trashed_before(cutoff, 50) {
+        purge_file(&file_id);
+    }
+    process_pending_deletes().await;
+}
+
+/// Removes a file, its version history and its share links, and queues their content for deletion
+/// from the buckets.
+fn purge_file(file_id: &FileId) {
+    let Some(meta) = remove_file(file_id) else {
+        return;
+    };
+    TRANSFERS.with(|t| t.borrow_mut().remove(file_id));
+    for token in file_link_tokens(file_id) {
+        remove_link(&token);
+    }
+
+    let mut contents = vec![(meta.content_id(), meta.size_bytes)];
+    let mut freed_bytes = meta.size_bytes;
+    for version in remove_versions(file_id) {
+        freed_bytes += version.size_bytes;
+        if !contents.iter().any(|(id, _)| *id == version.content_id) {
+            contents.push((version.content_id, version.size_bytes));
+        }
+    }
+    release_used_bytes(file_id.owner, freed_bytes);
+
+    for (content_id, size_bytes) in contents {
+        queue_content_delete(content_id, size_bytes);
+    }
+}
+
+/// Forgets where content is stored and queues its deletion from the bucket holding it.
+/// `process_pending_deletes` carries the deletion out.
+fn queue_content_delete(content_id: FileId, size_bytes: u64) {
+    CONTENT_MANIFESTS.with(|m| m.borrow_mut().remove(&content_id));
+    let Some(bucket) = FILE_TO_BUCKET.with(|ftb| ftb.borrow_mut().remove(&content_id)) else {
+        return;
+    };
+    PENDING_DELETES.with(|p| {
+        p.borrow_mut().insert(
+            content_id.clone(),
+            PendingDelete {
+                content_id,
+                bucket_id: bucket.0,
+                size_bytes,
+                attempts: 0,
+                next_attempt_at_ns: time(),
+                last_error: None,
+            },
+        )
+    });
+}
+
+thread_local! {
+    /// Files whose edit is being committed. Their chunks are being swapped in the bucket, so
+    /// they cannot change hands until the commit is done.
+    static EDITS_IN_FLIGHT: RefCell<BTreeSet<FileId>> = const { RefCell::new(BTreeSet::new()) };
+    /// Content a running `process_pending_deletes` is deleting, so that overlapping runs leave it
+    /// alone.
+    static DELETES_IN_FLIGHT: RefCell<BTreeSet<FileId>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+/// Deletes queued content from the buckets. Deletions that fail stay queued and are retried later,
+/// waiting twice as long after every failure.
+#[update]
+pub async fn process_pending_deletes() {
+    let now = time();
+    let due: Vec<PendingDelete> = PENDING_DELETES.with(|p| {
+        DELETES_IN_FLIGHT.with(|f| {
+            let mut in_flight = f.borrow_mut();
+            p.borrow()
+                .iter()
+                .map(|(_, pending)| pending)
+                .filter(|pending| pending.next_attempt_at_ns <= now)
+                .filter(|pending| in_flight.insert(pending.content_id.clone()))
+                .take(50)
+                .collect()
+        })
+    });
+
+    for mut pending in due {
+        // Buckets accept deletions from the directory they record without a token
+        let res: Result<(BucketResult,), _> = ic_cdk::call(
+            pending.bucket_id,
+            "delete_file",
+            (pending.content_id.clone(), None::<DeleteToken>),
+        )
+        .await;
+        DELETES_IN_FLIGHT.with(|f| f.borrow_mut().remove(&pending.content_id));
+        let failure = match res {
+            Ok((BucketResult::Ok,)) => None,
+            Ok((BucketResult::Err(_),)) => Some("Bucket refused to delete the file".to_string()),
+            Err((code, msg)) => Some(format!("Call error: {:?} {}", code, msg)),
+        };
+
+        match failure {
+            None => {
+                if PENDING_DELETES
+                    .with(|p| p.borrow_mut().remove(&pending.content_id))
+                    .is_some()
+                {
+                    release_bucket_bytes(pending.bucket_id, pending.size_bytes);
+                }
+            }
+            Some(reason) => {
+                eprintln!("Failed to delete file from bucket: {}", reason);
+                let delay = DELETE_RETRY_BASE_NS
+                    .saturating_mul(1 << pending.attempts.min(20))
+                    .min(MAX_DELETE_RETRY_DELAY_NS);
+                pending.attempts += 1;
+                pending.next_attempt_at_ns = time() + delay;
+                pending.last_error = Some(reason);
+                // Only reschedule content that is still queued
+                PENDING_DELETES.with(|p| {
+                    let mut map = p.borrow_mut();
+                    if map.contains_key(&pending.content_id) {
+                        map.insert(pending.content_id.clone(), pending);
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Lists the content waiting to be deleted from the buckets.
+#[query]
+pub fn admin_list_pending_deletes() -> ListPendingDeletesResult {
+    let result: Result<Vec<PendingDelete>, DirectoryError> = (|| {
+        if !is_admin(ic_cdk::caller()) {
+            return Err(DirectoryError::AdminOnly);
+        }
+        Ok(PENDING_DELETES.with(|p| p.borrow().iter().map(|(_, pending)| pending).collect()))
+    })();
+
+    result.into()
+}
+
+fn add_bucket_bytes(bucket_id: Principal, bytes: u64) {
+    let key = StorablePrincipal(bucket_id);
+    BUCKETS.with(|b| {
+        let mut map = b.borrow_mut();
+        if let Some(mut info) = map.get(&key) {
+            info.used_bytes += bytes;
+            map.insert(key, info);
+        }
+    });
+}
+
+fn release_bucket_bytes(bucket_id: Principal, bytes: u64) {
+    let key = StorablePrincipal(bucket_id);
+    BUCKETS.with(|b| {
+        let mut map = b.borrow_mut();
+        if let Some(mut info) = map.get(&key) {
+            info.used_bytes = info.used_bytes.saturating_sub(bytes);
+            map.insert(key, info);
+        }
+    });
+}
+
+/// Grants `principal` a role on a file, until `expires_at_ns` when given. Only the owner can
+/// grant access.
+///
+/// A principal's reader and writer grants on a file share one expiry; granting again replaces it.
+#[update]
+pub fn add_file_access(
+    file_id: FileId,
+    principal: UserId,
+    role: FileRole,
+    expires_at_ns: Option<u64>,
+) -> Result<(), DirectoryError> {
+    let mut meta = live_file(&file_id)?;
+    if !is_owner(meta.file_id.owner, ic_cdk::caller()) {
+        return Err(DirectoryError::Unauthorized);
+    }
+    if expires_at_ns.is_some_and(|t| t <= time()) {
+        return Err(DirectoryError::InvalidRequest(
+            "Expiry must be in the future".to_string(),
+        ));
+    }
+    match role {
+        FileRole::Reader => {
+            if !meta.readers.contains(&principal) {
+                meta.readers.push(principal);
+            }
+        }
+        FileRole::Writer => {
+            if !meta.writers.contains(&principal) {
+                meta.writers.push(principal);
+            }
+        }
+    }
+    let expirations = meta.access_expires_at_ns.get_or_insert_with(BTreeMap::new);
+    match expires_at_ns {
+        Some(t) => expirations.insert(principal, t),
+        None => expirations.remove(&principal),
+    };
+    if expirations.is_empty() {
+        meta.access_expires_at_ns = None;
+    }
+    put_file(meta);
+    Ok(())
+}
+
+#[update]
+pub fn remove_file_access(file_id: FileId, principal: UserId) -> Result<(), DirectoryError> {
+    let mut meta = get_file(&file_id).ok_or(DirectoryError::FileNotFound)?;
+    if !is_owner(meta.file_id.owner, ic_cdk::caller()) {
+        return Err(DirectoryError::Unauthorized);
+    }
+    revoke_access(&mut meta, principal);
+    put_file(meta);
+    Ok(())
+}
+
+/// Removes the reader and writer grants that have expired.
+#[update]
+pub fn sweep_expired_grants() {
+    for (file_id, principal) in expired_grants(time(), 100) {
+        if let Some(mut meta) = get_file(&file_id) {
+            revoke_access(&mut meta, principal);
+            put_file(meta);
+        }
+    }
+}
+
+fn revoke_access(meta: &mut FileMeta, principal: UserId) {
+    meta.readers.retain(|r| r != &principal);
+    meta.writers.retain(|w| w != &principal);
+    if let Some(expirations) = &mut meta.access_expires_at_ns {
+        expirations.remove(&principal);
+        if expirations.is_empty() {
+            meta.access_expires_at_ns = None;
+        }
+    }
+}
+
+/// Offers a file to another principal, replacing any pending offer. Only the owner can offer.
+///
+/// Nothing changes until the recipient calls `accept_file_transfer`.
+#[update]
+pub fn offer_file_transfer(file_id: FileId, to: UserId) -> Result<(), DirectoryError> {
+    let caller = ic_cdk::caller();
+    let meta = live_file(&file_id)?;
+    if !is_owner(meta.file_id.owner, caller) {
+        return Err(DirectoryError::Unauthorized);
+    }
+    if to == meta.file_id.owner {
+        return Err(DirectoryError::InvalidRequest(
+            "Cannot transfer a file to its owner".to_string(),
+        ));
+    }
+
+    TRANSFERS.with(|t| {
+        t.borrow_mut().insert(
+            file_id.clone(),
+            TransferOffer {
+                file_id,
+                to,
+                created_at_ns: time(),
+            },
+        )
+    });
+    Ok(())
+}
+
+/// Withdraws or declines a transfer offer. Allowed for the owner and the recipient.
+#[update]
+pub fn cancel_file_transfer(file_id: FileId) -> Result<(), DirectoryError> {
+    let caller = ic_cdk::caller();
+    let offer = TRANSFERS
+        .with(|t| t.borrow().get(&file_id))
+        .ok_or(DirectoryError::TransferNotFound)?;
+    if !is_owner(offer.file_id.owner, caller) && offer.to != caller {
+        return Err(DirectoryError::Unauthorized);
+    }
+
+    TRANSFERS.with(|t| t.borrow_mut().remove(&file_id));
+    Ok(())
+}
+
+/// Lists the pending transfer offers the caller made or received.
+#[query]
+pub fn list_file_transfers() -> Vec<TransferOffer> {
+    let caller = ic_cdk::caller();
+    TRANSFERS.with(|t| {
+        t.borrow()
+            .iter()
+            .map(|(_, offer)| offer)
+            .filter(|offer| is_owner(offer.file_id.owner, caller) || offer.to == caller)
+            .collect()
+    })
+}
+
+/// Accepts a transfer offer: the caller becomes the owner of the file, under `parent`.
+///
+/// The file gets a new id with the caller as owner, and its versions, access lists, tags and share
+/// links move along. The stored chunks stay where they are. The file's bytes, including its
+/// version history, move from the previous owner's usage to the caller's. Open version and edit
+/// sessions of the file are aborted, and the transfer waits for an edit being committed.
+#[update]
+pub fn accept_file_transfer(file_id: FileId, parent: Option<FolderId>) -> UpdateFileResult {
+    let result: Result<FileMeta, DirectoryError> = (|| {
+        let caller = ic_cdk::caller();
+        let offer = TRANSFERS
+            .with(|t| t.borrow().get(&file_id))
+            .ok_or(DirectoryError::TransferNotFound)?;
+        if offer.to != caller {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        let mut meta = live_file(&file_id)?;
+        if meta.status != FileStatus::Ready {
+            return Err(DirectoryError::InvalidRequest(
+                "File is not ready".to_string(),
+            ));
+        }
+        if let Some(folder_id) = &parent {
+            owned_folder(folder_id, caller)?;
+        }
+        if EDITS_IN_FLIGHT.with(|e| e.borrow().contains(&file_id)) {
+            return Err(DirectoryError::InvalidRequest(
+                "An edit of the file is being committed".to_string(),
+            ));
+        }
+
+        let versions = file_versions(&file_id);
+        let total_bytes = meta.size_bytes + versions.iter().map(|v| v.size_bytes).sum::<u64>();
+        check_account(caller, total_bytes)?;
+
+        // 1. Re-key the file and its history under the new owner. The content keeps its storage id.
+        let new_id = FileId {
+            owner: caller,
+            id: file_id.id.clone(),
+        };
+        remove_file(&file_id);
+        remove_versions(&file_id);
+        for version in versions {
+            put_version(&new_id, version);
+        }
+
+        meta.content_id = Some(meta.content_id());
+        meta.file_id = new_id.clone();
+        meta.name = unique_name(caller, parent.clone(), &meta.name);
+        meta.parent = parent;
+        revoke_access(&mut meta, caller);
+        meta.updated_at_ns = time();
+        put_file(meta.clone());
+
+        // 2. Move usage and links. Version and edit sessions were opened under the old id, so they
+        //    are aborted.
+        release_used_bytes(file_id.owner, total_bytes);
+        add_used_bytes(caller, total_bytes);
+        for token in file_link_tokens(&file_id) {
+            if let Some(mut info) = remove_link(&token) {
+                info.file_id = Some(new_id.clone());
+                put_link(token, info);
+            }
+        }
+        abort_file_sessions(&file_id);
+        TRANSFERS.with(|t| t.borrow_mut().remove(&file_id));
+
+        Ok(meta)
+    })();
+
+    result.into()
+}
+
+/// Aborts the version and edit sessions open on a file.
+fn abort_file_sessions(file_id: &FileId) {
+    UPLOADS.with(|u| {
+        let mut uploads = u.borrow_mut();
+        let upload_ids: Vec<Vec<u8>> = uploads
+            .iter()
+            .filter(|(_, session)| {
+                session.version_of.as_ref() == Some(file_id)
+                    || session.edit_of.as_ref() == Some(file_id)
+            })
+            .map(|(upload_id, _)| upload_id)
+            .collect();
+        for upload_id in upload_ids {
+            uploads.remove(&upload_id);
+        }
+    });
+}
+
+/// Creates a group owned by the caller.
+#[update]
+pub fn create_group(name: String, members: Vec<UserId>) -> CreateGroupResult {
+    let result: Result<Group, DirectoryError> = (|| {
+        validate_name(&name)?;
+        let mut group = Group {
+            group_id: GroupId {
+                owner: ic_cdk::caller(),
+                id: new_id(),
+            },
+            name,
+            members: vec![],
+            created_at_ns: time(),
+            updated_at_ns: time(),
+        };
+        add_group_members(&mut group, members)?;
+        put_group(group.clone());
+
+        Ok(group)
+    })();
+
+    result.into()
+}
+
+/// Returns a group. Visible to its owner and members.
+#[query]
+pub fn get_group(group_id: GroupId) -> GetGroupResult {
+    let result: Result<Group, DirectoryError> = (|| {
+        let caller = ic_cdk::caller();
+        let group = get_group_meta(&group_id).ok_or(DirectoryError::GroupNotFound)?;
+        if group.group_id.owner != caller && !group.members.contains(&caller) {
+            return Err(DirectoryError::Unauthorized);
+        }
+        Ok(group)
+    })();
+
+    result.into()
+}
+
+/// Lists the groups the caller owns.
+#[query]
+pub fn list_groups() -> Vec<Group> {
+    owned_groups(ic_cdk::caller())
+}
+
+/// Renames a group or changes its members. Only the owner can update a group.
+///
+/// Membership changes apply at once to every file shared with the group.
+#[update]
+pub fn update_group(group_id: GroupId, request: UpdateGroupRequest) -> UpdateGroupResult {
+    let result: Result<Group, DirectoryError> = (|| {
+        let mut group = owned_group(&group_id, ic_cdk::caller())?;
+
+        if let Some(name) = request.name {
+            validate_name(&name)?;
+            group.name = name;
+        }
+        add_group_members(&mut group, request.add_members.unwrap_or_default())?;
+        if let Some(members) = request.remove_members {
+            group.members.retain(|m| !members.contains(m));
+        }
+
+        group.updated_at_ns = time();
+        put_group(group.clone());
+
+        Ok(group)
+    })();
+
+    result.into()
+}
+
+/// Deletes a group and revokes the access it granted. Only the owner can delete a group.
+#[update]
+pub fn delete_group(group_id: GroupId) -> Result<(), DirectoryError> {
+    owned_group(&group_id, ic_cdk::caller())?;
+
+    for file_id in group_file_ids(&group_id) {
+        if let Some(mut meta) = get_file(&file_id) {
+            revoke_group(&mut meta, &group_id);
+            put_file(meta);
+        }
+    }
+    remove_group(&group_id);
+    Ok(())
+}
+
+/// Shares a file with one of the caller's groups. Only the owner can share files.
+#[update]
+pub fn add_group_access(
+    file_id: FileId,
+    group_id: GroupId,
+    role: FileRole,
+) -> Result<(), DirectoryError> {
+    let caller = ic_cdk::caller();
+    let mut meta = live_file(&file_id)?;
+    if !is_owner(meta.file_id.owner, caller) {
+        return Err(DirectoryError::Unauthorized);
+    }
+    owned_group(&group_id, caller)?;
+
+    let groups = match role {
+        FileRole::Reader => meta.reader_groups.get_or_insert_with(Vec::new),
+        FileRole::Writer => meta.writer_groups.get_or_insert_with(Vec::new),
+    };
+    if !groups.contains(&group_id) {
+        groups.push(group_id);
+    }
+    put_file(meta);
+    Ok(())
+}
+
+/// Stops sharing a file with a group. Only the owner can change access.
+#[update]
+pub fn remove_group_access(file_id: FileId, group_id: GroupId) -> Result<(), DirectoryError> {
+    let mut meta = get_file(&file_id).ok_or(DirectoryError::FileNotFound)?;
+    if !is_owner(meta.file_id.owner, ic_cdk::caller()) {
+        return Err(DirectoryError::Unauthorized);
+    }
+    revoke_group(&mut meta, &group_id);
+    put_file(meta);
+    Ok(())
+}
+
+fn owned_group(group_id: &GroupId, caller: Principal) -> Result<Group, DirectoryError> {
+    let group = get_group_meta(group_id).ok_or(DirectoryError::GroupNotFound)?;
+    if group.group_id.owner != caller {
+        return Err(DirectoryError::Unauthorized);
+    }
+    Ok(group)
+}
+
+fn add_group_members(group: &mut Group, members: Vec<UserId>) -> Result<(), DirectoryError> {
+    for member in members {
+        if !group.members.contains(&member) {
+            group.members.push(member);
+        }
+    }
+    if group.members.len() > MAX_GROUP_MEMBERS {
+        return Err(DirectoryError::InvalidRequest(format!(
+            "At most {} members are allowed in a group",
+            MAX_GROUP_MEMBERS
+        )));
+    }
+    Ok(())
+}
+
+fn revoke_group(meta: &mut FileMeta, group_id: &GroupId) {
+    for groups in [&mut meta.reader_groups, &mut meta.writer_groups] {
+        if let Some(ids) = groups {
+            ids.retain(|g| g != group_id);
+            if ids.is_empty() {
+                *groups = None;
+            }
+        }
+    }
+}
+
+/// Creates an organization with the caller as its only admin.
+///
+/// The organization gets its own principal, which owns the files its members upload for it and
+/// holds its quota and prepaid balance. Its quota starts at the default for new accounts.
+#[update]
+pub fn create_organization(name: String) -> CreateOrganizationResult {
+    let result: Result<Organization, DirectoryError> = (|| {
+        validate_name(&name)?;
+        // Derived principals (class 0x03) are never issued to signers
+        let mut bytes = new_id();
+        bytes.push(0x03);
+        let org = Organization {
+            org_id: Principal::from_slice(&bytes),
+            name,
+            members: vec![OrgMember {
+                principal: ic_cdk::caller(),
+                role: OrgRole::Admin,
+            }],
+            created_at_ns: time(),
+            updated_at_ns: time(),
+        };
+        put_org(org.clone());
+        USERS.with(|u| {
+            u.borrow_mut().insert(
+                StorablePrincipal(org.org_id),
+                UserState {
+                    used_bytes: 0,
+                    quota_bytes: 10 * GIB,
+                    expires_at_ns: None,
+                    prepaid_balance: 0,
+                },
+            )
+        });
+
+        Ok(org)
+    })();
+
+    result.into()
+}
+
+/// Returns an organization. Visible to its members.
+#[query]
+pub fn get_organization(org_id: UserId) -> GetOrganizationResult {
+    let result: Result<Organization, DirectoryError> = (|| {
+        let org = get_org(org_id).ok_or(DirectoryError::OrganizationNotFound)?;
+        if !org.members.iter().any(|m| m.principal == ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+        Ok(org)
+    })();
+
+    result.into()
+}
+
+/// Lists the organizations the caller belongs to.
+#[query]
+pub fn list_organizations() -> Vec<Organization> {
+    member_org_ids(ic_cdk::caller())
+        .into_iter()
+        .filter_map(get_org)
+        .collect()
+}
+
+/// Adds a member to an organization, or changes their role. Only admins can manage members.
+#[update]
+pub fn set_org_member(
+    org_id: UserId,
+    principal: UserId,
+    role: OrgRole,
+) -> Result<(), DirectoryError> {
+    let mut org = get_org(org_id).ok_or(DirectoryError::OrganizationNotFound)?;
+    if org_role_in(&org, ic_cdk::caller()) != Some(OrgRole::Admin) {
+        return Err(DirectoryError::Unauthorized);
+    }
+
+    match org.members.iter_mut().find(|m| m.principal == principal) {
+        Some(member) => member.role = role,
+        None => {
+            if org.members.len() >= MAX_ORG_MEMBERS {
+                return Err(DirectoryError::InvalidRequest(format!(
+                    "At most {} members are allowed in an organization",
+                    MAX_ORG_MEMBERS
+                )));
+            }
+            org.members.push(OrgMember { principal, role });
+        }
+    }
+    ensure_org_admin(&org)?;
+
+    org.updated_at_ns = time();
+    put_org(org);
+    Ok(())
+}
+
+/// Removes a member from an organization. Admins can remove anyone; members can leave.
+#[update]
+pub fn remove_org_member(org_id: UserId, principal: UserId) -> Result<(), DirectoryError> {
+    let caller = ic_cdk::caller();
+    let mut org = get_org(org_id).ok_or(DirectoryError::OrganizationNotFound)?;
+    if caller != principal && org_role_in(&org, caller) != Some(OrgRole::Admin) {
+        return Err(DirectoryError::Unauthorized);
+    }
+
+    org.members.retain(|m| m.principal != principal);
+    ensure_org_admin(&org)?;
+
+    org.updated_at_ns = time();
+    put_org(org);
+    Ok(())
+}
+
+/// Fails if no member of `org` is left to manage it.
+fn ensure_org_admin(org: &Organization) -> Result<(), DirectoryError> {
+    if !org.members.iter().any(|m| m.role == OrgRole::Admin) {
+        return Err(DirectoryError::InvalidRequest(
+            "An organization needs at least one admin".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn org_role_in(org: &Organization, principal: Principal) -> Option<OrgRole> {
+    org.members
+        .iter()
+        .find(|m| m.principal == principal)
+        .map(|m| m.role)
+}
+
+/// Role of `principal` in the organization `org_id`; `None` if `org_id` is not an organization
+/// or `principal` is not a member.
+fn org_role(org_id: UserId, principal: Principal) -> Option<OrgRole> {
+    get_org(org_id).and_then(|org| org_role_in(&org, principal))
+}
+
+/// Whether `caller` can store files for `owner`: their own account, or an organization they are an
+/// admin or member of.
+fn can_upload_for(owner: UserId, caller: Principal) -> bool {
+    owner == caller
+        || matches!(
+            org_role(owner, caller),
+            Some(OrgRole::Admin | OrgRole::Member)
+        )
+}
+
+/// Whether `caller` has the owner's rights over `owner`'s files: the owner, or an admin of the
+/// organization that owns them.
+fn is_owner(owner: UserId, caller: Principal) -> bool {
+    owner == caller || org_role(owner, caller) == Some(OrgRole::Admin)
+}
+
+/// Lists every kept version of a file, newest first. The first entry is the current version.
+#[query]
+pub fn list_versions(file_id: FileId) -> ListVersionsResult {
+    let result: Result<Vec<FileVersion>, DirectoryError> = (|| {
+        let meta = live_file(&file_id)?;
+        if !can_read(&meta, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        let mut versions = vec![current_version(&meta)];
+        versions.extend(file_versions(&file_id).into_iter().rev());
+        Ok(versions)
+    })();
+
+    result.into()
+}
+
+/// Makes the content of a past version current again. Allowed for the owner and writers.
+///
+/// The restored content becomes a new version, so the history is kept intact. Like any other
+/// kept version, it counts towards the owner's quota.
+#[update]
+pub fn restore_version(file_id: FileId, version: u32) -> UpdateFileResult {
+    let result: Result<FileMeta, DirectoryError> = (|| {
+        let mut meta = live_file(&file_id)?;
+        if !can_write(&meta, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+        if version == meta.version() {
+            return Ok(meta);
+        }
+        let restored =
+            get_version(&file_id, version).ok_or(DirectoryError::VersionNotFound(version))?;
+
+        let user_state = USERS
+            .with(|u| u.borrow().get(&StorablePrincipal(file_id.owner)))
+            .unwrap_or_default();
+        check_quota(&user_state, restored.size_bytes)?;
+
+        put_version(&file_id, current_version(&meta));
+        meta.version = Some(meta.version() + 1);
+        meta.content_id = Some(restored.content_id);
+        meta.mime = restored.mime;
+        meta.size_bytes = restored.size_bytes;
+        meta.chunk_size = restored.chunk_size;
+        meta.chunk_count = restored.chunk_count;
+        meta.sha256 = restored.sha256;
+        meta.merkle_root = restored.merkle_root;
+        meta.updated_at_ns = time();
+        put_file(meta.clone());
+        add_used_bytes(file_id.owner, restored.size_bytes);
+
+        Ok(meta)
+    })();
+
+    result.into()
+}
+
+/// Deletes past versions beyond the newest `keep_latest`, or older than `max_age_ns`, or both.
+/// The current version is never pruned. Allowed for the owner and writers.
+///
+/// Returns the pruned version numbers.
+#[update]
+pub fn prune_versions(
+    file_id: FileId,
+    keep_latest: Option<u32>,
+    max_age_ns: Option<u64>,
+) -> PruneVersionsResult {
+    let result: Result<Vec<u32>, DirectoryError> = (|| {
+        let meta = get_file(&file_id).ok_or(DirectoryError::FileNotFound)?;
+        if !can_write(&meta, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+        if keep_latest.is_none() && max_age_ns.is_none() {
+            return Err(DirectoryError::InvalidRequest(
+                "Either keep_latest or max_age_ns is required".to_string(),
+            ));
+        }
+
+        let now = time();
+        let mut pruned = vec![];
+        for (index, version) in file_versions(&file_id).into_iter().rev().enumerate() {
+            let beyond_count = keep_latest.is_some_and(|keep| index >= keep as usize);
+            let too_old =
+                max_age_ns.is_some_and(|age| now.saturating_sub(version.created_at_ns) > age);
+            if beyond_count || too_old {
+                remove_version(&file_id, version.version);
+                pruned.push(version);
+            }
+        }
+
+        // Restored versions share content, so only forget content no version references anymore
+        let mut in_use: Vec<FileId> = file_versions(&file_id)
+            .into_iter()
+            .map(|v| v.content_id)
+            .collect();
+        in_use.push(meta.content_id());
+        for version in &pruned {
+            if !in_use.contains(&version.content_id) {
+                queue_content_delete(version.content_id.clone(), version.size_bytes);
+            }
+        }
+        release_used_bytes(file_id.owner, pruned.iter().map(|v| v.size_bytes).sum());
+
+        Ok(pruned.iter().map(|v| v.version).collect())
+    })();
+
+    result.into()
+}
+
+#[update]
+pub fn create_folder(name: String, parent: Option<FolderId>) -> CreateFolderResult {
+    let result: Result<FolderMeta, DirectoryError> = (|| {
+        let caller = ic_cdk::caller();
+        validate_name(&name)?;
+        if let Some(folder_id) = &parent {
+            owned_folder(folder_id, caller)?;
+        }
+        ensure_name_free(caller, parent.clone(), &name)?;
+
+        let meta = FolderMeta {
+            folder_id: FolderId {
+                owner: caller,
+                id: new_id(),
+            },
+            name,
+            parent,
+            created_at_ns: time(),
+            updated_at_ns: time(),
+            readers: None,
+            writers: None,
+        };
+        put_folder(meta.clone());
+
+        Ok(meta)
+    })();
+
+    result.into()
+}
+
+#[query]
+pub fn get_folder_meta(folder_id: FolderId) -> GetFolderResult {
+    let result: Result<FolderMeta, DirectoryError> = readable_folder(&folder_id, ic_cdk::caller());
+
+    result.into()
+}
+
+/// Lists the subfolders and files of `folder`, or of the caller's root folder when `None`.
+///
+/// Folders shared with the caller, directly or through a parent folder, can be listed too.
+#[query]
+pub fn list_folder(folder: Option<FolderId>) -> ListFolderResult {
+    let result: Result<FolderListing, DirectoryError> = (|| {
+        let caller = ic_cdk::caller();
+        let folder = folder
+            .map(|folder_id| readable_folder(&folder_id, caller))
+            .transpose()?;
+        let owner = folder.as_ref().map_or(caller, |f| f.folder_id.owner);
+        let parent = folder.as_ref().map(|f| f.folder_id.clone());
+
+        let mut listing = FolderListing {
+            folder,
+            folders: vec![],
+            files: vec![],
+        };
+        for (_, entry) in folder_entries(owner, parent) {
+            match entry {
+                Entry::Folder(folder_id) => listing.folders.extend(get_folder(&folder_id)),
+                Entry::File(file_id) => listing.files.extend(get_file(&file_id)),
+            }
+        }
+
+        Ok(listing)
+    })();
+
+    result.into()
+}
+
+/// Grants `principal` a role on every file under a folder, including files in subfolders and files
+/// added later. Only the owner can share folders.
+///
+/// Granting again replaces the principal's role on the folder. A grant on a file, or on a folder
+/// closer to it, takes precedence over the role inherited from this folder.
+#[update]
+pub fn add_folder_access(
+    folder_id: FolderId,
+    principal: UserId,
+    role: FileRole,
+) -> Result<(), DirectoryError> {
+    let mut meta = owned_folder(&folder_id, ic_cdk::caller())?;
+    revoke_folder_access(&mut meta, principal);
+    let grantees = match role {
+        FileRole::Reader => meta.readers.get_or_insert_with(Vec::new),
+        FileRole::Writer => meta.writers.get_or_insert_with(Vec::new),
+    };
+    grantees.push(principal);
+    put_folder(meta);
+    Ok(())
+}
+
+/// Stops sharing a folder with `principal`. Only the owner can change access.
+#[update]
+pub fn remove_folder_access(folder_id: FolderId, principal: UserId) -> Result<(), DirectoryError> {
+    let mut meta = owned_folder(&folder_id, ic_cdk::caller())?;
+    revoke_folder_access(&mut meta, principal);
+    put_folder(meta);
+    Ok(())
+}
+
+fn revoke_folder_access(meta: &mut FolderMeta, principal: UserId) {
+    for grantees in [&mut meta.readers, &mut meta.writers] {
+        if let Some(ids) = grantees {
+            ids.retain(|p| *p != principal);
+            if ids.is_empty() {
+                *grantees = None;
+            }
+        }
+    }
+}
+
+/// Deletes an empty folder.
+#[update]
+pub fn delete_folder(folder_id: FolderId) -> DeleteFolderResult {
+    let result: Result<(), DirectoryError> = (|| {
+        let caller = ic_cdk::caller();
+        owned_folder(&folder_id, caller)?;
+        if !folder_entries(caller, Some(folder_id.clone())).is_empty() {
+            return Err(DirectoryError::FolderNotEmpty);
+        }
+
+        remove_folder(&folder_id);
+        remove_links(|info| info.folder_id.as_ref() == Some(&folder_id));
+        Ok(())
+    })();
+
+    result.into()
+}
+
+#[update]
+pub fn rename_folder(folder_id: FolderId, name: String) -> UpdateFolderResult {
+    let result: Result<FolderMeta, DirectoryError> = (|| {
+        let mut meta = owned_folder(&folder_id, ic_cdk::caller())?;
+        validate_name(&name)?;
+        if meta.name == name {
+            return Ok(meta);
+        }
+        ensure_name_free(folder_id.owner, meta.parent.clone(), &name)?;
+
+        meta.name = name;
+        meta.updated_at_ns = time();
+        put_folder(meta.clone());
+
+        Ok(meta)
+    })();
+
+    result.into()
+}
+
+/// Moves a folder, with everything inside it, under `parent` (`None` is the root folder).
+#[update]
+pub fn move_folder(folder_id: FolderId, parent: Option<FolderId>) -> UpdateFolderResult {
+    let result: Result<FolderMeta, DirectoryError> = (|| {
+        let caller = ic_cdk::caller();
+        let mut meta = owned_folder(&folder_id, caller)?;
+        if meta.parent == parent {
+            return Ok(meta);
+        }
+
+        // Refuse to move a folder below itself
+        let mut ancestor = parent.clone();
+        while let Some(current) = ancestor {
+            if current == folder_id {
+                return Err(DirectoryError::InvalidRequest(
+                    "Cannot move a folder into itself".to_string(),
+                ));
+            }
+            ancestor = owned_folder(&current, caller)?.parent;
+        }
+        ensure_name_free(caller, parent.clone(), &meta.name)?;
+
+        meta.parent = parent;
+        meta.updated_at_ns = time();
+        put_folder(meta.clone());
+
+        Ok(meta)
+    })();
+
+    result.into()
+}
+
+/// Renames a file in place. Allowed for the owner and writers.
+#[update]
+pub fn rename_file(file_id: FileId, name: String) -> UpdateFileResult {
+    let result: Result<FileMeta, DirectoryError> = (|| {
+        let caller = ic_cdk::caller();
+        let mut meta = live_file(&file_id)?;
+        if !can_write(&meta, caller) {
+            return Err(DirectoryError::Unauthorized);
+        }
+        validate_name(&name)?;
+        if meta.name == name {
+            return Ok(meta);
+        }
+        ensure_name_free(file_id.owner, meta.parent.clone(), &name)?;
+
+        meta.name = name;
+        meta.updated_at_ns = time();
+        put_file(meta.clone());
+
+        Ok(meta)
+    })();
+
+    result.into()
+}
+
+/// Makes a file private, public or unlisted. Only the owner can change visibility.
+#[update]
+pub fn set_file_visibility(file_id: FileId, visibility: Visibility) -> UpdateFileResult {
+    let result: Result<FileMeta, DirectoryError> = (|| {
+        let mut meta = live_file(&file_id)?;
+        if !is_owner(meta.file_id.owner, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        meta.visibility = Some(visibility);
+        meta.updated_at_ns = time();
+        put_file(meta.clone());
+
+        Ok(meta)
+    })();
+
+    result.into()
+}
+
+/// Lists `owner`'s public files that are ready for download. Open to any caller; files the caller
+/// was not granted are redacted.
+#[query]
+pub fn list_public_files(owner: UserId) -> Vec<FileMeta> {
+    let caller = ic_cdk::caller();
+    public_file_ids(owner)
+        .iter()
+        .filter_map(get_file)
+        .filter(|meta| meta.status == FileStatus::Ready)
+        .map(|meta| visible_file(meta, caller))
+        .collect()
+}
+
+/// Updates the name, MIME type or attributes of a file. Allowed for the owner and writers.
+#[update]
+pub fn update_file_meta(file_id: FileId, request: UpdateFileMetaRequest) -> UpdateFileResult {
+    let result: Result<FileMeta, DirectoryError> = (|| {
+        let mut meta = live_file(&file_id)?;
+        if !can_write(&meta, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        if let Some(name) = request.name {
+            validate_name(&name)?;
+            if meta.name != name {
+                ensure_name_free(file_id.owner, meta.parent.clone(), &name)?;
+                meta.name = name;
+            }
+        }
+        if let Some(mime) = request.mime {
+            if mime.is_empty() || mime.len() > MAX_MIME_BYTES {
+                return Err(DirectoryError::InvalidRequest(format!(
+                    "Invalid MIME type: {:?}",
+                    mime
+                )));
+            }
+            meta.mime = mime;
+        }
+
+        let mut attributes = meta.attributes.take().unwrap_or_default();
+        for key in request.remove_attributes.unwrap_or_default() {
+            attributes.remove(&key);
+        }
+        for (key, value) in request.set_attributes.unwrap_or_default() {
+            if key.is_empty()
+                || key.len() > MAX_ATTRIBUTE_KEY_BYTES
+                || value.len() > MAX_ATTRIBUTE_VALUE_BYTES
+            {
+                return Err(DirectoryError::InvalidRequest(format!(
+                    "Invalid attribute: {:?}",
+                    key
+                )));
+            }
+            attributes.insert(key, value);
+        }
+        if attributes.len() > MAX_ATTRIBUTES {
+            return Err(DirectoryError::InvalidRequest(format!(
+                "At most {} attributes are allowed",
+                MAX_ATTRIBUTES
+            )));
+        }
+        meta.attributes = (!attributes.is_empty()).then_some(attributes);
+
+        meta.updated_at_ns = time();
+        put_file(meta.clone());
+
+        Ok(meta)
+    })();
+
+    result.into()
+}
+
+/// Adds tags to a file. Allowed for the owner and writers.
+#[update]
+pub fn add_file_tags(file_id: FileId, tags: Vec<String>) -> UpdateFileResult {
+    let result: Result<FileMeta, DirectoryError> = (|| {
+        let mut meta = live_file(&file_id)?;
+        if !can_write(&meta, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
+
+        let mut current = meta.tags.take().unwrap_or_default();
+        for tag in tags {
+            validate_tag(&tag)?;
+            if let Err(index) = current.binary_search(&tag) {
+                current.insert(index, tag);
+            }
+        }
+        if current.len() > MAX_TAGS {
+            return Err(DirectoryError::InvalidRequest(format!(
+                "At most {} tags are allowed",
+                MAX_TAGS
+            )));
+        }
+        meta.tags = Some(current);
+
+        meta.updated_at_ns = time();
+        put_file(meta.clone());
+
+        Ok(meta)
+    })();
+
+    result.into()
+}
 
-        if meta.file_id.owner != ic_cdk::caller()
-            && !meta.readers.contains(&ic_cdk::caller())
-            && !meta.writers.contains(&ic_cdk::caller())
-        {
+/// Removes tags from a file. Allowed for the owner and writers.
+#[update]
+pub fn remove_file_tags(file_id: FileId, tags: Vec<String>) -> UpdateFileResult {
+    let result: Result<FileMeta, DirectoryError> = (|| {
+        let mut meta = live_file(&file_id)?;
+        if !can_write(&meta, ic_cdk::caller()) {
             return Err(DirectoryError::Unauthorized);
         }
 
+        let mut current = meta.tags.take().unwrap_or_default();
+        current.retain(|tag| !tags.contains(tag));
+        meta.tags = (!current.is_empty()).then_some(current);
+
+        meta.updated_at_ns = time();
+        put_file(meta.clone());
+
         Ok(meta)
     })();
 
     result.into()
 }
 
-#[query]
-pub fn get_download_plan(file_id: FileId) -> GetDownloadPlanResult {
-    let result: Result<DownloadPlan, DirectoryError> = (|| {
-        let meta = FILES
-            .with(|f| f.borrow().get(&file_id))
-            .ok_or(DirectoryError::FileNotFound)?;
+/// Copies a file without uploading its bytes again. The caller needs read access to the file.
+///
+/// The copy belongs to `owner`, which defaults to the caller. Writers may also copy a file for
+/// its owner, next to the original. The copy lands under `parent` with `name` (the original name
+/// by default) and takes over the current version's content, MIME type, attributes and tags, but
+/// not the version history or access lists. It counts towards the new owner's quota, and stays
+/// `Pending` until the buckets have copied its chunks, which are then sealed like those of a
+/// committed upload.
+#[update]
+pub async fn copy_file(
+    file_id: FileId,
+    name: Option<String>,
+    parent: Option<FolderId>,
+    owner: Option<UserId>,
+    payment: Option<PaymentType>,
+) -> CommitUploadResult {
+    let result: Result<FileMeta, DirectoryError> = async {
+        let caller = ic_cdk::caller();
+        let source = live_file(&file_id)?;
+        if !can_read(&source, caller) {
+            return Err(DirectoryError::Unauthorized);
+        }
+        if source.status != FileStatus::Ready {
+            return Err(DirectoryError::InvalidRequest(
+                "File is not ready".to_string(),
+            ));
+        }
 
-        if meta.file_id.owner != ic_cdk::caller()
-            && !meta.readers.contains(&ic_cdk::caller())
-            && !meta.writers.contains(&ic_cdk::caller())
+        let owner = owner.unwrap_or(caller);
+        if !can_upload_for(owner, caller) && !(owner == file_id.owner && can_write(&source, caller))
         {
             return Err(DirectoryError::Unauthorized);
         }
 
-        generate_download_plan(file_id)
-    })();
+        // 1. Validate the destination before charging anything
+        let name = name.unwrap_or_else(|| source.name.clone());
+        validate_name(&name)?;
+        if let Some(folder_id) = &parent {
+            owned_folder(folder_id, owner)?;
+        }
+        ensure_name_free(owner, parent.clone(), &name)?;
 
-    result.into()
-}
+        // 2. Payment, quota and expiration
+        charge_upload(owner, source.size_bytes, payment).await?;
 
-fn generate_download_plan(file_id: FileId) -> Result<DownloadPlan, DirectoryError> {
-    let meta = FILES
-        .with(|f| f.borrow().get(&file_id))
-        .ok_or(DirectoryError::FileNotFound)?;
+        // 3. Reserve the name and the bytes while the chunks are copied
+        let source_bucket = content_bucket(&source.content_id())?;
+        let target_bucket = writable_bucket()?;
+        // The name may have been taken while the payment was processed
+        ensure_name_free(owner, parent.clone(), &name)?;
 
-    let bucket_id = FILE_TO_BUCKET.with(|ftb| {
-        ftb.borrow()
-            .get(&file_id)
-            .map(|b| b.0)
-            .ok_or(DirectoryError::InvalidRequest(
-                "No bucket assigned for this file".to_string(),
-            ))
-    })?;
+        let copy_id = FileId {
+            owner,
+            id: new_id(),
+        };
+        // Pending until the chunks are copied
+        put_file(FileMeta {
+            status: FileStatus::Pending,
+            sha256: source.sha256.clone(),
+            attributes: source.attributes.clone(),
+            tags: source.tags.clone(),
+            merkle_root: source.merkle_root.clone(),
+            ..FileMeta::new_ready(
+                copy_id.clone(),
+                name,
+                source.mime.clone(),
+                parent,
+                source.size_bytes,
+                source.chunk_size,
+                source.chunk_count,
+            )
+        });
+        add_used_bytes(owner, source.size_bytes);
+        FILE_TO_BUCKET.with(|ftb| {
+            ftb.borrow_mut()
+                .insert(copy_id.clone(), StorablePrincipal(target_bucket))
+        });
 
-    let chunk_count = meta.chunk_count;
-    let chunk_size = meta.chunk_size;
-    let mut locations = Vec::with_capacity(chunk_count as usize);
+        // 4. Let the target bucket copy the chunks, fetching them from the source bucket if needed
+        let chunks = match copy_content(&source, source_bucket, &copy_id, target_bucket).await {
+            Ok(chunks) => chunks,
+            Err(e) => {
+                if let Some(meta) = remove_file(&copy_id) {
+                    release_used_bytes(owner, meta.size_bytes);
+                }
+                queue_content_delete(copy_id, 0);
+                process_pending_deletes().await;
+                return Err(e);
+            }
+        };
+        add_bucket_bytes(target_bucket, source.size_bytes);
+
+        // 5. The copy may have been deleted in the meantime
+        let mut meta = get_file(&copy_id).ok_or(DirectoryError::FileNotFound)?;
+        if meta.status == FileStatus::Pending {
+            let (sha256, merkle_root) = record_manifest(&copy_id, chunks);
+            meta.status = FileStatus::Ready;
+            meta.sha256 = Some(sha256);
+            meta.merkle_root = Some(merkle_root);
+            meta.updated_at_ns = time();
+            put_file(meta.clone());
+        }
 
-    for i in 0..chunk_count {
-        locations.push(shared::types::ChunkLocation {
-            chunk_index: i,
-            bucket: bucket_id,
-        });
+        Ok(meta)
     }
+    .await;
 
-    let expires_at = ic_cdk::api::time() + HOUR_NS;
-    let mut auth = Vec::with_capacity(1);
+    result.into()
+}
 
-    let mut token = DownloadToken {
-        file_id: file_id.clone(),
-        bucket_id,
-        directory_id: ic_cdk::id(),
+/// Asks `target_bucket` to copy the current content of `source` into `copy_id`, a batch of chunks
+/// per call, then seals the copy. Returns the manifest of the copied chunks.
+async fn copy_content(
+    source: &FileMeta,
+    source_bucket: Principal,
+    copy_id: &FileId,
+    target_bucket: Principal,
+) -> Result<Vec<ChunkInfo>, DirectoryError> {
+    if source.chunk_count == 0 {
+        return Ok(vec![]);
+    }
+
+    let secret = read_config(|c| c.shared_secret.clone().unwrap_or_default());
+    let expires_at = time() + HOUR_NS;
+
+    let mut download = DownloadToken {
+        file_id: source.content_id(),
+        bucket_id: source_bucket,
+        directory_id: id(),
         expires_at,
         sig: vec![],
     };
-    let secret = read_config(|c| c.shared_secret.clone().unwrap_or_default());
-    sign_download_token(&mut token, &secret);
-    auth.push(BucketAuth { bucket_id, token });
+    sign_download_token(&mut download, &secret);
+
+    let indexes: Vec<u32> = (0..source.chunk_count).collect();
+    for batch in indexes.chunks(MAX_COPY_BATCH_CHUNKS as usize) {
+        let mut upload = UploadToken {
+            upload_id: copy_id.id.clone(),
+            file_id: copy_id.clone(),
+            bucket_id: target_bucket,
+            directory_id: id(),
+            expires_at,
+            allowed_chunks: batch.to_vec(),
+            sig: vec![],
+        };
+        sign_token(&mut upload, &secret);
 
-    Ok(DownloadPlan {
-        chunk_count,
-        chunk_size,
-        locations,
-        auth,
-    })
+        let res: Result<(BucketChunksResult,), _> =
+            ic_cdk::call(target_bucket, "copy_chunks", (download.clone(), upload)).await;
+        match res {
+            Ok((BucketChunksResult::Ok(_),)) => {}
+            Ok((BucketChunksResult::Err(_),)) => {
+                return Err(DirectoryError::BucketCallFailed(
+                    "Bucket refused to copy the chunks".to_string(),
+                ))
+            }
+            Err((code, msg)) => {
+                return Err(DirectoryError::BucketCallFailed(format!(
+                    "Call error: {:?} {}",
+                    code, msg
+                )))
+            }
+        }
+    }
+
+    // Seal the copy like a committed upload, and check it against the source's manifest
+    let chunks = fetch_manifest(copy_id, target_bucket, source.chunk_count, true).await?;
+    let expected = CONTENT_MANIFESTS.with(|m| m.borrow().get(&source.content_id()));
+    if chunks.len() != source.chunk_count as usize
+        || expected.is_some_and(|manifest| manifest.chunks != chunks)
+    {
+        return Err(DirectoryError::IntegrityCheckFailed(
+            "Copied chunks do not match the source".to_string(),
+        ));
+    }
+    Ok(chunks)
 }
 
+/// Moves a file under `parent` (`None` is the root folder). Only the owner can move files.
 #[update]
-pub async fn create_share_link(file_id: FileId, ttl_ns: u64) -> CreateShareLinkResult {
-    let caller = ic_cdk::caller();
-    let res: Result<Vec<u8>, DirectoryError> = async {
-        let meta = FILES
-            .with(|f| f.borrow().get(&file_id))
-            .ok_or(DirectoryError::FileNotFound)?;
-        if meta.file_id.owner != caller && !meta.writers.contains(&caller) {
+pub fn move_file(file_id: FileId, parent: Option<FolderId>) -> UpdateFileResult {
+    let result: Result<FileMeta, DirectoryError> = (|| {
+        let caller = ic_cdk::caller();
+        let mut meta = live_file(&file_id)?;
+        if !is_owner(meta.file_id.owner, caller) {
             return Err(DirectoryError::Unauthorized);
         }
+        if meta.parent == parent {
+            return Ok(meta);
+        }
+        if let Some(folder_id) = &parent {
+            owned_folder(folder_id, meta.file_id.owner)?;
+        }
+        ensure_name_free(meta.file_id.owner, parent.clone(), &meta.name)?;
 
-        let (token,): (Vec<u8>,) = ic_cdk::api::management_canister::main::raw_rand()
-            .await
-            .map_err(|(_, msg)| DirectoryError::InvalidRequest(msg))?;
+        meta.parent = parent;
+        meta.updated_at_ns = time();
+        put_file(meta.clone());
 
-        let expires_at = ic_cdk::api::time() + ttl_ns;
-        LINKS.with(|l| {
-            l.borrow_mut().insert(
-                token.clone(),
-                LinkInfo {
-                    file_id,
-                    expires_at,
-                },
-            )
-        });
-        Ok(token)
-    }
-    .await;
-    res.into()
-}
+        Ok(meta)
+    })();
 
-#[update]
-pub fn revoke_share_link(token: Vec<u8>) -> Result<(), DirectoryError> {
-    let caller = ic_cdk::caller();
-    LINKS.with(|l| {
-        let mut map = l.borrow_mut();
-        if let Some(info) = map.get(&token) {
-            let meta = FILES
-                .with(|f| f.borrow().get(&info.file_id))
-                .ok_or(DirectoryError::FileNotFound)?;
-            if meta.file_id.owner != caller && !meta.writers.contains(&caller) {
-                return Err(DirectoryError::Unauthorized);
-            }
-            map.remove(&token);
-            Ok(())
-        } else {
-            Err(DirectoryError::LinkNotFound)
-        }
-    })
+    result.into()
 }
 
+/// Resolves a `/`-separated path in the caller's folder tree, e.g. `/reports/2024/q1.pdf`.
 #[query]
-pub fn resolve_share_link(token: Vec<u8>) -> ResolveShareLinkResult {
-    let res: Result<DownloadPlan, DirectoryError> = (|| {
-        let info = LINKS
-            .with(|l| l.borrow().get(&token))
-            .ok_or(DirectoryError::LinkNotFound)?;
-        if info.expires_at < ic_cdk::api::time() {
-            return Err(DirectoryError::LinkExpired);
+pub fn resolve_path(path: String) -> ResolvePathResult {
+    let result: Result<Entry, DirectoryError> = (|| {
+        let caller = ic_cdk::caller();
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let (last, folders) = segments
+            .split_last()
+            .ok_or_else(|| DirectoryError::InvalidRequest("Path is empty".to_string()))?;
+
+        let mut parent = None;
+        for segment in folders {
+            match lookup_entry(caller, parent, segment) {
+                Some(Entry::Folder(folder_id)) => parent = Some(folder_id),
+                _ => return Err(DirectoryError::FolderNotFound),
+            }
         }
-        generate_download_plan(info.file_id)
+
+        lookup_entry(caller, parent, last).ok_or(DirectoryError::FileNotFound)
     })();
-    res.into()
+
+    result.into()
 }
 
-#[update]
-pub fn delete_file(file_id: FileId) -> DeleteFileResult {
-    let result: Result<(), DirectoryError> = (|| {
-        let meta = FILES
-            .with(|f| f.borrow().get(&file_id))
-            .ok_or(DirectoryError::FileNotFound)?;
+/// Looks up a folder the caller owns or was granted access to, directly or through a parent folder.
+fn readable_folder(folder_id: &FolderId, caller: Principal) -> Result<FolderMeta, DirectoryError> {
+    let meta = get_folder(folder_id).ok_or(DirectoryError::FolderNotFound)?;
+    if meta.folder_id.owner != caller && folder_role(Some(folder_id.clone()), caller).is_none() {
+        return Err(DirectoryError::Unauthorized);
+    }
+    Ok(meta)
+}
 
-        if meta.file_id.owner != ic_cdk::caller() && !meta.writers.contains(&ic_cdk::caller()) {
-            return Err(DirectoryError::Unauthorized);
-        }
+/// Whether `caller` owns the folder or was granted write access to it, directly or through a
+/// parent folder.
+fn can_write_folder(meta: &FolderMeta, caller: Principal) -> bool {
+    can_upload_for(meta.folder_id.owner, caller)
+        || folder_role(Some(meta.folder_id.clone()), caller) == Some(FileRole::Writer)
+}
 
-        FILES.with(|f| f.borrow_mut().remove(&file_id));
+/// Whether `folder` is `ancestor` or lies under it.
+fn folder_within(mut folder: Option<FolderId>, ancestor: &FolderId) -> bool {
+    while let Some(folder_id) = folder {
+        if &folder_id == ancestor {
+            return true;
+        }
+        folder = get_folder(&folder_id).and_then(|meta| meta.parent);
+    }
+    false
+}
 
-        // Refund/Update usage
-        let key = StorablePrincipal(file_id.owner);
-        USERS.with(|u| {
-            let mut map = u.borrow_mut();
-            if let Some(mut state) = map.get(&key) {
-                state.used_bytes = state.used_bytes.saturating_sub(meta.size_bytes);
-                map.insert(key, state);
-            }
-        });
+fn owned_folder(folder_id: &FolderId, caller: Principal) -> Result<FolderMeta, DirectoryError> {
+    let meta = get_folder(folder_id).ok_or(DirectoryError::FolderNotFound)?;
+    if meta.folder_id.owner != caller {
+        return Err(DirectoryError::Unauthorized);
+    }
+    Ok(meta)
+}
 
-        Ok(())
-    })();
+fn validate_name(name: &str) -> Result<(), DirectoryError> {
+    if name.is_empty()
+        || name.len() > MAX_NAME_BYTES
+        || name == "."
+        || name == ".."
+        || name.contains('/')
+    {
+        return Err(DirectoryError::InvalidRequest(format!(
+            "Invalid name: {:?}",
+            name
+        )));
+    }
+    Ok(())
+}
 
-    result.into()
+fn validate_tag(tag: &str) -> Result<(), DirectoryError> {
+    if tag.is_empty() || tag.len() > MAX_TAG_BYTES {
+        return Err(DirectoryError::InvalidRequest(format!(
+            "Invalid tag: {:?}",
+            tag
+        )));
+    }
+    Ok(())
 }
 
-#[update]
-pub fn add_file_access(
-    file_id: FileId,
-    principal: UserId,
-    role: FileRole,
+fn ensure_name_free(
+    owner: UserId,
+    parent: Option<FolderId>,
+    name: &str,
 ) -> Result<(), DirectoryError> {
-    FILES.with(|f| {
-        let mut map = f.borrow_mut();
-        if let Some(mut meta) = map.get(&file_id) {
-            if meta.file_id.owner != ic_cdk::caller() {
-                return Err(DirectoryError::Unauthorized);
-            }
-            match role {
-                FileRole::Reader => {
-                    if !meta.readers.contains(&principal) {
-                        meta.readers.push(principal);
-                    }
-                }
-                FileRole::Writer => {
-                    if !meta.writers.contains(&principal) {
-                        meta.writers.push(principal);
-                    }
-                }
-            }
-            map.insert(file_id, meta);
-            Ok(())
-        } else {
-            Err(DirectoryError::FileNotFound)
-        }
-    })
+    if lookup_entry(owner, parent, name).is_some() {
+        return Err(DirectoryError::NameAlreadyExists(name.to_string()));
+    }
+    Ok(())
 }
 
-#[update]
-pub fn remove_file_access(file_id: FileId, principal: UserId) -> Result<(), DirectoryError> {
-    FILES.with(|f| {
-        let mut map = f.borrow_mut();
-        if let Some(mut meta) = map.get(&file_id) {
-            if meta.file_id.owner != ic_cdk::caller() {
-                return Err(DirectoryError::Unauthorized);
-            }
-            meta.readers.retain(|r| r != &principal);
-            meta.writers.retain(|w| w != &principal);
-            map.insert(file_id, meta);
-            Ok(())
-        } else {
-            Err(DirectoryError::FileNotFound)
-        }
-    })
+/// Generates a 16-byte identifier: the current time followed by a per-canister counter, so that
+/// identifiers issued within the same round stay distinct.
+fn new_id() -> Vec<u8> {
+    thread_local! {
+        static COUNTER: Cell<u64> = const { Cell::new(0) };
+    }
+
+    let counter = COUNTER.with(|c| {
+        let next = c.get().wrapping_add(1);
+        c.set(next);
+        next
+    });
+    let mut id = vec![0u8; 16];
+    id[..8].copy_from_slice(&time().to_be_bytes());
+    id[8..].copy_from_slice(&counter.to_be_bytes());
+    id
 }
 
 fn is_admin(caller: Principal) -> bool {
@@ -486,6 +3531,17 @@ fn is_admin(caller: Principal) -> bool {
     })
 }
 
+#[update]
+pub fn admin_set_trash_retention(retention_ns: u64) -> Result<(), DirectoryError> {
+    if !is_admin(ic_cdk::caller()) {
+        return Err(DirectoryError::AdminOnly);
+    }
+    crate::memory::mutate_config(|c| {
+        c.trash_retention_ns = Some(retention_ns);
+    });
+    Ok(())
+}
+
 #[update]
 pub fn admin_set_pricing(rate: u64) -> Result<(), DirectoryError> {
     if !is_admin(ic_cdk::caller()) {
@@ -565,6 +3621,27 @@ pub fn provision_bucket(bucket_id: Principal) -> ProvisionBucketResult {
     result.into()
 }
 
+/// Stops or resumes placing new content on a bucket. Content already stored there stays readable.
+#[update]
+pub fn admin_set_bucket_writable(
+    bucket_id: Principal,
+    writable: bool,
+) -> Result<(), DirectoryError> {
+    if !is_admin(ic_cdk::caller()) {
+        return Err(DirectoryError::AdminOnly);
+    }
+    BUCKETS.with(|b| {
+        let mut map = b.borrow_mut();
+        let key = StorablePrincipal(bucket_id);
+        let mut info = map
+            .get(&key)
+            .ok_or(DirectoryError::InvalidRequest("Unknown bucket".to_string()))?;
+        info.writable = writable;
+        map.insert(key, info);
+        Ok(())
+    })
+}
+
 #[query]
 pub fn list_buckets() -> ListBucketResult {
     let result: Result<Vec<Principal>, DirectoryError> = (|| {
@@ -588,58 +3665,187 @@ pub fn list_buckets() -> ListBucketResult {
 #[update]
 pub fn get_upload_tokens(upload_id: Vec<u8>, chunks: Vec<u32>) -> GetUploadTokensResult {
     let result: Result<Vec<UploadToken>, DirectoryError> = (|| {
-        let session = UPLOADS
-            .with(|u| u.borrow().get(&upload_id))
-            .ok_or(DirectoryError::UploadSessionNotFound)?;
+        let session = uploader_session(&upload_id)?;
+        issue_upload_tokens(&session, chunks)
+    })();
 
-        // Auth check: caller must be owner
-        if session.file_id.owner != ic_cdk::caller() {
-            return Err(DirectoryError::Unauthorized);
-        }
+    result.into()
+}
 
-        // Pick a bucket (strategy: use first writable bucket for v1)
-        let bucket_id = BUCKETS
-            .with(|b| {
-                b.borrow()
-                    .iter()
-                    .find(|(_, info)| info.writable)
-                    .map(|(_, info)| info.id)
-            })
-            .ok_or(DirectoryError::NoWritableBuckets)?;
+/// Indexes of the chunks the directory has not yet seen reported for the session, in order.
+#[query]
+pub fn get_missing_chunks(upload_id: Vec<u8>) -> GetMissingChunksResult {
+    let result: Result<Vec<u32>, DirectoryError> = (|| {
+        let session = uploader_session(&upload_id)?;
+        missing_chunks(&session)
+    })();
 
-        // Record the assignment
-        FILE_TO_BUCKET.with(|ftb| {
-            ftb.borrow_mut()
-                .insert(session.file_id.clone(), StorablePrincipal(bucket_id))
-        });
+    result.into()
+}
 
-        // Issue tokens. For v1 we can batch all chunks into one token or one per chunk.
-        // Let's do batch for efficiency if chunks are provided.
-        let mut token = UploadToken {
-            upload_id: session.upload_id.clone(),
-            file_id: session.file_id.clone(),
-            bucket_id,
-            directory_id: id(),
-            expires_at: session.expires_at_ns,
-            allowed_chunks: chunks,
-            sig: vec![],
-        };
+/// Moves the session's expiry to `ttl_ns` from now. Only the owner of the file can extend, and a
+/// session cannot stay open for more than `MAX_UPLOAD_TTL_NS` after it started. Tokens issued
+/// before keep their expiry; `resume_upload` issues fresh ones.
+#[update]
+pub fn extend_upload(upload_id: Vec<u8>, ttl_ns: u64) -> StartUploadResult {
+    let result: Result<UploadSession, DirectoryError> = (|| {
+        let mut session = live_session(&upload_id)?;
+        if !is_owner(session.file_id.owner, ic_cdk::caller()) {
+            return Err(DirectoryError::Unauthorized);
+        }
 
-        let secret = read_config(|c| c.shared_secret.clone().unwrap_or_default());
-        sign_token(&mut token, &secret);
+        // Sessions started before start times were recorded were opened for an hour
+        let started_at_ns = session
+            .started_at_ns
+            .unwrap_or(session.expires_at_ns.saturating_sub(HOUR_NS));
+        let expires_at_ns = time().saturating_add(ttl_ns);
+        if ttl_ns == 0 || expires_at_ns > started_at_ns.saturating_add(MAX_UPLOAD_TTL_NS) {
+            return Err(DirectoryError::InvalidRequest(format!(
+                "Upload sessions can stay open for at most {} ns",
+                MAX_UPLOAD_TTL_NS
+            )));
+        }
+        session.expires_at_ns = expires_at_ns;
+        UPLOADS.with(|u| u.borrow_mut().insert(upload_id, session.clone()));
+        Ok(session)
+    })();
 
-        Ok(vec![token])
+    result.into()
+}
+
+/// Issues tokens for every chunk still missing from the session, so a client that lost its
+/// state can pick the upload up again.
+#[update]
+pub fn resume_upload(upload_id: Vec<u8>) -> GetUploadTokensResult {
+    let result: Result<Vec<UploadToken>, DirectoryError> = (|| {
+        let session = uploader_session(&upload_id)?;
+        let missing = missing_chunks(&session)?;
+        if missing.is_empty() {
+            return Ok(vec![]);
+        }
+        issue_upload_tokens(&session, missing)
     })();
 
     result.into()
 }
 
+/// Loads an upload session that has not expired.
+fn live_session(upload_id: &[u8]) -> Result<UploadSession, DirectoryError> {
+    UPLOADS
+        .with(|u| u.borrow().get(&upload_id.to_vec()))
+        .filter(|session| session.expires_at_ns >= time())
+        .ok_or(DirectoryError::UploadSessionNotFound)
+}
+
+/// Loads a live upload session the caller is the uploader of.
+fn uploader_session(upload_id: &[u8]) -> Result<UploadSession, DirectoryError> {
+    let session = live_session(upload_id)?;
+
+    // Auth check: caller must be the uploader
+    if session.uploader() != ic_cdk::caller() {
+        return Err(DirectoryError::Unauthorized);
+    }
+    Ok(session)
+}
+
+/// Chunks the session still needs before it can be committed.
+fn missing_chunks(session: &UploadSession) -> Result<Vec<u32>, DirectoryError> {
+    let required = match &session.edit_of {
+        Some(file_id) => required_edit_chunks(
+            live_file(file_id)?.size_bytes,
+            session.expected_size_bytes,
+            session.chunk_size,
+        ),
+        None => (0..session.expected_chunk_count).collect(),
+    };
+    Ok(required
+        .into_iter()
+        .filter(|i| !session.uploaded_chunks.contains(i))
+        .collect())
+}
+
+/// Assigns the session's bucket and signs a token for `chunks`, valid until the session expires.
+fn issue_upload_tokens(
+    session: &UploadSession,
+    chunks: Vec<u32>,
+) -> Result<Vec<UploadToken>, DirectoryError> {
+    let bucket_id = match &session.edit_of {
+        // Edited chunks replace the file's chunks in place, so they are staged next to them
+        Some(file_id) => content_bucket(&live_file(file_id)?.content_id())?,
+        // A resumed upload stays on the bucket that holds its earlier chunks
+        None => match FILE_TO_BUCKET.with(|ftb| ftb.borrow().get(&session.file_id)) {
+            Some(bucket) => bucket.0,
+            None => writable_bucket()?,
+        },
+    };
+
+    // Record the assignment
+    FILE_TO_BUCKET.with(|ftb| {
+        ftb.borrow_mut()
+            .insert(session.file_id.clone(), StorablePrincipal(bucket_id))
+    });
+
+    // Issue tokens. For v1 we can batch all chunks into one token or one per chunk.
+    // Let's do batch for efficiency if chunks are provided.
+    let mut token = UploadToken {
+        upload_id: session.upload_id.clone(),
+        file_id: session.file_id.clone(),
+        bucket_id,
+        directory_id: id(),
+        expires_at: session.expires_at_ns,
+        allowed_chunks: chunks,
+        sig: vec![],
+    };
+
+    let secret = read_config(|c| c.shared_secret.clone().unwrap_or_default());
+    sign_token(&mut token, &secret);
+
+    Ok(vec![token])
+}
+
+/// Picks the bucket that receives new content (strategy: use first writable bucket for v1).
+fn writable_bucket() -> Result<Principal, DirectoryError> {
+    BUCKETS
+        .with(|b| {
+            b.borrow()
+                .iter()
+                .find(|(_, info)| info.writable)
+                .map(|(_, info)| info.id)
+        })
+        .ok_or(DirectoryError::NoWritableBuckets)
+}
+
+/// Looks up the bucket storing `content_id`.
+fn content_bucket(content_id: &FileId) -> Result<Principal, DirectoryError> {
+    FILE_TO_BUCKET.with(|ftb| {
+        ftb.borrow()
+            .get(content_id)
+            .map(|b| b.0)
+            .ok_or(DirectoryError::InvalidRequest(
+                "No bucket assigned for this file".to_string(),
+            ))
+    })
+}
+
 // MONTH_NS and GIB_BYTES moved to shared::constants
 
 #[update]
-pub async fn top_up_balance(amount: u64, payment: PaymentType) -> TopUpBalanceResult {
+pub async fn top_up_balance(
+    amount: u64,
+    payment: PaymentType,
+    account: Option<UserId>,
+) -> TopUpBalanceResult {
     let result: Result<u64, DirectoryError> = async {
         let caller = ic_cdk::caller();
+        let account = account.unwrap_or(caller);
+        if account != caller
+            && !matches!(
+                org_role(account, caller),
+                Some(OrgRole::Admin | OrgRole::Billing)
+            )
+        {
+            return Err(DirectoryError::Unauthorized);
+        }
 
         // 1. Deduct payment
         PAYMENT_GUARD
@@ -651,7 +3857,7 @@ pub async fn top_up_balance(amount: u64, payment: PaymentType) -> TopUpBalanceRe
             .map_err(|e| DirectoryError::PaymentFailed(format!("Payment failed: {:?}", e)))?;
 
         // 2. Update expiration
-        let key = StorablePrincipal(caller);
+        let key = StorablePrincipal(account);
         let config = read_config(|c| c.clone());
 
         let new_expiry = USERS.with(|u| {
@@ -741,17 +3947,25 @@ pub async fn garbage_collect() {
     });
 
     for user_id in expired_users {
-        let file_ids: Vec<FileId> = FILES.with(|f| {
+        for fid in owned_file_ids(user_id) {
+            purge_file(&fid);
+        }
+
+        let folder_ids: Vec<FolderId> = FOLDERS.with(|f| {
             f.borrow()
-                .iter()
-                .filter(|(fid, _)| fid.owner == user_id)
-                .map(|(fid, _)| fid.clone())
+                .range(
+                    FolderId {
+                        owner: user_id,
+                        id: vec![],
+                    }..,
+                )
+                .take_while(|(fid, _)| fid.owner == user_id)
+                .map(|(fid, _)| fid)
                 .collect()
         });
 
-        for fid in file_ids {
-            FILES.with(|f| f.borrow_mut().remove(&fid));
-            FILE_TO_BUCKET.with(|ftb| ftb.borrow_mut().remove(&fid));
+        for fid in folder_ids {
+            remove_folder(&fid);
         }
 
         USERS.with(|u| u.borrow_mut().remove(&StorablePrincipal(user_id)));
@@ -10,6 +10,8 @@ pub struct Config {
     pub rate_per_gb_per_month: Option<u64>,
     /// Secret used to sign tokens shared with bucket canisters.
     pub shared_secret: Option<Vec<u8>>,
+    /// How long trashed files are kept before being purged, in nanoseconds.
+    pub trash_retention_ns: Option<u64>,
 }
 
 /// Arguments for initializing the directory canister.
@@ -32,6 +34,8 @@ pub struct UpgradeArgs {
     pub rate_per_gb_per_month: Option<u64>,
     /// Optional update for the shared secret.
     pub shared_secret: Option<Vec<u8>>,
+    /// Optional update for the trash retention period.
+    pub trash_retention_ns: Option<u64>,
 }
 
 #[derive(CandidType, Deserialize)]
@@ -46,6 +50,7 @@ impl From<InitArgs> for Config {
             admins: Some(args.admins),
             rate_per_gb_per_month: Some(args.rate_per_gb_per_month),
             shared_secret: Some(args.shared_secret),
+            trash_retention_ns: None,
         }
     }
 }
@@ -24,4 +24,15 @@ pub enum DirectoryError {
     AccountExpired,
     AdminOnly,
     BucketAlreadyExists,
+    FolderNotFound,
+    FolderNotEmpty,
+    NameAlreadyExists(String),
+    VersionNotFound(u32),
+    BucketCallFailed(String),
+    TransferNotFound,
+    GroupNotFound,
+    OrganizationNotFound,
+    LinkExhausted,
+    InvalidLinkPassword,
+    IntegrityCheckFailed(String),
 }
@@ -7,32 +7,55 @@ pub mod results;
 pub mod types;
 
 pub use api::{
-    add_file_access, admin_set_pricing, admin_set_quota, admin_withdraw, commit_upload,
-    create_share_link, delete_file, estimate_upload_cost, garbage_collect, get_pricing, get_status,
-    get_upload_tokens, get_usage, list_files, provision_bucket, reap_expired_uploads,
-    remove_file_access, report_chunk_uploaded, resolve_share_link, revoke_share_link, start_upload,
-    top_up_balance,
+    accept_file_transfer, add_file_access, add_file_tags, add_folder_access, add_group_access,
+    admin_list_pending_deletes, admin_set_bucket_writable, admin_set_pricing, admin_set_quota,
+    admin_set_trash_retention, admin_withdraw, browse_share_link, cancel_file_transfer,
+    commit_edit, commit_upload, copy_file, create_folder, create_folder_share_link, create_group,
+    create_organization, create_share_link, create_upload_request, delete_file, delete_folder,
+    delete_group, empty_trash, estimate_upload_cost, extend_upload, garbage_collect,
+    get_folder_meta, get_group, get_missing_chunks, get_organization, get_pricing, get_status,
+    get_upload_tokens, get_usage, list_file_transfers, list_files, list_files_page, list_folder,
+    list_folder_share_links, list_groups, list_organizations, list_public_files, list_share_links,
+    list_trash, list_upload_requests, list_versions, move_file, move_folder, offer_file_transfer,
+    process_pending_deletes, provision_bucket, prune_versions, purge_trash, reap_expired_uploads,
+    remove_file_access, remove_file_tags, remove_folder_access, remove_group_access,
+    remove_org_member, rename_file, rename_folder, report_chunk_uploaded, resolve_path,
+    resolve_share_link, restore_file, restore_version, resume_upload, revoke_share_link,
+    revoke_upload_request, search_files, set_file_visibility, set_org_member, start_edit,
+    start_upload, start_version_upload, sweep_expired_grants, top_up_balance, update_file_meta,
+    update_group,
 };
 use candid::Principal;
 use ic_cdk::{export_candid, spawn};
 use ic_cdk_macros::{heartbeat, init, post_upgrade};
 pub use ic_papi_api::PaymentType;
 use shared::{
-    types::{FileId, FileMeta, FileRole, PricingConfig, UserId},
+    types::{FileId, FileMeta, FileRole, FolderId, GroupId, PricingConfig, UserId, Visibility},
     CanisterStatus,
 };
 
 use crate::{
     config::Args,
     errors::DirectoryError,
-    memory::{mutate_config, set_config},
+    memory::{
+        backfill_entries, backfill_file_indexes, backfill_file_links, mutate_config, set_config,
+    },
     results::{
-        AbortUploadResult, AdminWithdrawResult, CommitUploadResult, CreateShareLinkResult,
-        DeleteFileResult, GetDownloadPlanResult, GetFileMetaResult, GetUploadTokensResult,
-        ListBucketResult, ProvisionBucketResult, ReportChunkUploadedResult, ResolveShareLinkResult,
-        StartUploadResult, TopUpBalanceResult,
+        AbortUploadResult, AdminWithdrawResult, BrowseShareLinkResult, CommitUploadResult,
+        CreateFolderResult, CreateGroupResult, CreateOrganizationResult, CreateShareLinkResult,
+        DeleteFileResult, DeleteFolderResult, GetDownloadPlanResult, GetFileMetaResult,
+        GetFolderResult, GetGroupResult, GetMissingChunksResult, GetOrganizationResult,
+        GetUploadTokensResult, ListBucketResult, ListFilesPageResult, ListFolderResult,
+        ListPendingDeletesResult, ListShareLinksResult, ListVersionsResult, ProvisionBucketResult,
+        PruneVersionsResult, ReportChunkUploadedResult, ResolvePathResult, ResolveShareLinkResult,
+        StartUploadResult, TopUpBalanceResult, UpdateFileResult, UpdateFolderResult,
+        UpdateGroupResult,
+    },
+    types::{
+        BrowseShareLinkRequest, Group, ListFilesRequest, OrgRole, Organization, SearchFilesRequest,
+        ShareLinkOptions, TransferOffer, UpdateFileMetaRequest, UpdateGroupRequest, UploadRequest,
+        UploadRequestOptions, UserState,
     },
-    types::UserState,
 };
 
 #[init]
@@ -58,12 +81,19 @@ fn post_upgrade(args: Option<Args>) {
                     if let Some(secret) = upgrade_args.shared_secret {
                         config.shared_secret = Some(secret);
                     }
+                    if let Some(retention) = upgrade_args.trash_retention_ns {
+                        config.trash_retention_ns = Some(retention);
+                    }
                 });
             }
             Args::Upgrade(None) => {}
             Args::Init(_) => ic_cdk::trap("Cannot use init variant in post_upgrade"),
         }
     }
+
+    backfill_entries();
+    backfill_file_indexes();
+    backfill_file_links();
 }
 
 #[heartbeat]
@@ -77,6 +107,9 @@ fn heartbeat() {
         let current = t.get();
         if current % 1000 == 0 {
             spawn(garbage_collect());
+            spawn(purge_trash());
+            spawn(process_pending_deletes());
+            sweep_expired_grants();
         }
         t.set(current + 1);
     });
@@ -1,4 +1,4 @@
-use std::{borrow::Cow, cell::RefCell};
+use std::{borrow::Cow, cell::RefCell, thread::LocalKey};
 
 use candid::{decode_one, encode_one, Principal};
 use ic_stable_structures::{
@@ -6,15 +6,24 @@ use ic_stable_structures::{
     storable::Bound,
     Cell as StableCell, DefaultMemoryImpl, StableBTreeMap, Storable,
 };
-use shared::types::{FileId, FileMeta, LinkInfo, UploadSession};
+use shared::types::{
+    FileId, FileMeta, FileStatus, FolderId, FolderMeta, GroupId, LinkInfo, UploadSession, UserId,
+    Visibility,
+};
 
 use crate::{
     config::Config,
-    types::{BucketInfo, UserState},
+    types::{
+        BucketInfo, ContentManifest, Entry, EntryKey, FileLinkKey, FileVersion, GrantExpiryKey,
+        Group, GroupFileKey, MemberKey, OrgMemberKey, Organization, PendingDelete,
+        PrincipalFileKey, PrincipalFolderKey, TagKey, TransferOffer, TrashKey, UploadRequest,
+        UserState, VersionKey,
+    },
 };
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 pub type ConfigCell = StableCell<Option<Config>, Memory>;
+pub type FileIndex = StableBTreeMap<PrincipalFileKey, (), Memory>;
 
 impl Storable for UserState {
     const BOUND: Bound = Bound::Unbounded;
@@ -52,6 +61,234 @@ impl Storable for Config {
     }
 }
 
+impl Storable for EntryKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode EntryKey"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode EntryKey")
+    }
+}
+
+impl Storable for PrincipalFileKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode PrincipalFileKey"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode PrincipalFileKey")
+    }
+}
+
+impl Storable for TagKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode TagKey"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode TagKey")
+    }
+}
+
+impl Storable for PrincipalFolderKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode PrincipalFolderKey"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode PrincipalFolderKey")
+    }
+}
+
+impl Storable for GrantExpiryKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode GrantExpiryKey"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode GrantExpiryKey")
+    }
+}
+
+impl Storable for TrashKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode TrashKey"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode TrashKey")
+    }
+}
+
+impl Storable for VersionKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode VersionKey"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode VersionKey")
+    }
+}
+
+impl Storable for FileVersion {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode FileVersion"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode FileVersion")
+    }
+}
+
+impl Storable for Group {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode Group"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode Group")
+    }
+}
+
+impl Storable for Organization {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode Organization"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode Organization")
+    }
+}
+
+impl Storable for OrgMemberKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode OrgMemberKey"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode OrgMemberKey")
+    }
+}
+
+impl Storable for MemberKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode MemberKey"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode MemberKey")
+    }
+}
+
+impl Storable for GroupFileKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode GroupFileKey"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode GroupFileKey")
+    }
+}
+
+impl Storable for FileLinkKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode FileLinkKey"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode FileLinkKey")
+    }
+}
+
+impl Storable for TransferOffer {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode TransferOffer"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode TransferOffer")
+    }
+}
+
+impl Storable for PendingDelete {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode PendingDelete"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode PendingDelete")
+    }
+}
+
+impl Storable for ContentManifest {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode ContentManifest"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode ContentManifest")
+    }
+}
+
+impl Storable for UploadRequest {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode UploadRequest"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode UploadRequest")
+    }
+}
+
+impl Storable for Entry {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode Entry"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode Entry")
+    }
+}
+
 // Wrapper for Principal to make it Storable
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct StorablePrincipal(pub Principal);
@@ -102,6 +339,103 @@ thread_local! {
     pub static LINKS: RefCell<StableBTreeMap<Vec<u8>, LinkInfo, Memory>> = RefCell::new(
         StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6))))
     );
+
+    pub static FOLDERS: RefCell<StableBTreeMap<FolderId, FolderMeta, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7))))
+    );
+
+    /// Name index of every folder: `(owner, parent, name)` -> file or subfolder.
+    pub static ENTRIES: RefCell<StableBTreeMap<EntryKey, Entry, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8))))
+    );
+
+    /// Owner -> files they own.
+    pub static OWNER_FILES: RefCell<FileIndex> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9))))
+    );
+
+    /// Grantee -> files shared with them as reader or writer.
+    pub static SHARED_FILES: RefCell<FileIndex> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10))))
+    );
+
+    /// Past versions of each file; the current version lives in the file's `FileMeta`.
+    pub static VERSIONS: RefCell<StableBTreeMap<VersionKey, FileVersion, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11))))
+    );
+
+    /// Trashed files by deletion time.
+    pub static TRASH: RefCell<StableBTreeMap<TrashKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12))))
+    );
+
+    /// (owner, tag) -> files carrying the tag.
+    pub static TAGS: RefCell<StableBTreeMap<TagKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13))))
+    );
+
+    /// Pending ownership transfers, keyed by the offered file.
+    pub static TRANSFERS: RefCell<StableBTreeMap<FileId, TransferOffer, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14))))
+    );
+
+    pub static GROUPS: RefCell<StableBTreeMap<GroupId, Group, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15))))
+    );
+
+    /// Index of group membership by member, maintained by `put_group` and `remove_group`.
+    pub static GROUP_MEMBERS: RefCell<StableBTreeMap<MemberKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16))))
+    );
+
+    /// Index of files shared with each group, maintained by `put_file` and `remove_file`.
+    pub static GROUP_FILES: RefCell<StableBTreeMap<GroupFileKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17))))
+    );
+
+    pub static ORGS: RefCell<StableBTreeMap<StorablePrincipal, Organization, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18))))
+    );
+
+    /// Index of organization membership by member, maintained by `put_org`.
+    pub static ORG_MEMBERS: RefCell<StableBTreeMap<OrgMemberKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19))))
+    );
+
+    /// Expiring reader and writer grants by expiry time, maintained by `put_file` and `remove_file`.
+    pub static GRANT_EXPIRY: RefCell<StableBTreeMap<GrantExpiryKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(20))))
+    );
+
+    /// Grantee -> folders shared with them, maintained by `put_folder` and `remove_folder`.
+    pub static SHARED_FOLDERS: RefCell<StableBTreeMap<PrincipalFolderKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(21))))
+    );
+
+    /// Owner -> their public files outside the trash, maintained by `put_file` and `remove_file`.
+    pub static PUBLIC_FILES: RefCell<FileIndex> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(22))))
+    );
+
+    /// Upload request links, keyed by token.
+    pub static UPLOAD_REQUESTS: RefCell<StableBTreeMap<Vec<u8>, UploadRequest, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23))))
+    );
+
+    /// Content queued for deletion from the buckets, keyed by storage id.
+    pub static PENDING_DELETES: RefCell<StableBTreeMap<FileId, PendingDelete, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(24))))
+    );
+
+    /// Chunk lengths and digests of committed content, keyed by storage id.
+    pub static CONTENT_MANIFESTS: RefCell<StableBTreeMap<FileId, ContentManifest, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(25))))
+    );
+
+    /// File -> its share links, maintained by `put_link` and `remove_link`.
+    pub static FILE_LINKS: RefCell<StableBTreeMap<FileLinkKey, (), Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(26))))
+    );
 }
 
 pub fn read_config<R>(f: impl FnOnce(&Config) -> R) -> R {
@@ -136,6 +470,706 @@ pub fn mutate_config(f: impl FnOnce(&mut Config)) {
     });
 }
 
+pub fn get_file(file_id: &FileId) -> Option<FileMeta> {
+    FILES.with(|f| f.borrow().get(file_id))
+}
+
+/// Inserts or updates a file, keeping the folder name index, the per-principal indexes, the trash
+/// index and the tag index in sync. Trashed files keep no name in their folder.
+///
+/// Callers are responsible for checking that the file's name is free in its folder.
+pub fn put_file(meta: FileMeta) {
+    let previous = FILES.with(|f| f.borrow_mut().insert(meta.file_id.clone(), meta.clone()));
+    if let Some(previous) = previous {
+        unindex_file(&previous);
+    }
+    index_file(&meta);
+}
+
+pub fn remove_file(file_id: &FileId) -> Option<FileMeta> {
+    let meta = FILES.with(|f| f.borrow_mut().remove(file_id))?;
+    unindex_file(&meta);
+    Some(meta)
+}
+
+/// Files owned by `owner`, without scanning `FILES`.
+pub fn owned_file_ids(owner: UserId) -> Vec<FileId> {
+    indexed_file_ids(&OWNER_FILES, owner, None, usize::MAX)
+}
+
+/// Up to `limit` files of `owner` following `after`, in file id order.
+pub fn owned_file_ids_after(owner: UserId, after: Option<&FileId>, limit: usize) -> Vec<FileId> {
+    indexed_file_ids(&OWNER_FILES, owner, after, limit)
+}
+
+/// Files shared with `grantee` as reader or writer, without scanning `FILES`.
+pub fn shared_file_ids(grantee: UserId) -> Vec<FileId> {
+    indexed_file_ids(&SHARED_FILES, grantee, None, usize::MAX)
+}
+
+/// Up to `limit` files shared with `grantee` following `after`, in file id order.
+pub fn shared_file_ids_after(grantee: UserId, after: Option<&FileId>, limit: usize) -> Vec<FileId> {
+    indexed_file_ids(&SHARED_FILES, grantee, after, limit)
+}
+
+pub fn public_file_ids(owner: UserId) -> Vec<FileId> {
+    indexed_file_ids(&PUBLIC_FILES, owner, None, usize::MAX)
+}
+
+/// Files of `owner` carrying `tag`.
+pub fn tagged_file_ids(owner: UserId, tag: &str) -> Vec<FileId> {
+    let start = TagKey {
+        owner,
+        tag: tag.to_string(),
+        file_id: FileId {
+            owner: Principal::management_canister(),
+            id: vec![],
+        },
+    };
+    TAGS.with(|t| {
+        t.borrow()
+            .range(start..)
+            .take_while(|(k, _)| k.owner == owner && k.tag == tag)
+            .map(|(k, _)| k.file_id)
+            .collect()
+    })
+}
+
+/// Lists files trashed before `cutoff_ns`, oldest first.
+pub fn trashed_before(cutoff_ns: u64, limit: usize) -> Vec<FileId> {
+    TRASH.with(|t| {
+        t.borrow()
+            .iter()
+            .take_while(|(k, _)| k.deleted_at_ns < cutoff_ns)
+            .take(limit)
+            .map(|(k, _)| k.file_id)
+            .collect()
+    })
+}
+
+/// Grants that expired at or before `now_ns`, soonest first, as `(file, grantee)` pairs.
+pub fn expired_grants(now_ns: u64, limit: usize) -> Vec<(FileId, UserId)> {
+    GRANT_EXPIRY.with(|g| {
+        g.borrow()
+            .iter()
+            .take_while(|(k, _)| k.expires_at_ns <= now_ns)
+            .take(limit)
+            .map(|(k, _)| (k.file_id, k.principal))
+            .collect()
+    })
+}
+
+pub fn get_version(file_id: &FileId, version: u32) -> Option<FileVersion> {
+    let key = VersionKey {
+        file_id: file_id.clone(),
+        version,
+    };
+    VERSIONS.with(|v| v.borrow().get(&key))
+}
+
+pub fn put_version(file_id: &FileId, version: FileVersion) {
+    let key = VersionKey {
+        file_id: file_id.clone(),
+        version: version.version,
+    };
+    VERSIONS.with(|v| v.borrow_mut().insert(key, version));
+}
+
+pub fn remove_version(file_id: &FileId, version: u32) -> Option<FileVersion> {
+    let key = VersionKey {
+        file_id: file_id.clone(),
+        version,
+    };
+    VERSIONS.with(|v| v.borrow_mut().remove(&key))
+}
+
+/// Lists the past versions of a file, oldest first.
+pub fn file_versions(file_id: &FileId) -> Vec<FileVersion> {
+    let start = VersionKey {
+        file_id: file_id.clone(),
+        version: 0,
+    };
+    VERSIONS.with(|v| {
+        v.borrow()
+            .range(start..)
+            .take_while(|(k, _)| &k.file_id == file_id)
+            .map(|(_, version)| version)
+            .collect()
+    })
+}
+
+pub fn get_group(group_id: &GroupId) -> Option<Group> {
+    GROUPS.with(|g| g.borrow().get(group_id))
+}
+
+/// Inserts or updates a group, keeping the membership index in sync.
+pub fn put_group(group: Group) {
+    let previous = GROUPS.with(|g| g.borrow_mut().insert(group.group_id.clone(), group.clone()));
+    GROUP_MEMBERS.with(|m| {
+        let mut map = m.borrow_mut();
+        for key in previous.iter().flat_map(member_keys) {
+            map.remove(&key);
+        }
+        for key in member_keys(&group) {
+            map.insert(key, ());
+        }
+    });
+}
+
+pub fn remove_group(group_id: &GroupId) -> Option<Group> {
+    let group = GROUPS.with(|g| g.borrow_mut().remove(group_id))?;
+    GROUP_MEMBERS.with(|m| {
+        let mut map = m.borrow_mut();
+        for key in member_keys(&group) {
+            map.remove(&key);
+        }
+    });
+    Some(group)
+}
+
+pub fn is_group_member(group_id: &GroupId, member: UserId) -> bool {
+    let key = MemberKey {
+        member,
+        group_id: group_id.clone(),
+    };
+    GROUP_MEMBERS.with(|m| m.borrow().contains_key(&key))
+}
+
+/// Groups owned by `owner`, ordered by id.
+pub fn owned_groups(owner: UserId) -> Vec<Group> {
+    let start = GroupId { owner, id: vec![] };
+    GROUPS.with(|g| {
+        g.borrow()
+            .range(start..)
+            .take_while(|(k, _)| k.owner == owner)
+            .map(|(_, group)| group)
+            .collect()
+    })
+}
+
+/// Groups `member` belongs to.
+pub fn member_group_ids(member: UserId) -> Vec<GroupId> {
+    let start = MemberKey {
+        member,
+        group_id: GroupId {
+            owner: Principal::management_canister(),
+            id: vec![],
+        },
+    };
+    GROUP_MEMBERS.with(|m| {
+        m.borrow()
+            .range(start..)
+            .take_while(|(k, _)| k.member == member)
+            .map(|(k, _)| k.group_id)
+            .collect()
+    })
+}
+
+/// Files shared with `group_id` as reader or writer.
+pub fn group_file_ids(group_id: &GroupId) -> Vec<FileId> {
+    group_file_ids_after(group_id, None, usize::MAX)
+}
+
+/// Up to `limit` files shared with `group_id` following `after`, in file id order.
+pub fn group_file_ids_after(
+    group_id: &GroupId,
+    after: Option<&FileId>,
+    limit: usize,
+) -> Vec<FileId> {
+    let start = GroupFileKey {
+        group_id: group_id.clone(),
+        file_id: after.cloned().unwrap_or(FileId {
+            owner: Principal::management_canister(),
+            id: vec![],
+        }),
+    };
+    GROUP_FILES.with(|g| {
+        g.borrow()
+            .range(start..)
+            .take_while(|(k, _)| &k.group_id == group_id)
+            .filter(|(k, _)| Some(&k.file_id) != after)
+            .take(limit)
+            .map(|(k, _)| k.file_id)
+            .collect()
+    })
+}
+
+pub fn get_org(org_id: UserId) -> Option<Organization> {
+    ORGS.with(|o| o.borrow().get(&StorablePrincipal(org_id)))
+}
+
+/// Inserts or updates an organization, keeping the membership index in sync.
+pub fn put_org(org: Organization) {
+    let previous = ORGS.with(|o| {
+        o.borrow_mut()
+            .insert(StorablePrincipal(org.org_id), org.clone())
+    });
+    ORG_MEMBERS.with(|m| {
+        let mut map = m.borrow_mut();
+        for key in previous.iter().flat_map(org_member_keys) {
+            map.remove(&key);
+        }
+        for key in org_member_keys(&org) {
+            map.insert(key, ());
+        }
+    });
+}
+
+/// Organizations `member` belongs to.
+pub fn member_org_ids(member: UserId) -> Vec<UserId> {
+    let start = OrgMemberKey {
+        member,
+        org_id: Principal::management_canister(),
+    };
+    ORG_MEMBERS.with(|m| {
+        m.borrow()
+            .range(start..)
+            .take_while(|(k, _)| k.member == member)
+            .map(|(k, _)| k.org_id)
+            .collect()
+    })
+}
+
+pub fn get_folder(folder_id: &FolderId) -> Option<FolderMeta> {
+    FOLDERS.with(|f| f.borrow().get(folder_id))
+}
+
+/// Inserts or updates a folder, keeping the folder name index in sync.
+///
+/// Callers are responsible for checking that the folder's name is free in its parent.
+pub fn put_folder(meta: FolderMeta) {
+    let entry = Entry::Folder(meta.folder_id.clone());
+    let previous = FOLDERS.with(|f| f.borrow_mut().insert(meta.folder_id.clone(), meta.clone()));
+    SHARED_FOLDERS.with(|s| {
+        let mut map = s.borrow_mut();
+        for key in previous.iter().flat_map(folder_grantee_keys) {
+            map.remove(&key);
+        }
+        for key in folder_grantee_keys(&meta) {
+            map.insert(key, ());
+        }
+    });
+    if let Some(previous) = previous {
+        unlink_entry(&folder_entry_key(&previous), &entry);
+    }
+    link_entry(folder_entry_key(&meta), entry);
+}
+
+pub fn remove_folder(folder_id: &FolderId) -> Option<FolderMeta> {
+    let meta = FOLDERS.with(|f| f.borrow_mut().remove(folder_id))?;
+    SHARED_FOLDERS.with(|s| {
+        let mut map = s.borrow_mut();
+        for key in folder_grantee_keys(&meta) {
+            map.remove(&key);
+        }
+    });
+    unlink_entry(&folder_entry_key(&meta), &Entry::Folder(folder_id.clone()));
+    Some(meta)
+}
+
+/// Inserts or updates a share link, keeping the file link index in sync.
+pub fn put_link(token: Vec<u8>, info: LinkInfo) {
+    let previous = LINKS.with(|l| l.borrow_mut().insert(token.clone(), info.clone()));
+    FILE_LINKS.with(|f| {
+        let mut map = f.borrow_mut();
+        if let Some(file_id) = previous.and_then(|previous| previous.file_id) {
+            map.remove(&FileLinkKey {
+                file_id,
+                token: token.clone(),
+            });
+        }
+        if let Some(file_id) = info.file_id {
+            map.insert(FileLinkKey { file_id, token }, ());
+        }
+    });
+}
+
+pub fn remove_link(token: &[u8]) -> Option<LinkInfo> {
+    let info = LINKS.with(|l| l.borrow_mut().remove(&token.to_vec()))?;
+    if let Some(file_id) = info.file_id.clone() {
+        FILE_LINKS.with(|f| {
+            f.borrow_mut().remove(&FileLinkKey {
+                file_id,
+                token: token.to_vec(),
+            })
+        });
+    }
+    Some(info)
+}
+
+/// Tokens of the share links to `file_id`, without scanning `LINKS`.
+pub fn file_link_tokens(file_id: &FileId) -> Vec<Vec<u8>> {
+    let start = FileLinkKey {
+        file_id: file_id.clone(),
+        token: vec![],
+    };
+    FILE_LINKS.with(|f| {
+        f.borrow()
+            .range(start..)
+            .take_while(|(k, _)| &k.file_id == file_id)
+            .map(|(k, _)| k.token)
+            .collect()
+    })
+}
+
+/// Folders shared with `grantee` as reader or writer.
+pub fn shared_folder_ids(grantee: UserId) -> Vec<FolderId> {
+    let start = PrincipalFolderKey {
+        principal: grantee,
+        folder_id: FolderId {
+            owner: Principal::management_canister(),
+            id: vec![],
+        },
+    };
+    SHARED_FOLDERS.with(|s| {
+        s.borrow()
+            .range(start..)
+            .take_while(|(k, _)| k.principal == grantee)
+            .map(|(k, _)| k.folder_id)
+            .collect()
+    })
+}
+
+pub fn lookup_entry(owner: UserId, parent: Option<FolderId>, name: &str) -> Option<Entry> {
+    let key = EntryKey {
+        owner,
+        parent,
+        name: name.to_string(),
+    };
+    ENTRIES.with(|e| e.borrow().get(&key))
+}
+
+/// Lists the entries of a folder ordered by name.
+pub fn folder_entries(owner: UserId, parent: Option<FolderId>) -> Vec<(String, Entry)> {
+    folder_entries_after(owner, parent, None, usize::MAX)
+}
+
+/// Up to `limit` entries of a folder following the name `after`, in name order.
+pub fn folder_entries_after(
+    owner: UserId,
+    parent: Option<FolderId>,
+    after: Option<&str>,
+    limit: usize,
+) -> Vec<(String, Entry)> {
+    let start = EntryKey {
+        owner,
+        parent: parent.clone(),
+        name: after.unwrap_or_default().to_string(),
+    };
+    ENTRIES.with(|e| {
+        e.borrow()
+            .range(start..)
+            .take_while(|(k, _)| k.owner == owner && k.parent == parent)
+            .filter(|(k, _)| Some(k.name.as_str()) != after)
+            .take(limit)
+            .map(|(k, v)| (k.name, v))
+            .collect()
+    })
+}
+
+/// Returns `name`, or `name (n).ext` with the smallest `n` that is free in the folder.
+pub fn unique_name(owner: UserId, parent: Option<FolderId>, name: &str) -> String {
+    if lookup_entry(owner, parent.clone(), name).is_none() {
+        return name.to_string();
+    }
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (name, String::new()),
+    };
+    (1u32..)
+        .map(|n| format!("{} ({}){}", stem, n, ext))
+        .find(|candidate| lookup_entry(owner, parent.clone(), candidate).is_none())
+        .expect("ran out of candidate names")
+}
+
+/// Builds the folder name index for files created before folders existed.
+///
+/// Runs once, on the first upgrade that finds an empty index. Duplicate names in the root folder
+/// are disambiguated with [`unique_name`].
+pub fn backfill_entries() {
+    let needs_backfill = ENTRIES.with(|e| e.borrow().is_empty())
+        && FOLDERS.with(|f| f.borrow().is_empty())
+        && !FILES.with(|f| f.borrow().is_empty());
+    if !needs_backfill {
+        return;
+    }
+
+    let files: Vec<FileMeta> = FILES.with(|f| f.borrow().iter().map(|(_, meta)| meta).collect());
+    for mut meta in files {
+        meta.name = unique_name(meta.file_id.owner, meta.parent.clone(), &meta.name);
+        put_file(meta);
+    }
+}
+
+/// Builds the per-principal indexes for files created before they existed.
+///
+/// Runs once, on the first upgrade that finds an empty owner index.
+pub fn backfill_file_indexes() {
+    let needs_backfill =
+        OWNER_FILES.with(|o| o.borrow().is_empty()) && !FILES.with(|f| f.borrow().is_empty());
+    if !needs_backfill {
+        return;
+    }
+
+    FILES.with(|f| {
+        for (_, meta) in f.borrow().iter() {
+            index_principals(&meta);
+        }
+    });
+}
+
+/// Builds the file link index for links created before it existed.
+///
+/// Runs on upgrades that find an empty index, until a file link exists.
+pub fn backfill_file_links() {
+    if !FILE_LINKS.with(|f| f.borrow().is_empty()) {
+        return;
+    }
+
+    LINKS.with(|l| {
+        for (token, info) in l.borrow().iter() {
+            if let Some(file_id) = info.file_id {
+                FILE_LINKS.with(|f| f.borrow_mut().insert(FileLinkKey { file_id, token }, ()));
+            }
+        }
+    });
+}
+
+fn index_file(meta: &FileMeta) {
+    match trash_key(meta) {
+        Some(key) => {
+            TRASH.with(|t| t.borrow_mut().insert(key, ()));
+        }
+        None => link_entry(file_entry_key(meta), Entry::File(meta.file_id.clone())),
+    }
+    index_principals(meta);
+    TAGS.with(|t| {
+        let mut map = t.borrow_mut();
+        for key in tag_keys(meta) {
+            map.insert(key, ());
+        }
+    });
+    GROUP_FILES.with(|g| {
+        let mut map = g.borrow_mut();
+        for key in group_file_keys(meta) {
+            map.insert(key, ());
+        }
+    });
+    GRANT_EXPIRY.with(|g| {
+        let mut map = g.borrow_mut();
+        for key in grant_expiry_keys(meta) {
+            map.insert(key, ());
+        }
+    });
+    if let Some(key) = public_key(meta) {
+        PUBLIC_FILES.with(|p| p.borrow_mut().insert(key, ()));
+    }
+}
+
+fn unindex_file(meta: &FileMeta) {
+    match trash_key(meta) {
+        Some(key) => {
+            TRASH.with(|t| t.borrow_mut().remove(&key));
+        }
+        None => unlink_entry(&file_entry_key(meta), &Entry::File(meta.file_id.clone())),
+    }
+    OWNER_FILES.with(|o| o.borrow_mut().remove(&owner_key(meta)));
+    SHARED_FILES.with(|s| {
+        let mut map = s.borrow_mut();
+        for key in grantee_keys(meta) {
+            map.remove(&key);
+        }
+    });
+    TAGS.with(|t| {
+        let mut map = t.borrow_mut();
+        for key in tag_keys(meta) {
+            map.remove(&key);
+        }
+    });
+    GROUP_FILES.with(|g| {
+        let mut map = g.borrow_mut();
+        for key in group_file_keys(meta) {
+            map.remove(&key);
+        }
+    });
+    GRANT_EXPIRY.with(|g| {
+        let mut map = g.borrow_mut();
+        for key in grant_expiry_keys(meta) {
+            map.remove(&key);
+        }
+    });
+    if let Some(key) = public_key(meta) {
+        PUBLIC_FILES.with(|p| p.borrow_mut().remove(&key));
+    }
+}
+
+fn index_principals(meta: &FileMeta) {
+    OWNER_FILES.with(|o| o.borrow_mut().insert(owner_key(meta), ()));
+    SHARED_FILES.with(|s| {
+        let mut map = s.borrow_mut();
+        for key in grantee_keys(meta) {
+            map.insert(key, ());
+        }
+    });
+}
+
+fn tag_keys(meta: &FileMeta) -> Vec<TagKey> {
+    meta.tags
+        .iter()
+        .flatten()
+        .map(|tag| TagKey {
+            owner: meta.file_id.owner,
+            tag: tag.clone(),
+            file_id: meta.file_id.clone(),
+        })
+        .collect()
+}
+
+fn group_file_keys(meta: &FileMeta) -> Vec<GroupFileKey> {
+    meta.reader_groups
+        .iter()
+        .chain(meta.writer_groups.iter())
+        .flatten()
+        .map(|group_id| GroupFileKey {
+            group_id: group_id.clone(),
+            file_id: meta.file_id.clone(),
+        })
+        .collect()
+}
+
+fn grant_expiry_keys(meta: &FileMeta) -> Vec<GrantExpiryKey> {
+    meta.access_expires_at_ns
+        .iter()
+        .flatten()
+        .map(|(principal, expires_at_ns)| GrantExpiryKey {
+            expires_at_ns: *expires_at_ns,
+            file_id: meta.file_id.clone(),
+            principal: *principal,
+        })
+        .collect()
+}
+
+fn member_keys(group: &Group) -> Vec<MemberKey> {
+    group
+        .members
+        .iter()
+        .map(|member| MemberKey {
+            member: *member,
+            group_id: group.group_id.clone(),
+        })
+        .collect()
+}
+
+fn org_member_keys(org: &Organization) -> Vec<OrgMemberKey> {
+    org.members
+        .iter()
+        .map(|member| OrgMemberKey {
+            member: member.principal,
+            org_id: org.org_id,
+        })
+        .collect()
+}
+
+fn trash_key(meta: &FileMeta) -> Option<TrashKey> {
+    if meta.status != FileStatus::Deleted {
+        return None;
+    }
+    Some(TrashKey {
+        deleted_at_ns: meta.deleted_at_ns.unwrap_or_default(),
+        file_id: meta.file_id.clone(),
+    })
+}
+
+fn owner_key(meta: &FileMeta) -> PrincipalFileKey {
+    PrincipalFileKey {
+        principal: meta.file_id.owner,
+        file_id: meta.file_id.clone(),
+    }
+}
+
+fn public_key(meta: &FileMeta) -> Option<PrincipalFileKey> {
+    if meta.visibility() != Visibility::Public || meta.status == FileStatus::Deleted {
+        return None;
+    }
+    Some(owner_key(meta))
+}
+
+fn folder_grantee_keys(meta: &FolderMeta) -> Vec<PrincipalFolderKey> {
+    meta.readers
+        .iter()
+        .chain(meta.writers.iter())
+        .flatten()
+        .map(|grantee| PrincipalFolderKey {
+            principal: *grantee,
+            folder_id: meta.folder_id.clone(),
+        })
+        .collect()
+}
+
+fn grantee_keys(meta: &FileMeta) -> Vec<PrincipalFileKey> {
+    meta.readers
+        .iter()
+        .chain(meta.writers.iter())
+        .map(|grantee| PrincipalFileKey {
+            principal: *grantee,
+            file_id: meta.file_id.clone(),
+        })
+        .collect()
+}
+
+fn indexed_file_ids(
+    index: &'static LocalKey<RefCell<FileIndex>>,
+    principal: UserId,
+    after: Option<&FileId>,
+    limit: usize,
+) -> Vec<FileId> {
+    let start = PrincipalFileKey {
+        principal,
+        file_id: after.cloned().unwrap_or(FileId {
+            owner: Principal::management_canister(),
+            id: vec![],
+        }),
+    };
+    index.with(|i| {
+        i.borrow()
+            .range(start..)
+            .take_while(|(k, _)| k.principal == principal)
+            .filter(|(k, _)| Some(&k.file_id) != after)
+            .take(limit)
+            .map(|(k, _)| k.file_id)
+            .collect()
+    })
+}
+
+fn file_entry_key(meta: &FileMeta) -> EntryKey {
+    EntryKey {
+        owner: meta.file_id.owner,
+        parent: meta.parent.clone(),
+        name: meta.name.clone(),
+    }
+}
+
+fn folder_entry_key(meta: &FolderMeta) -> EntryKey {
+    EntryKey {
+        owner: meta.folder_id.owner,
+        parent: meta.parent.clone(),
+        name: meta.name.clone(),
+    }
+}
+
+fn link_entry(key: EntryKey, entry: Entry) {
+    ENTRIES.with(|e| e.borrow_mut().insert(key, entry));
+}
+
+fn unlink_entry(key: &EntryKey, entry: &Entry) {
+    ENTRIES.with(|e| {
+        let mut map = e.borrow_mut();
+        if map.get(key).as_ref() == Some(entry) {
+            map.remove(key);
+        }
+    });
+}
+
 pub fn icp_ledger() -> Principal {
     Principal::from_text(shared::constants::ICP_LEDGER).expect("invalid ICP_LEDGER")
 }
@@ -1,8 +1,14 @@
 use candid::{CandidType, Deserialize, Principal};
 use serde::Serialize;
-use shared::types::{DownloadPlan, FileMeta, UploadSession, UploadToken};
+use shared::types::{DownloadPlan, FileMeta, FolderMeta, UploadSession, UploadToken};
 
-use crate::errors::DirectoryError;
+use crate::{
+    errors::DirectoryError,
+    types::{
+        Entry, FilePage, FileVersion, FolderListing, Group, Organization, PendingDelete, ShareLink,
+        SharedFolderPage,
+    },
+};
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub enum StartUploadResult {
@@ -144,6 +150,19 @@ impl From<Result<Vec<UploadToken>, DirectoryError>> for GetUploadTokensResult {
     }
 }
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum GetMissingChunksResult {
+    Ok(Vec<u32>),
+    Err(DirectoryError),
+}
+impl From<Result<Vec<u32>, DirectoryError>> for GetMissingChunksResult {
+    fn from(value: Result<Vec<u32>, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => GetMissingChunksResult::Ok(v),
+            Err(e) => GetMissingChunksResult::Err(e),
+        }
+    }
+}
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub enum TopUpBalanceResult {
     Ok(u64), // New expiry
     Err(DirectoryError),
@@ -198,3 +217,255 @@ impl From<Result<DownloadPlan, DirectoryError>> for ResolveShareLinkResult {
         }
     }
 }
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum CreateFolderResult {
+    Ok(FolderMeta),
+    Err(DirectoryError),
+}
+impl From<Result<FolderMeta, DirectoryError>> for CreateFolderResult {
+    fn from(value: Result<FolderMeta, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => CreateFolderResult::Ok(v),
+            Err(e) => CreateFolderResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum GetFolderResult {
+    Ok(FolderMeta),
+    Err(DirectoryError),
+}
+impl From<Result<FolderMeta, DirectoryError>> for GetFolderResult {
+    fn from(value: Result<FolderMeta, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => GetFolderResult::Ok(v),
+            Err(e) => GetFolderResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ListFolderResult {
+    Ok(FolderListing),
+    Err(DirectoryError),
+}
+impl From<Result<FolderListing, DirectoryError>> for ListFolderResult {
+    fn from(value: Result<FolderListing, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => ListFolderResult::Ok(v),
+            Err(e) => ListFolderResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum DeleteFolderResult {
+    Ok,
+    Err(DirectoryError),
+}
+impl From<Result<(), DirectoryError>> for DeleteFolderResult {
+    fn from(value: Result<(), DirectoryError>) -> Self {
+        match value {
+            Ok(_) => DeleteFolderResult::Ok,
+            Err(e) => DeleteFolderResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum UpdateFolderResult {
+    Ok(FolderMeta),
+    Err(DirectoryError),
+}
+impl From<Result<FolderMeta, DirectoryError>> for UpdateFolderResult {
+    fn from(value: Result<FolderMeta, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => UpdateFolderResult::Ok(v),
+            Err(e) => UpdateFolderResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum UpdateFileResult {
+    Ok(FileMeta),
+    Err(DirectoryError),
+}
+impl From<Result<FileMeta, DirectoryError>> for UpdateFileResult {
+    fn from(value: Result<FileMeta, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => UpdateFileResult::Ok(v),
+            Err(e) => UpdateFileResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ResolvePathResult {
+    Ok(Entry),
+    Err(DirectoryError),
+}
+impl From<Result<Entry, DirectoryError>> for ResolvePathResult {
+    fn from(value: Result<Entry, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => ResolvePathResult::Ok(v),
+            Err(e) => ResolvePathResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ListFilesPageResult {
+    Ok(FilePage),
+    Err(DirectoryError),
+}
+impl From<Result<FilePage, DirectoryError>> for ListFilesPageResult {
+    fn from(value: Result<FilePage, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => ListFilesPageResult::Ok(v),
+            Err(e) => ListFilesPageResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum BrowseShareLinkResult {
+    Ok(SharedFolderPage),
+    Err(DirectoryError),
+}
+impl From<Result<SharedFolderPage, DirectoryError>> for BrowseShareLinkResult {
+    fn from(value: Result<SharedFolderPage, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => BrowseShareLinkResult::Ok(v),
+            Err(e) => BrowseShareLinkResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ListPendingDeletesResult {
+    Ok(Vec<PendingDelete>),
+    Err(DirectoryError),
+}
+impl From<Result<Vec<PendingDelete>, DirectoryError>> for ListPendingDeletesResult {
+    fn from(value: Result<Vec<PendingDelete>, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => ListPendingDeletesResult::Ok(v),
+            Err(e) => ListPendingDeletesResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ListShareLinksResult {
+    Ok(Vec<ShareLink>),
+    Err(DirectoryError),
+}
+impl From<Result<Vec<ShareLink>, DirectoryError>> for ListShareLinksResult {
+    fn from(value: Result<Vec<ShareLink>, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => ListShareLinksResult::Ok(v),
+            Err(e) => ListShareLinksResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ListVersionsResult {
+    Ok(Vec<FileVersion>),
+    Err(DirectoryError),
+}
+impl From<Result<Vec<FileVersion>, DirectoryError>> for ListVersionsResult {
+    fn from(value: Result<Vec<FileVersion>, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => ListVersionsResult::Ok(v),
+            Err(e) => ListVersionsResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum PruneVersionsResult {
+    Ok(Vec<u32>),
+    Err(DirectoryError),
+}
+impl From<Result<Vec<u32>, DirectoryError>> for PruneVersionsResult {
+    fn from(value: Result<Vec<u32>, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => PruneVersionsResult::Ok(v),
+            Err(e) => PruneVersionsResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum CreateGroupResult {
+    Ok(Group),
+    Err(DirectoryError),
+}
+impl From<Result<Group, DirectoryError>> for CreateGroupResult {
+    fn from(value: Result<Group, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => CreateGroupResult::Ok(v),
+            Err(e) => CreateGroupResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum GetGroupResult {
+    Ok(Group),
+    Err(DirectoryError),
+}
+impl From<Result<Group, DirectoryError>> for GetGroupResult {
+    fn from(value: Result<Group, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => GetGroupResult::Ok(v),
+            Err(e) => GetGroupResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum UpdateGroupResult {
+    Ok(Group),
+    Err(DirectoryError),
+}
+impl From<Result<Group, DirectoryError>> for UpdateGroupResult {
+    fn from(value: Result<Group, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => UpdateGroupResult::Ok(v),
+            Err(e) => UpdateGroupResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum CreateOrganizationResult {
+    Ok(Organization),
+    Err(DirectoryError),
+}
+impl From<Result<Organization, DirectoryError>> for CreateOrganizationResult {
+    fn from(value: Result<Organization, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => CreateOrganizationResult::Ok(v),
+            Err(e) => CreateOrganizationResult::Err(e),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum GetOrganizationResult {
+    Ok(Organization),
+    Err(DirectoryError),
+}
+impl From<Result<Organization, DirectoryError>> for GetOrganizationResult {
+    fn from(value: Result<Organization, DirectoryError>) -> Self {
+        match value {
+            Ok(v) => GetOrganizationResult::Ok(v),
+            Err(e) => GetOrganizationResult::Err(e),
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
+use shared::types::{
+    ChunkInfo, FileId, FileMeta, FileStatus, FolderId, FolderMeta, GroupId, LinkInfo, UserId,
+};
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
 pub struct UserState {
@@ -17,3 +20,399 @@ pub struct BucketInfo {
     pub soft_limit_bytes: u64,
     pub hard_limit_bytes: u64,
 }
+
+/// Reply of the bucket methods that return nothing on success. The bucket's error is not decoded.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum BucketResult {
+    Ok,
+    Err(candid::Reserved),
+}
+
+/// Stored content waiting to be deleted from its bucket. Failed deletions are retried with
+/// exponential backoff until they succeed.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PendingDelete {
+    pub content_id: FileId,
+    pub bucket_id: Principal,
+    /// Bytes the content takes in the bucket, released from `BucketInfo::used_bytes` once deleted.
+    pub size_bytes: u64,
+    pub attempts: u32,
+    pub next_attempt_at_ns: u64,
+    pub last_error: Option<String>,
+}
+
+/// Reply of the bucket's `copy_chunks`: the number of chunks copied. The
+/// bucket's error is not decoded.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum BucketChunksResult {
+    Ok(u32),
+    Err(candid::Reserved),
+}
+
+/// Reply of the bucket's `get_chunk_manifest`. The bucket's error is not decoded.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum BucketManifestResult {
+    Ok(Vec<ChunkInfo>),
+    Err(candid::Reserved),
+}
+
+/// Length and digest of every chunk of a stored content, in chunk order, as the bucket reported
+/// them when the content was committed.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ContentManifest {
+    pub chunks: Vec<ChunkInfo>,
+}
+
+/// Key of the `ENTRIES` index: a name inside one of the owner's folders (`None` is the root).
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EntryKey {
+    pub owner: UserId,
+    pub parent: Option<FolderId>,
+    pub name: String,
+}
+
+/// Key of the per-principal file indexes: `OWNER_FILES` and `SHARED_FILES`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PrincipalFileKey {
+    pub principal: UserId,
+    pub file_id: FileId,
+}
+
+/// Key of `VERSIONS`: one past version of a file.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VersionKey {
+    pub file_id: FileId,
+    pub version: u32,
+}
+
+/// The content of one version of a file.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct FileVersion {
+    pub version: u32,
+    /// Storage id of the version's chunks.
+    pub content_id: FileId,
+    pub mime: String,
+    pub size_bytes: u64,
+    pub chunk_size: u32,
+    pub chunk_count: u32,
+    pub sha256: Option<Vec<u8>>,
+    pub created_at_ns: u64,
+    pub merkle_root: Option<Vec<u8>>,
+}
+
+/// Key of the `TRASH` index, ordered by deletion time so expired files are found first.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TrashKey {
+    pub deleted_at_ns: u64,
+    pub file_id: FileId,
+}
+
+/// Key of the `SHARED_FOLDERS` index: a folder shared with `principal`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PrincipalFolderKey {
+    pub principal: UserId,
+    pub folder_id: FolderId,
+}
+
+/// Key of the `GRANT_EXPIRY` index: an expiring reader or writer grant on a file.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GrantExpiryKey {
+    pub expires_at_ns: u64,
+    pub file_id: FileId,
+    pub principal: UserId,
+}
+
+/// Key of the `TAGS` index: a file carrying `tag`, grouped by the file's owner.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TagKey {
+    pub owner: UserId,
+    pub tag: String,
+    pub file_id: FileId,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Group {
+    pub group_id: GroupId,
+    pub name: String,
+    pub members: Vec<UserId>,
+    pub created_at_ns: u64,
+    pub updated_at_ns: u64,
+}
+
+/// Changes to a group; fields left as `None` are kept.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UpdateGroupRequest {
+    pub name: Option<String>,
+    pub add_members: Option<Vec<UserId>>,
+    /// Members to remove. Applied after `add_members`.
+    pub remove_members: Option<Vec<UserId>>,
+}
+
+/// Key of the `GROUP_MEMBERS` index: a group `member` belongs to.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MemberKey {
+    pub member: UserId,
+    pub group_id: GroupId,
+}
+
+/// Key of the `GROUP_FILES` index: a file shared with a group.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GroupFileKey {
+    pub group_id: GroupId,
+    pub file_id: FileId,
+}
+
+/// Key of the `FILE_LINKS` index: a share link to a file.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileLinkKey {
+    pub file_id: FileId,
+    pub token: Vec<u8>,
+}
+
+/// What a member can do in an organization.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrgRole {
+    /// Manages members and has the owner's rights over the organization's files.
+    Admin,
+    /// Uploads files for the organization and edits its files.
+    Member,
+    /// Tops up the organization's prepaid balance.
+    Billing,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OrgMember {
+    pub principal: UserId,
+    pub role: OrgRole,
+}
+
+/// An account shared by several principals. The organization's files, quota and prepaid balance
+/// belong to `org_id`, a principal that no one can sign calls as.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Organization {
+    pub org_id: UserId,
+    pub name: String,
+    pub members: Vec<OrgMember>,
+    pub created_at_ns: u64,
+    pub updated_at_ns: u64,
+}
+
+/// Key of the `ORG_MEMBERS` index: an organization `member` belongs to.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct OrgMemberKey {
+    pub member: UserId,
+    pub org_id: UserId,
+}
+
+/// An offer to hand a file over to another principal, pending until they accept it.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferOffer {
+    pub file_id: FileId,
+    pub to: UserId,
+    pub created_at_ns: u64,
+}
+
+/// Optional settings for a new share link.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ShareLinkOptions {
+    pub label: Option<String>,
+    /// Maximum number of times the link can be resolved.
+    pub max_uses: Option<u32>,
+    /// Password that resolving the link requires. Only its hash is kept.
+    pub password: Option<String>,
+}
+
+/// A share link as listed to the file's owner and writers.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ShareLink {
+    pub token: Vec<u8>,
+    pub file_id: Option<FileId>,
+    pub folder_id: Option<FolderId>,
+    pub version: Option<u32>,
+    pub expires_at: u64,
+    pub label: Option<String>,
+    pub created_by: Option<UserId>,
+    pub created_at_ns: Option<u64>,
+    pub max_uses: Option<u32>,
+    pub password_protected: bool,
+    /// Number of times the link was resolved with `resolve_share_link`.
+    pub use_count: u32,
+    /// When the link was last resolved with `resolve_share_link`.
+    pub last_used_at_ns: Option<u64>,
+}
+
+impl ShareLink {
+    pub fn new(token: Vec<u8>, info: LinkInfo) -> Self {
+        Self {
+            token,
+            file_id: info.file_id,
+            folder_id: info.folder_id,
+            version: info.version,
+            expires_at: info.expires_at,
+            label: info.label,
+            created_by: info.created_by,
+            created_at_ns: info.created_at_ns,
+            max_uses: info.max_uses,
+            password_protected: info.password_sha256.is_some(),
+            use_count: info.use_count.unwrap_or(0),
+            last_used_at_ns: info.last_used_at_ns,
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BrowseShareLinkRequest {
+    pub password: Option<String>,
+    /// Subfolder of the linked folder to list; `None` lists the linked folder.
+    pub folder: Option<FolderId>,
+    /// Opaque cursor returned as `next_cursor` by the previous page.
+    pub cursor: Option<Vec<u8>>,
+    pub limit: Option<u32>,
+}
+
+/// A page of a folder reached through a folder link.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SharedFolderPage {
+    pub folder: FolderMeta,
+    pub folders: Vec<FolderMeta>,
+    pub files: Vec<FileMeta>,
+    /// `None` once the last page has been returned.
+    pub next_cursor: Option<Vec<u8>>,
+}
+
+/// Optional settings for a new upload request.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UploadRequestOptions {
+    pub label: Option<String>,
+    /// Maximum number of bytes the files uploaded through the request may add up to.
+    pub max_total_bytes: Option<u64>,
+    pub max_files: Option<u32>,
+    /// MIME types the files may have. `image/*` matches every image type. `None` allows any type.
+    pub allowed_mime_types: Option<Vec<String>>,
+}
+
+/// A link that lets anyone holding it upload files into its creator's account.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct UploadRequest {
+    pub token: Vec<u8>,
+    /// Owner of the uploaded files, charged for their storage.
+    pub owner: UserId,
+    /// Folder the files are placed in; `None` means the owner's root folder.
+    pub parent: Option<FolderId>,
+    pub options: UploadRequestOptions,
+    pub created_at_ns: u64,
+    pub expires_at_ns: u64,
+    /// Files committed through the request so far, and their total size.
+    pub uploaded_files: u32,
+    pub uploaded_bytes: u64,
+}
+
+/// What a name inside a folder points to.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Entry {
+    File(FileId),
+    Folder(FolderId),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct FolderListing {
+    pub folder: Option<FolderMeta>,
+    pub folders: Vec<FolderMeta>,
+    pub files: Vec<FileMeta>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum FileSortKey {
+    /// Order of the file indexes, by owner and then file id.
+    Id,
+    Name,
+    Size,
+    CreatedAt,
+    UpdatedAt,
+}
+
+/// Whether a listed file belongs to the caller or was shared with them.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum FileOwnership {
+    Owned,
+    Shared,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FileFilter {
+    pub mime_prefix: Option<String>,
+    pub status: Option<FileStatus>,
+    pub role: Option<FileOwnership>,
+    /// Only files having all of these attribute values.
+    pub attributes: Option<Vec<(String, String)>>,
+}
+
+/// Changes to a file's metadata; fields left as `None` are kept.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UpdateFileMetaRequest {
+    pub name: Option<String>,
+    pub mime: Option<String>,
+    /// Attributes to add or overwrite.
+    pub set_attributes: Option<Vec<(String, String)>>,
+    /// Attribute keys to remove. Applied before `set_attributes`.
+    pub remove_attributes: Option<Vec<String>>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ListFilesRequest {
+    /// Restricts the listing to one folder the caller owns or was granted access to.
+    pub parent: Option<FolderId>,
+    pub filter: Option<FileFilter>,
+    /// Defaults to `Name` inside `parent` and to `Id` otherwise: the orders that are read straight
+    /// off an index. Other orders, and descending ones, sort the whole listing and are refused
+    /// above `MAX_SORTED_FILES` files.
+    pub sort_by: Option<FileSortKey>,
+    pub descending: Option<bool>,
+    /// Opaque cursor returned as `next_cursor` by the previous page.
+    pub cursor: Option<Vec<u8>>,
+    pub limit: Option<u32>,
+}
+
+/// A boolean expression over tags.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum TagExpr {
+    Tag(String),
+    /// Files matching every sub-expression.
+    And(Vec<TagExpr>),
+    /// Files matching at least one sub-expression.
+    Or(Vec<TagExpr>),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SearchFilesRequest {
+    /// Whose files to search; defaults to the caller. Only files the caller can read are returned.
+    pub owner: Option<UserId>,
+    pub tags: Option<TagExpr>,
+    pub name_prefix: Option<String>,
+    /// Opaque cursor returned as `next_cursor` by the previous page.
+    pub cursor: Option<Vec<u8>>,
+    pub limit: Option<u32>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct FilePage {
+    pub files: Vec<FileMeta>,
+    /// `None` once the last page has been returned.
+    pub next_cursor: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SortValue {
+    Text(String),
+    Number(u64),
+}
+
+/// Position after the last file of a page. Pages continue strictly after `(value, file_id)`, so
+/// files added or removed in between never shift the remaining entries.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ListCursor {
+    pub sort_by: FileSortKey,
+    pub descending: bool,
+    pub value: SortValue,
+    pub file_id: FileId,
+}
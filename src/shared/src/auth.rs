@@ -1,7 +1,7 @@
 use hmac::{Hmac, Mac};
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
 
-use crate::types::{DownloadToken, UploadToken};
+use crate::types::{DeleteToken, DownloadToken, UploadToken};
 
 type HmacSha256 = Hmac<Sha256>;
 
@@ -50,6 +50,44 @@ pub fn sign_download_token(token: &mut DownloadToken, secret: &[u8]) {
     token.sig = mac.finalize().into_bytes().to_vec();
 }
 
+/// Domain separator for delete tokens, so that a download token for the same file and bucket
+/// never verifies as one.
+const DELETE_TOKEN_DOMAIN: &[u8] = b"delete";
+
+pub fn sign_delete_token(token: &mut DeleteToken, secret: &[u8]) {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC can take key of any size");
+
+    mac.update(DELETE_TOKEN_DOMAIN);
+    mac.update(token.file_id.owner.as_slice());
+    mac.update(&token.file_id.id);
+    mac.update(token.bucket_id.as_slice());
+    mac.update(token.directory_id.as_slice());
+    mac.update(&token.expires_at.to_be_bytes());
+
+    token.sig = mac.finalize().into_bytes().to_vec();
+}
+
+pub fn verify_delete_token(token: &DeleteToken, secret: &[u8]) -> bool {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC can take key of any size");
+
+    mac.update(DELETE_TOKEN_DOMAIN);
+    mac.update(token.file_id.owner.as_slice());
+    mac.update(&token.file_id.id);
+    mac.update(token.bucket_id.as_slice());
+    mac.update(token.directory_id.as_slice());
+    mac.update(&token.expires_at.to_be_bytes());
+
+    mac.verify_slice(&token.sig).is_ok()
+}
+
+/// Hashes a share link password, salted with the link's token.
+pub fn hash_link_password(token: &[u8], password: &str) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(token);
+    hasher.update(password.as_bytes());
+    hasher.finalize().to_vec()
+}
+
 pub fn verify_download_token(token: &DownloadToken, secret: &[u8]) -> bool {
     let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC can take key of any size");
 
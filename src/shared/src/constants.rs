@@ -10,3 +10,36 @@ pub const MONTH_NS: u64 = 30 * DAY_NS;
 
 pub const ICP_LEDGER: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
 pub const CKUSDC_LEDGER: &str = "yfumr-cyaaa-aaaar-qaela-cai";
+
+pub const MAX_NAME_BYTES: usize = 255;
+pub const MAX_MIME_BYTES: usize = 255;
+
+pub const MAX_ATTRIBUTES: usize = 32;
+pub const MAX_ATTRIBUTE_KEY_BYTES: usize = 64;
+pub const MAX_ATTRIBUTE_VALUE_BYTES: usize = 1024;
+
+pub const MAX_TAGS: usize = 32;
+pub const MAX_TAG_BYTES: usize = 64;
+/// Maximum number of tags in one search expression.
+pub const MAX_TAG_QUERY_TERMS: usize = 16;
+
+/// Most chunks a bucket copies in one `copy_chunks` call, so that each call stays well within the
+/// instruction and memory limits of a message.
+pub const MAX_COPY_BATCH_CHUNKS: u32 = 16;
+
+pub const MAX_GROUP_MEMBERS: usize = 1_000;
+pub const MAX_ORG_MEMBERS: usize = 1_000;
+
+pub const DEFAULT_PAGE_SIZE: u32 = 100;
+pub const MAX_PAGE_SIZE: u32 = 1_000;
+/// Most files `list_files_page` sorts in memory. Larger listings are paged in index order.
+pub const MAX_SORTED_FILES: usize = 10_000;
+
+pub const DEFAULT_TRASH_RETENTION_NS: u64 = MONTH_NS;
+
+/// Delay before retrying a failed chunk deletion, doubled on every further failure.
+pub const DELETE_RETRY_BASE_NS: u64 = MINUTE_NS;
+pub const MAX_DELETE_RETRY_DELAY_NS: u64 = DAY_NS;
+
+/// Longest time an upload session can stay open, counted from its start.
+pub const MAX_UPLOAD_TTL_NS: u64 = 7 * DAY_NS;
@@ -0,0 +1,16 @@
+use sha2::{Digest, Sha256};
+
+/// SHA-256 digest of one chunk.
+pub fn chunk_sha256(bytes: &[u8]) -> Vec<u8> {
+    Sha256::digest(bytes).to_vec()
+}
+
+/// Hash of a whole file: SHA-256 over the concatenated SHA-256 digests of its chunks, in chunk
+/// order. This is what `FileMeta.sha256` holds, so it can be checked without rereading the file.
+pub fn content_sha256<'a>(chunk_digests: impl IntoIterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for digest in chunk_digests {
+        hasher.update(digest);
+    }
+    hasher.finalize().to_vec()
+}
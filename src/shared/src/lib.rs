@@ -1,5 +1,7 @@
 pub mod auth;
 pub mod constants;
+pub mod integrity;
+pub mod merkle;
 pub mod types;
 
 use candid::CandidType;
@@ -0,0 +1,109 @@
+//! Merkle tree over the chunks of a file.
+//!
+//! Each leaf commits to a chunk's index, length and SHA-256 digest, so a chunk cannot be swapped
+//! for another one of the file or cut short without changing the root. Inner nodes hash their two
+//! children; a node without a sibling moves up a level unchanged. Leaves and inner nodes use
+//! different prefixes so that one can never be passed off as the other.
+//!
+//! Building the tree hashes every node once, so a root costs one pass over the leaves.
+
+use sha2::{Digest, Sha256};
+
+use crate::types::ChunkInfo;
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// Leaf hash of one chunk.
+pub fn leaf_hash(chunk: &ChunkInfo) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(chunk.chunk_index.to_be_bytes());
+    hasher.update(chunk.size_bytes.to_be_bytes());
+    hasher.update(&chunk.sha256);
+    hasher.finalize().to_vec()
+}
+
+fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().to_vec()
+}
+
+/// Hashes one level of the tree into the next one.
+fn next_level(level: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => single.clone(),
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Root over the chunks of a file, in chunk order. A file without chunks has the SHA-256 of
+/// nothing as its root.
+pub fn root(chunks: &[ChunkInfo]) -> Vec<u8> {
+    let mut level: Vec<Vec<u8>> = chunks.iter().map(leaf_hash).collect();
+    if level.is_empty() {
+        return Sha256::digest([]).to_vec();
+    }
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.remove(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(count: u32) -> Vec<ChunkInfo> {
+        (0..count)
+            .map(|chunk_index| ChunkInfo {
+                chunk_index,
+                size_bytes: 1024,
+                sha256: Sha256::digest(chunk_index.to_be_bytes()).to_vec(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty_root_is_hash_of_nothing() {
+        assert_eq!(root(&[]), Sha256::digest([]).to_vec());
+    }
+
+    #[test]
+    fn single_leaf_is_root() {
+        let chunks = chunks(1);
+        assert_eq!(root(&chunks), leaf_hash(&chunks[0]));
+    }
+
+    #[test]
+    fn odd_leaf_moves_up_unchanged() {
+        let chunks = chunks(3);
+        let left = node_hash(&leaf_hash(&chunks[0]), &leaf_hash(&chunks[1]));
+        assert_eq!(root(&chunks), node_hash(&left, &leaf_hash(&chunks[2])));
+    }
+
+    #[test]
+    fn tampered_leaf_changes_root() {
+        let original = chunks(5);
+        let expected = root(&original);
+
+        let mut tampered = original.clone();
+        tampered[4].sha256[0] ^= 1;
+        assert_ne!(root(&tampered), expected);
+
+        let mut truncated = original.clone();
+        truncated[2].size_bytes -= 1;
+        assert_ne!(root(&truncated), expected);
+
+        let mut swapped = original;
+        swapped.swap(0, 1);
+        assert_ne!(root(&swapped), expected);
+    }
+}
@@ -1,4 +1,4 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::BTreeMap};
 
 use candid::{decode_one, encode_one, CandidType, Principal};
 use ic_stable_structures::{storable::Bound, Storable};
@@ -26,6 +26,43 @@ impl Storable for FileId {
     }
 }
 
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FolderId {
+    pub owner: UserId,
+    pub id: Vec<u8>,
+}
+
+impl Storable for FolderId {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode FolderId"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode FolderId")
+    }
+}
+
+/// A named set of principals that files can be shared with, owned by the principal that created it.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GroupId {
+    pub owner: UserId,
+    pub id: Vec<u8>,
+}
+
+impl Storable for GroupId {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode GroupId"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode GroupId")
+    }
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum FileStatus {
     Pending,
@@ -39,6 +76,17 @@ pub enum FileRole {
     Writer,
 }
 
+/// Who can download a file besides the principals granted access.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    /// Only the owner and grantees.
+    Private,
+    /// Anyone, including anonymous callers; listed by `list_public_files`.
+    Public,
+    /// Anyone who knows the file id, without being listed.
+    Unlisted,
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct PricingConfig {
     pub rate_per_gb_per_month: u64,
@@ -55,9 +103,103 @@ pub struct FileMeta {
     pub created_at_ns: u64,
     pub updated_at_ns: u64,
     pub status: FileStatus,
+    /// Hash of the content as computed by `integrity::content_sha256`; `None` when it was not
+    /// verified.
     pub sha256: Option<Vec<u8>>,
     pub readers: Vec<UserId>,
     pub writers: Vec<UserId>,
+    /// Folder containing the file; `None` means the owner's root folder.
+    pub parent: Option<FolderId>,
+    /// Current version number; `None` means the first version.
+    pub version: Option<u32>,
+    /// Storage id of the current version's chunks; `None` means `file_id` itself.
+    pub content_id: Option<FileId>,
+    /// When the file was moved to the trash; set while `status` is `Deleted`.
+    pub deleted_at_ns: Option<u64>,
+    /// Application-defined attributes, e.g. a project id or document type.
+    pub attributes: Option<BTreeMap<String, String>>,
+    /// Labels for finding the file with `search_files`, sorted and unique.
+    pub tags: Option<Vec<String>>,
+    /// Groups whose members can read the file.
+    pub reader_groups: Option<Vec<GroupId>>,
+    /// Groups whose members can read and write the file.
+    pub writer_groups: Option<Vec<GroupId>>,
+    /// When the grants of `readers` and `writers` listed here end. Unlisted grants do not expire.
+    pub access_expires_at_ns: Option<BTreeMap<UserId, u64>>,
+    /// `None` means `Private`.
+    pub visibility: Option<Visibility>,
+    /// Root of the `merkle` tree over the content's chunks; `None` when the chunk hashes are not
+    /// known.
+    pub merkle_root: Option<Vec<u8>>,
+}
+
+impl FileMeta {
+    /// A new file, ready for download, with no grants, attributes or tags and no known hashes.
+    pub fn new_ready(
+        file_id: FileId,
+        name: String,
+        mime: String,
+        parent: Option<FolderId>,
+        size_bytes: u64,
+        chunk_size: u32,
+        chunk_count: u32,
+    ) -> Self {
+        let now = ic_cdk::api::time();
+        FileMeta {
+            file_id,
+            name,
+            mime,
+            size_bytes,
+            chunk_size,
+            chunk_count,
+            created_at_ns: now,
+            updated_at_ns: now,
+            status: FileStatus::Ready,
+            sha256: None,
+            readers: vec![],
+            writers: vec![],
+            parent,
+            version: None,
+            content_id: None,
+            deleted_at_ns: None,
+            attributes: None,
+            tags: None,
+            reader_groups: None,
+            writer_groups: None,
+            access_expires_at_ns: None,
+            visibility: None,
+            merkle_root: None,
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version.unwrap_or(1)
+    }
+
+    pub fn content_id(&self) -> FileId {
+        self.content_id
+            .clone()
+            .unwrap_or_else(|| self.file_id.clone())
+    }
+
+    pub fn visibility(&self) -> Visibility {
+        self.visibility.clone().unwrap_or(Visibility::Private)
+    }
+
+    /// The file as shown to callers without a grant on it: its grants, attributes and tags are
+    /// left out.
+    pub fn redacted(self) -> Self {
+        FileMeta {
+            readers: vec![],
+            writers: vec![],
+            attributes: None,
+            tags: None,
+            reader_groups: None,
+            writer_groups: None,
+            access_expires_at_ns: None,
+            ..self
+        }
+    }
 }
 
 impl Storable for FileMeta {
@@ -72,6 +214,43 @@ impl Storable for FileMeta {
     }
 }
 
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct FolderMeta {
+    pub folder_id: FolderId,
+    pub name: String,
+    /// Parent folder; `None` means the owner's root folder.
+    pub parent: Option<FolderId>,
+    pub created_at_ns: u64,
+    pub updated_at_ns: u64,
+    /// Principals that can read every file under the folder, subfolders included.
+    pub readers: Option<Vec<UserId>>,
+    /// Principals that can read and write every file under the folder, subfolders included.
+    pub writers: Option<Vec<UserId>>,
+}
+
+impl FolderMeta {
+    /// The folder as shown to callers without a grant on it: its grants are left out.
+    pub fn redacted(self) -> Self {
+        FolderMeta {
+            readers: None,
+            writers: None,
+            ..self
+        }
+    }
+}
+
+impl Storable for FolderMeta {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(encode_one(self).expect("failed to encode FolderMeta"))
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        decode_one(&bytes).expect("failed to decode FolderMeta")
+    }
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct UploadSession {
     pub upload_id: UploadId,
@@ -83,6 +262,25 @@ pub struct UploadSession {
     pub expected_chunk_count: u32,
     pub uploaded_chunks: Vec<u32>,
     pub expires_at_ns: u64,
+    /// Folder the file is placed in on commit; `None` means the owner's root folder.
+    pub parent: Option<FolderId>,
+    /// File this upload adds a new version to; `None` uploads a new file and `file_id` becomes
+    /// its id. For versions, `file_id` is the storage id of the new content.
+    pub version_of: Option<FileId>,
+    /// Principal that started the upload; `None` means the file owner.
+    pub uploader: Option<UserId>,
+    /// File this session edits in place; `file_id` is then the storage id of the staged chunks.
+    pub edit_of: Option<FileId>,
+    /// Token of the upload request the file is uploaded through.
+    pub upload_request: Option<Vec<u8>>,
+    /// When the session was started; `None` for sessions started before this was recorded.
+    pub started_at_ns: Option<u64>,
+}
+
+impl UploadSession {
+    pub fn uploader(&self) -> UserId {
+        self.uploader.unwrap_or(self.file_id.owner)
+    }
 }
 
 impl Storable for UploadSession {
@@ -103,6 +301,8 @@ pub struct DownloadPlan {
     pub chunk_size: u32,
     pub locations: Vec<ChunkLocation>,
     pub auth: Vec<BucketAuth>,
+    /// Root of the `merkle` tree over the chunks listed in `locations`, as recorded on the file.
+    pub merkle_root: Option<Vec<u8>>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -114,6 +314,24 @@ pub struct DownloadToken {
     pub sig: Vec<u8>,
 }
 
+/// Capability to delete the chunks of `file_id` from `bucket_id`, issued by the directory.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DeleteToken {
+    pub file_id: FileId,
+    pub bucket_id: BucketId,
+    pub directory_id: Principal,
+    pub expires_at: u64,
+    pub sig: Vec<u8>,
+}
+
+/// Length and SHA-256 digest of a stored chunk, as recorded by its bucket.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChunkInfo {
+    pub chunk_index: u32,
+    pub size_bytes: u32,
+    pub sha256: Vec<u8>,
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct BucketAuth {
     pub bucket_id: BucketId,
@@ -122,8 +340,25 @@ pub struct BucketAuth {
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct LinkInfo {
-    pub file_id: FileId,
+    /// File the link points to; `None` for folder links. Links stored while this was a plain
+    /// `FileId` decode as `Some`.
+    pub file_id: Option<FileId>,
     pub expires_at: u64,
+    /// Version the link is pinned to; `None` follows the current version.
+    pub version: Option<u32>,
+    pub label: Option<String>,
+    pub created_by: Option<UserId>,
+    pub created_at_ns: Option<u64>,
+    /// How many times the link can be resolved.
+    pub max_uses: Option<u32>,
+    /// Password hash from `auth::hash_link_password`, for password protected links.
+    pub password_sha256: Option<Vec<u8>>,
+    /// Number of times the link was resolved.
+    pub use_count: Option<u32>,
+    /// When the link was last resolved.
+    pub last_used_at_ns: Option<u64>,
+    /// Folder the link points to. Holders can browse it and download any file under it.
+    pub folder_id: Option<FolderId>,
 }
 
 impl Storable for LinkInfo {
@@ -142,6 +377,10 @@ impl Storable for LinkInfo {
 pub struct ChunkLocation {
     pub chunk_index: u32,
     pub bucket: Principal,
+    /// Length of the chunk; `None` when the chunk hashes of the content are not known.
+    pub size_bytes: Option<u32>,
+    /// SHA-256 digest of the chunk; `None` when the chunk hashes of the content are not known.
+    pub sha256: Option<Vec<u8>>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -1,9 +1,12 @@
+use std::time::{Duration, UNIX_EPOCH};
+
 use candid::Principal;
-use directory::results::{
-    CommitUploadResult, DeleteFileResult, GetFileMetaResult, StartUploadResult,
+use directory::{
+    errors::DirectoryError,
+    results::{CommitUploadResult, DeleteFileResult, GetFileMetaResult, StartUploadResult},
 };
 use ic_papi_api::PaymentType;
-use shared::types::FileRole;
+use shared::types::{FileMeta, FileRole};
 
 use crate::util::{PicCanisterTrait, TestSetup};
 
@@ -192,3 +195,127 @@ fn test_acl_writer_delete() {
         .unwrap();
     assert!(matches!(meta_res_gone, GetFileMetaResult::Err(_)));
 }
+
+#[test]
+fn test_acl_shared_files_listed() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    let viewer = Principal::from_slice(&[2; 29]);
+
+    let meta = setup.upload_file("shared.txt", b"data", None);
+    setup.upload_file("private.txt", b"data", None);
+
+    let add_res: DeleteFileResult = setup
+        .directory
+        .update(
+            owner,
+            "add_file_access",
+            (meta.file_id.clone(), viewer, FileRole::Reader),
+        )
+        .unwrap();
+    assert!(matches!(add_res, DeleteFileResult::Ok));
+
+    // The grantee sees only the shared file
+    let files: Vec<FileMeta> = setup.directory.query(viewer, "list_files", ()).unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].file_id, meta.file_id);
+
+    // The owner sees both
+    let files: Vec<FileMeta> = setup.directory.query(owner, "list_files", ()).unwrap();
+    assert_eq!(files.len(), 2);
+
+    let remove_res: DeleteFileResult = setup
+        .directory
+        .update(owner, "remove_file_access", (meta.file_id.clone(), viewer))
+        .unwrap();
+    assert!(matches!(remove_res, DeleteFileResult::Ok));
+
+    let files: Vec<FileMeta> = setup.directory.query(viewer, "list_files", ()).unwrap();
+    assert!(files.is_empty());
+}
+
+#[test]
+fn test_acl_grant_expires() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    let contractor = Principal::from_slice(&[2; 29]);
+    let meta = setup.upload_file("brief.txt", b"data", None);
+
+    let now = setup
+        .pic
+        .get_time()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+    let hour_ns = 3_600_000_000_000u64;
+
+    // 1. Expiries must lie in the future
+    let res: Result<(), DirectoryError> = setup
+        .directory
+        .update(
+            owner,
+            "add_file_access",
+            (
+                meta.file_id.clone(),
+                contractor,
+                FileRole::Writer,
+                Some(now),
+            ),
+        )
+        .unwrap();
+    assert!(matches!(res, Err(DirectoryError::InvalidRequest(_))));
+
+    let res: Result<(), DirectoryError> = setup
+        .directory
+        .update(
+            owner,
+            "add_file_access",
+            (
+                meta.file_id.clone(),
+                contractor,
+                FileRole::Writer,
+                Some(now + hour_ns),
+            ),
+        )
+        .unwrap();
+    assert!(res.is_ok());
+    let res: GetFileMetaResult = setup
+        .directory
+        .query(contractor, "get_file_meta", (meta.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(res, GetFileMetaResult::Ok(_)));
+
+    // 2. Once expired, the grant is ignored even before the sweep removes it
+    setup.pic.advance_time(Duration::from_secs(2 * 3600));
+    let res: GetFileMetaResult = setup
+        .directory
+        .query(contractor, "get_file_meta", (meta.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(
+        res,
+        GetFileMetaResult::Err(DirectoryError::Unauthorized)
+    ));
+    let res: DeleteFileResult = setup
+        .directory
+        .update(contractor, "delete_file", (meta.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(
+        res,
+        DeleteFileResult::Err(DirectoryError::Unauthorized)
+    ));
+
+    let _: () = setup
+        .directory
+        .update(Principal::anonymous(), "sweep_expired_grants", ())
+        .unwrap();
+    let meta = match setup
+        .directory
+        .query(owner, "get_file_meta", (meta.file_id.clone(),))
+        .unwrap()
+    {
+        GetFileMetaResult::Ok(meta) => meta,
+        GetFileMetaResult::Err(e) => panic!("Get file meta failed: {:?}", e),
+    };
+    assert!(meta.writers.is_empty());
+    assert!(meta.access_expires_at_ns.is_none());
+}
@@ -1,4 +1,14 @@
+use bucket::{
+    errors::BucketError,
+    results::{DeleteFileResult, GetChunkResult, PutChunkResult},
+};
 use candid::Principal;
+use directory::results::{CommitUploadResult, GetUploadTokensResult, StartUploadResult};
+use ic_papi_api::PaymentType;
+use shared::{
+    auth::{sign_delete_token, sign_download_token},
+    types::{DeleteToken, DownloadToken, UploadToken},
+};
 
 use crate::util::{PicCanisterTrait, TestSetup};
 
@@ -12,4 +22,186 @@ fn test_bucket_stat() {
     assert!(res.contains("Chunks stored"));
 }
 
+#[test]
+fn test_delete_file_authorization() {
+    let setup = TestSetup::default();
+    let stranger = Principal::from_slice(&[2; 29]);
+    let meta = setup.upload_file("a.txt", b"hello", None);
+    let content_id = meta.content_id();
+    let stored = || -> String {
+        setup
+            .bucket
+            .query(Principal::anonymous(), "stat", ())
+            .unwrap()
+    };
+    assert_eq!(stored(), "Chunks stored: 1");
+
+    let delete = |token: Option<DeleteToken>| -> DeleteFileResult {
+        setup
+            .bucket
+            .update(stranger, "delete_file", (content_id.clone(), token))
+            .unwrap()
+    };
+    let mut token = DeleteToken {
+        file_id: content_id.clone(),
+        bucket_id: setup.bucket.canister_id,
+        directory_id: setup.directory.canister_id,
+        expires_at: u64::MAX,
+        sig: vec![],
+    };
+
+    // 1. Without a token, only the directory can delete
+    assert!(matches!(
+        delete(None),
+        DeleteFileResult::Err(BucketError::Unauthorized)
+    ));
+
+    // 2. Tokens must be signed with the directory's secret, as delete tokens
+    sign_delete_token(&mut token, &[1; 32]);
+    assert!(matches!(
+        delete(Some(token.clone())),
+        DeleteFileResult::Err(BucketError::InvalidSignature)
+    ));
+    let mut download = DownloadToken {
+        file_id: content_id.clone(),
+        bucket_id: setup.bucket.canister_id,
+        directory_id: setup.directory.canister_id,
+        expires_at: u64::MAX,
+        sig: vec![],
+    };
+    sign_download_token(&mut download, &[0; 32]);
+    token.sig = download.sig;
+    assert!(matches!(
+        delete(Some(token.clone())),
+        DeleteFileResult::Err(BucketError::InvalidSignature)
+    ));
+
+    // 3. A token for another file does not cover this one
+    let mut other = token.clone();
+    other.file_id.id = vec![0; 16];
+    sign_delete_token(&mut other, &[0; 32]);
+    assert!(matches!(
+        delete(Some(other)),
+        DeleteFileResult::Err(BucketError::Unauthorized)
+    ));
+    assert_eq!(stored(), "Chunks stored: 1");
+
+    // 4. A valid token lets anyone delete
+    sign_delete_token(&mut token, &[0; 32]);
+    assert!(matches!(delete(Some(token)), DeleteFileResult::Ok));
+    assert_eq!(stored(), "Chunks stored: 0");
+}
+
+#[test]
+fn test_tokens_from_other_directory() {
+    let setup = TestSetup::default();
+    let meta = setup.upload_file("a.txt", b"hello", None);
+
+    // Signed with the right secret, but for a directory the bucket does not serve
+    let mut token = DownloadToken {
+        file_id: meta.content_id(),
+        bucket_id: setup.bucket.canister_id,
+        directory_id: Principal::from_slice(&[2; 29]),
+        expires_at: u64::MAX,
+        sig: vec![],
+    };
+    sign_download_token(&mut token, &[0; 32]);
+    let res: GetChunkResult = setup
+        .bucket
+        .query(Principal::anonymous(), "get_chunk", (token.clone(), 0u32))
+        .unwrap();
+    assert!(matches!(
+        res,
+        GetChunkResult::Err(BucketError::Unauthorized)
+    ));
+
+    token.directory_id = setup.directory.canister_id;
+    sign_download_token(&mut token, &[0; 32]);
+    let res: GetChunkResult = setup
+        .bucket
+        .query(Principal::anonymous(), "get_chunk", (token, 0u32))
+        .unwrap();
+    assert!(matches!(res, GetChunkResult::Ok(bytes) if bytes == b"hello"));
+}
+
+#[test]
+fn test_put_chunk_retries() {
+    let setup = TestSetup::default();
+    let caller = Principal::from_slice(&[1; 29]);
+
+    let session = match setup
+        .directory
+        .update_with_cycles(
+            &setup.proxy,
+            caller,
+            "start_upload",
+            (
+                "retry.txt".to_string(),
+                "text/plain".to_string(),
+                5u64,
+                None::<PaymentType>,
+            ),
+            200_000,
+        )
+        .unwrap()
+    {
+        StartUploadResult::Ok(session) => session,
+        StartUploadResult::Err(e) => panic!("Start upload failed: {:?}", e),
+    };
+    let token: UploadToken = match setup
+        .directory
+        .update(
+            setup.proxy.canister_id,
+            "get_upload_tokens",
+            (session.upload_id.clone(), vec![0u32]),
+        )
+        .unwrap()
+    {
+        GetUploadTokensResult::Ok(tokens) => tokens[0].clone(),
+        GetUploadTokensResult::Err(e) => panic!("Get tokens failed: {:?}", e),
+    };
+    let put = |bytes: &[u8], cycles: u128| -> PutChunkResult {
+        setup
+            .bucket
+            .update_with_cycles(
+                &setup.proxy,
+                caller,
+                "put_chunk",
+                (token.clone(), 0u32, bytes.to_vec(), None::<PaymentType>),
+                cycles,
+            )
+            .unwrap()
+    };
+
+    // 1. Retrying a stored chunk succeeds without paying again
+    assert!(matches!(put(b"hello", 100_000), PutChunkResult::Ok(5)));
+    assert!(matches!(put(b"hello", 0), PutChunkResult::Ok(5)));
+
+    // 2. Until the upload is committed, a chunk can still be replaced
+    assert!(matches!(
+        put(b"world", 0),
+        PutChunkResult::Err(BucketError::PaymentFailed(_))
+    ));
+    assert!(matches!(put(b"world", 100_000), PutChunkResult::Ok(5)));
+    assert!(matches!(put(b"hello", 100_000), PutChunkResult::Ok(5)));
+    setup.settle();
+
+    let res: CommitUploadResult = setup
+        .directory
+        .update(
+            setup.proxy.canister_id,
+            "commit_upload",
+            (session.upload_id.clone(),),
+        )
+        .unwrap();
+    assert!(matches!(res, CommitUploadResult::Ok(_)));
+
+    // 3. After the commit, only identical retries are accepted
+    assert!(matches!(
+        put(b"world", 100_000),
+        PutChunkResult::Err(BucketError::ChunkConflict(0))
+    ));
+    assert!(matches!(put(b"hello", 0), PutChunkResult::Ok(5)));
+}
+
 // More complex bucket tests will be in flow_tests.rs because they need tokens from directory
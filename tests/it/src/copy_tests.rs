@@ -0,0 +1,142 @@
+use candid::Principal;
+use directory::{
+    errors::DirectoryError,
+    results::{CommitUploadResult, GetDownloadPlanResult},
+};
+use ic_papi_api::PaymentType;
+use shared::{
+    constants::MAX_COPY_BATCH_CHUNKS,
+    types::{FileId, FileMeta, FileStatus, FolderId, UserId},
+};
+
+use crate::util::{PicCanisterTrait, TestSetup};
+
+fn copy_file(
+    setup: &TestSetup,
+    file_id: &FileId,
+    name: Option<&str>,
+    owner: Option<UserId>,
+) -> CommitUploadResult {
+    setup
+        .directory
+        .update_with_cycles(
+            &setup.proxy,
+            Principal::from_slice(&[1; 29]),
+            "copy_file",
+            (
+                file_id.clone(),
+                name.map(str::to_string),
+                None::<FolderId>,
+                owner,
+                None::<PaymentType>,
+            ),
+            200_000,
+        )
+        .unwrap()
+}
+
+#[test]
+fn test_copy_file() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    let _: Result<(), DirectoryError> = setup
+        .directory
+        .update(Principal::anonymous(), "admin_set_quota", (owner, 1024u64))
+        .unwrap();
+
+    let meta = setup.upload_file("notes.txt", b"hello", None);
+    assert_eq!(setup.used_bytes(owner), 5);
+
+    // 1. The copy is a new file with the same content, charged to the owner
+    let copy: FileMeta = match copy_file(&setup, &meta.file_id, Some("copy.txt"), None) {
+        CommitUploadResult::Ok(copy) => copy,
+        CommitUploadResult::Err(e) => panic!("Copy failed: {:?}", e),
+    };
+    assert_ne!(copy.file_id, meta.file_id);
+    assert_eq!(copy.name, "copy.txt");
+    assert_eq!(copy.status, FileStatus::Ready);
+    assert_eq!(copy.size_bytes, 5);
+    assert_eq!(setup.used_bytes(owner), 10);
+    assert_eq!(setup.download(&copy.file_id, None), b"hello");
+
+    // 2. The default name is the original one, which is taken in the same folder
+    let res = copy_file(&setup, &meta.file_id, None, None);
+    assert!(matches!(
+        res,
+        CommitUploadResult::Err(DirectoryError::NameAlreadyExists(_))
+    ));
+
+    // 3. Copies cannot be made for owners the caller cannot write for
+    let res = copy_file(
+        &setup,
+        &meta.file_id,
+        Some("theirs.txt"),
+        Some(Principal::from_slice(&[9; 29])),
+    );
+    assert!(matches!(
+        res,
+        CommitUploadResult::Err(DirectoryError::Unauthorized)
+    ));
+    assert_eq!(setup.used_bytes(owner), 10);
+}
+
+#[test]
+fn test_copy_file_in_batches() {
+    let setup = TestSetup::default();
+    let chunk_count = MAX_COPY_BATCH_CHUNKS + 1;
+    let data: Vec<u8> = (0..(chunk_count as usize - 1) * 1024 * 1024 + 5)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    let meta = setup.upload_file("big.bin", &data, None);
+    assert_eq!(meta.chunk_count, chunk_count);
+
+    // The copy spans several batches and ends up with the same chunks as the original
+    let copy = match copy_file(&setup, &meta.file_id, Some("big copy.bin"), None) {
+        CommitUploadResult::Ok(copy) => copy,
+        CommitUploadResult::Err(e) => panic!("Copy failed: {:?}", e),
+    };
+    assert_eq!(copy.status, FileStatus::Ready);
+    assert_eq!(copy.chunk_count, chunk_count);
+    assert_eq!(copy.sha256, meta.sha256);
+    assert_eq!(copy.merkle_root, meta.merkle_root);
+    assert_eq!(setup.download(&copy.file_id, None), &data[..1024 * 1024]);
+}
+
+#[test]
+fn test_copy_file_across_buckets() {
+    let setup = TestSetup::default();
+    let meta = setup.upload_file("photo.jpg", b"pixels", None);
+
+    // New content only goes to the second bucket from now on
+    let second = setup.add_bucket();
+    let _: Result<(), DirectoryError> = setup
+        .directory
+        .update(
+            Principal::anonymous(),
+            "admin_set_bucket_writable",
+            (setup.bucket.canister_id, false),
+        )
+        .unwrap();
+
+    let copy = match copy_file(&setup, &meta.file_id, Some("photo copy.jpg"), None) {
+        CommitUploadResult::Ok(copy) => copy,
+        CommitUploadResult::Err(e) => panic!("Copy failed: {:?}", e),
+    };
+
+    let plan = match setup
+        .directory
+        .query(
+            setup.proxy.canister_id,
+            "get_download_plan",
+            (copy.file_id.clone(),),
+        )
+        .unwrap()
+    {
+        GetDownloadPlanResult::Ok(plan) => plan,
+        GetDownloadPlanResult::Err(e) => panic!("Get download plan failed: {:?}", e),
+    };
+    assert_eq!(plan.auth[0].bucket_id, second.canister_id);
+
+    assert_eq!(setup.download(&copy.file_id, None), b"pixels");
+    assert_eq!(setup.download(&meta.file_id, None), b"pixels");
+}
@@ -1,5 +1,10 @@
 use candid::Principal;
-use directory::results::StartUploadResult;
+use directory::{
+    results::{GetFileMetaResult, ListFilesPageResult, StartUploadResult, UpdateFileResult},
+    types::{
+        FileFilter, FileOwnership, FilePage, FileSortKey, ListFilesRequest, UpdateFileMetaRequest,
+    },
+};
 use ic_papi_api::PaymentType;
 
 use crate::util::{PicCanisterTrait, TestSetup};
@@ -41,3 +46,151 @@ fn test_list_files_empty() {
         setup.directory.update(caller, "list_files", ()).unwrap();
     assert!(files.is_empty());
 }
+
+#[test]
+fn test_list_files_page() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    for name in ["c.txt", "a.txt", "b.txt"] {
+        setup.upload_file(name, b"data", None);
+    }
+
+    let list = |sort_by: Option<FileSortKey>, cursor: Option<Vec<u8>>| -> FilePage {
+        let request = ListFilesRequest {
+            sort_by,
+            limit: Some(2),
+            cursor,
+            ..Default::default()
+        };
+        match setup
+            .directory
+            .query(owner, "list_files_page", (request,))
+            .unwrap()
+        {
+            ListFilesPageResult::Ok(page) => page,
+            ListFilesPageResult::Err(e) => panic!("List files failed: {:?}", e),
+        }
+    };
+
+    let first = list(Some(FileSortKey::Name), None);
+    let names: Vec<&str> = first.files.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["a.txt", "b.txt"]);
+    assert!(first.next_cursor.is_some());
+
+    // A file sorting before the cursor does not shift the next page
+    setup.upload_file("0.txt", b"data", None);
+
+    let second = list(Some(FileSortKey::Name), first.next_cursor);
+    let names: Vec<&str> = second.files.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["c.txt"]);
+    assert!(second.next_cursor.is_none());
+
+    // The default order pages through the file index
+    let mut file_ids = vec![];
+    let mut cursor = None;
+    loop {
+        let page = list(None, cursor);
+        file_ids.extend(page.files.into_iter().map(|f| f.file_id));
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(file_ids.len(), 4);
+    assert!(file_ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+    // Nothing is shared with the owner
+    let request = ListFilesRequest {
+        filter: Some(FileFilter {
+            role: Some(FileOwnership::Shared),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let shared: ListFilesPageResult = setup
+        .directory
+        .query(owner, "list_files_page", (request,))
+        .unwrap();
+    assert!(matches!(shared, ListFilesPageResult::Ok(page) if page.files.is_empty()));
+}
+
+#[test]
+fn test_update_file_meta() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    let report = setup.upload_file("report.bin", b"data", None);
+    setup.upload_file("other.bin", b"data", None);
+
+    // 1. Rename, change the MIME type and tag the file with attributes
+    let request = UpdateFileMetaRequest {
+        name: Some("report.pdf".to_string()),
+        mime: Some("application/pdf".to_string()),
+        set_attributes: Some(vec![
+            ("project".to_string(), "apollo".to_string()),
+            ("kind".to_string(), "invoice".to_string()),
+        ]),
+        ..Default::default()
+    };
+    let updated: UpdateFileResult = setup
+        .directory
+        .update(owner, "update_file_meta", (report.file_id.clone(), request))
+        .unwrap();
+    assert!(matches!(updated, UpdateFileResult::Ok(_)));
+
+    let meta = match setup
+        .directory
+        .query(owner, "get_file_meta", (report.file_id.clone(),))
+        .unwrap()
+    {
+        GetFileMetaResult::Ok(meta) => meta,
+        GetFileMetaResult::Err(e) => panic!("Get file meta failed: {:?}", e),
+    };
+    assert_eq!(meta.name, "report.pdf");
+    assert_eq!(meta.mime, "application/pdf");
+    let attributes = meta.attributes.expect("attributes are set");
+    assert_eq!(
+        attributes.get("project").map(String::as_str),
+        Some("apollo")
+    );
+
+    // 2. Attributes filter the listing
+    let list = |attributes: Vec<(&str, &str)>| -> Vec<String> {
+        let request = ListFilesRequest {
+            filter: Some(FileFilter {
+                attributes: Some(
+                    attributes
+                        .into_iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        match setup
+            .directory
+            .query(owner, "list_files_page", (request,))
+            .unwrap()
+        {
+            ListFilesPageResult::Ok(page) => page.files.into_iter().map(|f| f.name).collect(),
+            ListFilesPageResult::Err(e) => panic!("List files failed: {:?}", e),
+        }
+    };
+    assert_eq!(list(vec![("project", "apollo")]), vec!["report.pdf"]);
+    assert_eq!(
+        list(vec![("project", "apollo"), ("kind", "invoice")]),
+        vec!["report.pdf"]
+    );
+    assert!(list(vec![("project", "gemini")]).is_empty());
+
+    // 3. Removing an attribute drops the file from the filtered listing
+    let request = UpdateFileMetaRequest {
+        remove_attributes: Some(vec!["project".to_string()]),
+        ..Default::default()
+    };
+    let _: UpdateFileResult = setup
+        .directory
+        .update(owner, "update_file_meta", (report.file_id.clone(), request))
+        .unwrap();
+    assert!(list(vec![("project", "apollo")]).is_empty());
+}
@@ -0,0 +1,143 @@
+use bucket::{errors::BucketError, results::GetChunkResult};
+use candid::Principal;
+use directory::{
+    errors::DirectoryError,
+    results::{CommitUploadResult, GetDownloadPlanResult, StartUploadResult},
+};
+use ic_papi_api::PaymentType;
+use shared::types::{FileId, UploadSession};
+
+use crate::util::{PicCanisterTrait, TestSetup};
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+fn start_edit(setup: &TestSetup, file_id: &FileId, size_bytes: u64) -> UploadSession {
+    let res: StartUploadResult = setup
+        .directory
+        .update_with_cycles(
+            &setup.proxy,
+            Principal::from_slice(&[1; 29]),
+            "start_edit",
+            (file_id.clone(), size_bytes, None::<PaymentType>),
+            200_000,
+        )
+        .unwrap();
+    match res {
+        StartUploadResult::Ok(session) => session,
+        StartUploadResult::Err(e) => panic!("Start edit failed: {:?}", e),
+    }
+}
+
+fn commit_edit(setup: &TestSetup, session: &UploadSession) -> CommitUploadResult {
+    setup
+        .directory
+        .update(
+            setup.proxy.canister_id,
+            "commit_edit",
+            (session.upload_id.clone(),),
+        )
+        .unwrap()
+}
+
+fn get_chunk(setup: &TestSetup, file_id: &FileId, chunk_index: u32) -> GetChunkResult {
+    let owner = setup.proxy.canister_id;
+    let plan = match setup
+        .directory
+        .query(owner, "get_download_plan", (file_id.clone(),))
+        .unwrap()
+    {
+        GetDownloadPlanResult::Ok(plan) => plan,
+        GetDownloadPlanResult::Err(e) => panic!("Get download plan failed: {:?}", e),
+    };
+    setup
+        .bucket
+        .query(
+            owner,
+            "get_chunk",
+            (plan.auth[0].token.clone(), chunk_index),
+        )
+        .unwrap()
+}
+
+fn chunk_bytes(setup: &TestSetup, file_id: &FileId, chunk_index: u32) -> Vec<u8> {
+    match get_chunk(setup, file_id, chunk_index) {
+        GetChunkResult::Ok(bytes) => bytes,
+        GetChunkResult::Err(e) => panic!("Get chunk failed: {:?}", e),
+    }
+}
+
+#[test]
+fn test_edit_file_in_place() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    let _: Result<(), DirectoryError> = setup
+        .directory
+        .update(Principal::anonymous(), "admin_set_quota", (owner, 1024u64))
+        .unwrap();
+
+    let meta = setup.upload_file("notes.txt", b"hello", None);
+    let session = start_edit(&setup, &meta.file_id, 11);
+
+    // 1. The rewritten last chunk is required, and regular commits are refused
+    let res = commit_edit(&setup, &session);
+    assert!(matches!(
+        res,
+        CommitUploadResult::Err(DirectoryError::UploadIncomplete {
+            uploaded: 0,
+            expected: 1
+        })
+    ));
+    let res: CommitUploadResult = setup
+        .directory
+        .update(owner, "commit_upload", (session.upload_id.clone(),))
+        .unwrap();
+    assert!(matches!(
+        res,
+        CommitUploadResult::Err(DirectoryError::InvalidRequest(_))
+    ));
+
+    // 2. Readers keep the old content until the commit
+    setup.put_chunks(&session, &[(0, b"hello world")]);
+    assert_eq!(chunk_bytes(&setup, &meta.file_id, 0), b"hello");
+
+    let edited = match commit_edit(&setup, &session) {
+        CommitUploadResult::Ok(edited) => edited,
+        CommitUploadResult::Err(e) => panic!("Commit edit failed: {:?}", e),
+    };
+    assert_eq!(edited.file_id, meta.file_id);
+    assert_eq!(edited.size_bytes, 11);
+    assert_eq!(edited.chunk_count, 1);
+    assert_eq!(chunk_bytes(&setup, &meta.file_id, 0), b"hello world");
+
+    assert_eq!(setup.used_bytes(owner), 11);
+}
+
+#[test]
+fn test_edit_appends_and_truncates_chunks() {
+    let setup = TestSetup::default();
+    let data = vec![7u8; CHUNK_SIZE];
+    let meta = setup.upload_file("data.bin", &data, None);
+
+    // 1. Appending only needs the new chunk
+    let session = start_edit(&setup, &meta.file_id, CHUNK_SIZE as u64 + 3);
+    setup.put_chunks(&session, &[(1, b"abc")]);
+    let edited = match commit_edit(&setup, &session) {
+        CommitUploadResult::Ok(edited) => edited,
+        CommitUploadResult::Err(e) => panic!("Commit edit failed: {:?}", e),
+    };
+    assert_eq!(edited.chunk_count, 2);
+    assert_eq!(chunk_bytes(&setup, &meta.file_id, 0), data);
+    assert_eq!(chunk_bytes(&setup, &meta.file_id, 1), b"abc");
+
+    // 2. Shrinking back to a chunk boundary drops the tail without uploading anything
+    let session = start_edit(&setup, &meta.file_id, CHUNK_SIZE as u64);
+    let edited = match commit_edit(&setup, &session) {
+        CommitUploadResult::Ok(edited) => edited,
+        CommitUploadResult::Err(e) => panic!("Commit edit failed: {:?}", e),
+    };
+    assert_eq!(edited.chunk_count, 1);
+    assert!(matches!(
+        get_chunk(&setup, &meta.file_id, 1),
+        GetChunkResult::Err(BucketError::ChunkNotFound)
+    ));
+}
@@ -1,12 +1,25 @@
+use std::time::Duration;
+
 use bucket::results::{GetChunkResult, PutChunkResult};
 use candid::Principal;
-use directory::results::{
-    CommitUploadResult, GetDownloadPlanResult, GetUploadTokensResult, ReportChunkUploadedResult,
-    StartUploadResult,
+use directory::{
+    errors::DirectoryError,
+    results::{
+        CommitUploadResult, GetDownloadPlanResult, GetMissingChunksResult, GetUploadTokensResult,
+        ReportChunkUploadedResult, StartUploadResult,
+    },
 };
 use ic_papi_api::PaymentType;
+use shared::{
+    constants::{DAY_NS, HOUR_NS, MAX_UPLOAD_TTL_NS},
+    integrity::{chunk_sha256, content_sha256},
+    merkle,
+    types::{ChunkInfo, UploadSession},
+};
 
-use crate::util::{PicCanisterTrait, TestSetup};
+use crate::util::{started, PicCanisterTrait, TestSetup};
+
+const OCTET_STREAM: &str = "application/octet-stream";
 
 #[test]
 fn test_full_upload_flow() {
@@ -63,8 +76,8 @@ fn test_full_upload_flow() {
         .unwrap();
     assert!(matches!(put_res, PutChunkResult::Ok(10)));
 
-    // 4. Report Chunk to Directory
-    let _: ReportChunkUploadedResult = setup
+    // 4. The bucket reports the chunk to the directory; nobody else can
+    let report: ReportChunkUploadedResult = setup
         .directory
         .update(
             setup.proxy.canister_id,
@@ -72,6 +85,11 @@ fn test_full_upload_flow() {
             (session.upload_id.clone(), 0u32),
         )
         .unwrap();
+    assert!(matches!(
+        report,
+        ReportChunkUploadedResult::Err(DirectoryError::Unauthorized)
+    ));
+    setup.settle();
 
     // 5. Commit Upload
     let commit_res: CommitUploadResult = setup
@@ -121,3 +139,223 @@ fn test_full_upload_flow() {
         GetChunkResult::Err(e) => panic!("Get chunk failed: {:?}", e),
     }
 }
+
+fn commit(
+    setup: &TestSetup,
+    session: &UploadSession,
+    sha256: Option<Vec<u8>>,
+) -> CommitUploadResult {
+    setup
+        .directory
+        .update(
+            setup.proxy.canister_id,
+            "commit_upload",
+            (session.upload_id.clone(), sha256),
+        )
+        .unwrap()
+}
+
+#[test]
+fn test_commit_verifies_content() {
+    let setup = TestSetup::default();
+
+    // 1. The stored chunks must add up to the announced size
+    let session = started(setup.start_upload("short.bin", OCTET_STREAM, 10, None, None));
+    setup.put_chunks(&session, &[(0, b"hello")]);
+    assert!(matches!(
+        commit(&setup, &session, None),
+        CommitUploadResult::Err(DirectoryError::IntegrityCheckFailed(_))
+    ));
+
+    // 2. A declared hash must match the stored content
+    let data = b"0123456789";
+    let expected = content_sha256([chunk_sha256(data).as_slice()]);
+    let session =
+        started(setup.start_upload("exact.bin", OCTET_STREAM, data.len() as u64, None, None));
+    setup.put_chunks(&session, &[(0, data)]);
+    assert!(matches!(
+        commit(&setup, &session, Some(vec![0; 32])),
+        CommitUploadResult::Err(DirectoryError::IntegrityCheckFailed(_))
+    ));
+
+    // 3. Every chunk but the last must be full, even if the total size adds up
+    let full = vec![7u8; 1024 * 1024];
+    let uneven =
+        started(setup.start_upload("uneven.bin", OCTET_STREAM, 1024 * 1024 + 1, None, None));
+    setup.put_chunks(&uneven, &[(0, b"x"), (1, &full)]);
+    assert!(matches!(
+        commit(&setup, &uneven, None),
+        CommitUploadResult::Err(DirectoryError::IntegrityCheckFailed(_))
+    ));
+
+    // 4. The computed hash is recorded on the file
+    let meta = match commit(&setup, &session, Some(expected.clone())) {
+        CommitUploadResult::Ok(meta) => meta,
+        CommitUploadResult::Err(e) => panic!("Commit failed: {:?}", e),
+    };
+    assert_eq!(meta.sha256, Some(expected));
+    let meta = setup.upload_file("plain.txt", b"hello", None);
+    assert_eq!(
+        meta.sha256,
+        Some(content_sha256([chunk_sha256(b"hello").as_slice()]))
+    );
+}
+
+#[test]
+fn test_download_plan_chunk_hashes() {
+    let setup = TestSetup::default();
+    let data: Vec<u8> = (0..2 * 1024 * 1024 + 5).map(|i| (i % 251) as u8).collect();
+    let meta = setup.upload_file("big.bin", &data, None);
+    assert_eq!(meta.chunk_count, 3);
+
+    let plan = match setup
+        .directory
+        .query(
+            setup.proxy.canister_id,
+            "get_download_plan",
+            (meta.file_id.clone(), None::<u32>),
+        )
+        .unwrap()
+    {
+        GetDownloadPlanResult::Ok(plan) => plan,
+        GetDownloadPlanResult::Err(e) => panic!("Get download plan failed: {:?}", e),
+    };
+
+    // 1. Every chunk comes with its length and hash, and the root matches the file's
+    let chunks: Vec<ChunkInfo> = plan
+        .locations
+        .iter()
+        .map(|location| ChunkInfo {
+            chunk_index: location.chunk_index,
+            size_bytes: location.size_bytes.expect("chunk length"),
+            sha256: location.sha256.clone().expect("chunk hash"),
+        })
+        .collect();
+    let root = merkle::root(&chunks);
+    assert_eq!(plan.merkle_root, Some(root.clone()));
+    assert_eq!(meta.merkle_root, Some(root.clone()));
+
+    // 2. Each downloaded chunk matches its listed length and hash
+    let token = plan.auth[0].token.clone();
+    for (chunk, expected) in chunks.iter().zip(data.chunks(1024 * 1024)) {
+        let bytes = match setup
+            .bucket
+            .query(
+                Principal::anonymous(),
+                "get_chunk",
+                (token.clone(), chunk.chunk_index),
+            )
+            .unwrap()
+        {
+            GetChunkResult::Ok(bytes) => bytes,
+            GetChunkResult::Err(e) => panic!("Get chunk failed: {:?}", e),
+        };
+        assert_eq!(bytes, expected);
+        assert_eq!(chunk.size_bytes as usize, bytes.len());
+        assert_eq!(chunk.sha256, chunk_sha256(&bytes));
+    }
+
+    // 3. A list with a swapped chunk does not match the root
+    let mut swapped = chunks.clone();
+    swapped[1].sha256 = chunks[0].sha256.clone();
+    assert_ne!(merkle::root(&swapped), root);
+}
+
+#[test]
+fn test_resume_upload() {
+    let setup = TestSetup::default();
+    let data: Vec<u8> = (0..2 * 1024 * 1024 + 5).map(|i| (i % 251) as u8).collect();
+    let chunks: Vec<&[u8]> = data.chunks(1024 * 1024).collect();
+    let session =
+        started(setup.start_upload("resumed.bin", OCTET_STREAM, data.len() as u64, None, None));
+    setup.put_chunks(&session, &[(0, chunks[0]), (2, chunks[2])]);
+
+    // 1. The uploader can see which chunks are still missing; nobody else can
+    let missing = |caller: Principal| -> GetMissingChunksResult {
+        setup
+            .directory
+            .query(caller, "get_missing_chunks", (session.upload_id.clone(),))
+            .unwrap()
+    };
+    assert!(matches!(
+        missing(setup.proxy.canister_id),
+        GetMissingChunksResult::Ok(indexes) if indexes == vec![1]
+    ));
+    assert!(matches!(
+        missing(Principal::anonymous()),
+        GetMissingChunksResult::Err(DirectoryError::Unauthorized)
+    ));
+
+    // 2. The owner can keep the session alive past its original hour, within the limit
+    let extend_as = |caller: Principal, ttl_ns: u64| -> StartUploadResult {
+        setup
+            .directory
+            .update(caller, "extend_upload", (session.upload_id.clone(), ttl_ns))
+            .unwrap()
+    };
+    let extend = |ttl_ns: u64| extend_as(setup.proxy.canister_id, ttl_ns);
+    assert!(matches!(
+        extend_as(Principal::anonymous(), DAY_NS),
+        StartUploadResult::Err(DirectoryError::Unauthorized)
+    ));
+    assert!(matches!(
+        extend(MAX_UPLOAD_TTL_NS + 1),
+        StartUploadResult::Err(DirectoryError::InvalidRequest(_))
+    ));
+    let extended = match extend(2 * DAY_NS) {
+        StartUploadResult::Ok(extended) => extended,
+        StartUploadResult::Err(e) => panic!("Extend upload failed: {:?}", e),
+    };
+    assert!(extended.expires_at_ns > session.expires_at_ns);
+    setup.pic.advance_time(Duration::from_secs(2 * 3600));
+    let _: () = setup
+        .directory
+        .update(setup.proxy.canister_id, "reap_expired_uploads", ())
+        .unwrap();
+
+    // The limit counts from the start of the session, not from the last extension
+    assert!(matches!(
+        extend(MAX_UPLOAD_TTL_NS - HOUR_NS),
+        StartUploadResult::Err(DirectoryError::InvalidRequest(_))
+    ));
+
+    // 3. After a restart the client gets fresh tokens for exactly the missing chunks
+    let resume = || -> GetUploadTokensResult {
+        setup
+            .directory
+            .update(
+                setup.proxy.canister_id,
+                "resume_upload",
+                (session.upload_id.clone(),),
+            )
+            .unwrap()
+    };
+    let token = match resume() {
+        GetUploadTokensResult::Ok(tokens) => tokens[0].clone(),
+        GetUploadTokensResult::Err(e) => panic!("Resume upload failed: {:?}", e),
+    };
+    assert_eq!(token.allowed_chunks, vec![1]);
+    assert_eq!(token.expires_at, extended.expires_at_ns);
+    let res: PutChunkResult = setup
+        .bucket
+        .update_with_cycles(
+            &setup.proxy,
+            Principal::from_slice(&[1; 29]),
+            "put_chunk",
+            (token, 1u32, chunks[1].to_vec(), None::<PaymentType>),
+            100_000,
+        )
+        .unwrap();
+    assert!(matches!(res, PutChunkResult::Ok(_)));
+    setup.settle();
+
+    assert!(matches!(
+        missing(setup.proxy.canister_id),
+        GetMissingChunksResult::Ok(indexes) if indexes.is_empty()
+    ));
+    assert!(matches!(resume(), GetUploadTokensResult::Ok(tokens) if tokens.is_empty()));
+    assert!(matches!(
+        commit(&setup, &session, None),
+        CommitUploadResult::Ok(_)
+    ));
+}
@@ -0,0 +1,252 @@
+use candid::Principal;
+use directory::{
+    errors::DirectoryError,
+    results::{
+        CreateFolderResult, DeleteFileResult, DeleteFolderResult, ListFilesPageResult,
+        ListFolderResult, ResolvePathResult, UpdateFileResult, UpdateFolderResult,
+    },
+    types::{Entry, ListFilesRequest},
+};
+use shared::types::{FileMeta, FileRole, FolderId};
+
+use crate::util::{PicCanisterTrait, TestSetup};
+
+fn create_folder(setup: &TestSetup, name: &str, parent: Option<FolderId>) -> CreateFolderResult {
+    setup
+        .directory
+        .update(
+            setup.proxy.canister_id,
+            "create_folder",
+            (name.to_string(), parent),
+        )
+        .unwrap()
+}
+
+#[test]
+fn test_folder_tree() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+
+    // 1. Create a folder and upload a file into it
+    let docs = match create_folder(&setup, "docs", None) {
+        CreateFolderResult::Ok(f) => f.folder_id,
+        CreateFolderResult::Err(e) => panic!("Create folder failed: {:?}", e),
+    };
+    let in_docs = setup.upload_file("a.txt", b"hello", Some(docs.clone()));
+    assert_eq!(in_docs.parent, Some(docs.clone()));
+
+    // 2. Names are unique within a folder
+    assert!(matches!(
+        create_folder(&setup, "docs", None),
+        CreateFolderResult::Err(DirectoryError::NameAlreadyExists(_))
+    ));
+
+    let at_root = setup.upload_file("a.txt", b"world", None);
+    let move_res: UpdateFileResult = setup
+        .directory
+        .update(
+            owner,
+            "move_file",
+            (at_root.file_id.clone(), Some(docs.clone())),
+        )
+        .unwrap();
+    assert!(matches!(
+        move_res,
+        UpdateFileResult::Err(DirectoryError::NameAlreadyExists(_))
+    ));
+
+    // 3. Rename, then move
+    let rename_res: UpdateFileResult = setup
+        .directory
+        .update(
+            owner,
+            "rename_file",
+            (at_root.file_id.clone(), "b.txt".to_string()),
+        )
+        .unwrap();
+    assert!(matches!(rename_res, UpdateFileResult::Ok(ref m) if m.name == "b.txt"));
+
+    let move_res: UpdateFileResult = setup
+        .directory
+        .update(
+            owner,
+            "move_file",
+            (at_root.file_id.clone(), Some(docs.clone())),
+        )
+        .unwrap();
+    assert!(matches!(move_res, UpdateFileResult::Ok(_)));
+
+    let files: Vec<FileMeta> = setup
+        .directory
+        .query(owner, "list_files", (Some(docs.clone()),))
+        .unwrap();
+    let names: Vec<String> = files.into_iter().map(|f| f.name).collect();
+    assert_eq!(names, vec!["a.txt".to_string(), "b.txt".to_string()]);
+
+    // 4. Paths resolve through the tree
+    let path_res: ResolvePathResult = setup
+        .directory
+        .query(owner, "resolve_path", ("/docs/b.txt".to_string(),))
+        .unwrap();
+    match path_res {
+        ResolvePathResult::Ok(Entry::File(file_id)) => assert_eq!(file_id, at_root.file_id),
+        other => panic!("Unexpected path resolution: {:?}", other),
+    }
+
+    // 5. The root listing only shows the folder
+    let root_res: ListFolderResult = setup
+        .directory
+        .query(owner, "list_folder", (None::<FolderId>,))
+        .unwrap();
+    match root_res {
+        ListFolderResult::Ok(listing) => {
+            assert!(listing.files.is_empty());
+            assert_eq!(listing.folders.len(), 1);
+            assert_eq!(listing.folders[0].name, "docs");
+        }
+        ListFolderResult::Err(e) => panic!("List folder failed: {:?}", e),
+    }
+
+    // 6. Non-empty folders cannot be deleted
+    let delete_res: DeleteFolderResult = setup
+        .directory
+        .update(owner, "delete_folder", (docs.clone(),))
+        .unwrap();
+    assert!(matches!(
+        delete_res,
+        DeleteFolderResult::Err(DirectoryError::FolderNotEmpty)
+    ));
+}
+
+#[test]
+fn test_move_folder_into_itself() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+
+    let parent = match create_folder(&setup, "parent", None) {
+        CreateFolderResult::Ok(f) => f.folder_id,
+        CreateFolderResult::Err(e) => panic!("Create folder failed: {:?}", e),
+    };
+    let child = match create_folder(&setup, "child", Some(parent.clone())) {
+        CreateFolderResult::Ok(f) => f.folder_id,
+        CreateFolderResult::Err(e) => panic!("Create folder failed: {:?}", e),
+    };
+
+    let move_res: UpdateFolderResult = setup
+        .directory
+        .update(owner, "move_folder", (parent.clone(), Some(child.clone())))
+        .unwrap();
+    assert!(matches!(
+        move_res,
+        UpdateFolderResult::Err(DirectoryError::InvalidRequest(_))
+    ));
+
+    // Moving the child to the root works and empties the parent
+    let move_res: UpdateFolderResult = setup
+        .directory
+        .update(owner, "move_folder", (child, None::<FolderId>))
+        .unwrap();
+    assert!(matches!(move_res, UpdateFolderResult::Ok(_)));
+
+    let delete_res: DeleteFolderResult = setup
+        .directory
+        .update(owner, "delete_folder", (parent,))
+        .unwrap();
+    assert!(matches!(delete_res, DeleteFolderResult::Ok));
+}
+
+#[test]
+fn test_folder_access_is_inherited() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    let viewer = Principal::from_slice(&[2; 29]);
+    let editor = Principal::from_slice(&[3; 29]);
+
+    let docs = match create_folder(&setup, "docs", None) {
+        CreateFolderResult::Ok(f) => f.folder_id,
+        CreateFolderResult::Err(e) => panic!("Create folder failed: {:?}", e),
+    };
+    let year = match create_folder(&setup, "2024", Some(docs.clone())) {
+        CreateFolderResult::Ok(f) => f.folder_id,
+        CreateFolderResult::Err(e) => panic!("Create folder failed: {:?}", e),
+    };
+    let report = setup.upload_file("report.txt", b"hello", Some(year.clone()));
+    for (principal, role) in [(viewer, FileRole::Reader), (editor, FileRole::Writer)] {
+        let res: Result<(), DirectoryError> = setup
+            .directory
+            .update(owner, "add_folder_access", (docs.clone(), principal, role))
+            .unwrap();
+        assert!(res.is_ok());
+    }
+
+    // 1. Grants reach files in subfolders, including files added later
+    let notes = setup.upload_file("notes.txt", b"world", Some(year.clone()));
+    assert!(setup.can_read(viewer, &report.file_id));
+    assert!(setup.can_read(viewer, &notes.file_id));
+    let files: Vec<FileMeta> = setup.directory.query(viewer, "list_files", ()).unwrap();
+    assert_eq!(files.len(), 2);
+    let listing = match setup
+        .directory
+        .query(viewer, "list_folder", (Some(year.clone()),))
+        .unwrap()
+    {
+        ListFolderResult::Ok(listing) => listing,
+        ListFolderResult::Err(e) => panic!("List folder failed: {:?}", e),
+    };
+    assert_eq!(listing.files.len(), 2);
+    let files: Vec<FileMeta> = setup
+        .directory
+        .query(viewer, "list_files", (Some(year.clone()),))
+        .unwrap();
+    assert_eq!(files.len(), 2);
+    let request = ListFilesRequest {
+        parent: Some(year.clone()),
+        ..Default::default()
+    };
+    let page: ListFilesPageResult = setup
+        .directory
+        .query(viewer, "list_files_page", (request,))
+        .unwrap();
+    assert!(matches!(page, ListFilesPageResult::Ok(page) if page.files.len() == 2));
+
+    let res: DeleteFileResult = setup
+        .directory
+        .update(viewer, "delete_file", (notes.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(
+        res,
+        DeleteFileResult::Err(DirectoryError::Unauthorized)
+    ));
+
+    // 2. A grant on the file overrides the inherited role
+    let res: Result<(), DirectoryError> = setup
+        .directory
+        .update(
+            owner,
+            "add_file_access",
+            (report.file_id.clone(), editor, FileRole::Reader),
+        )
+        .unwrap();
+    assert!(res.is_ok());
+    let res: DeleteFileResult = setup
+        .directory
+        .update(editor, "delete_file", (report.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(
+        res,
+        DeleteFileResult::Err(DirectoryError::Unauthorized)
+    ));
+    let res: DeleteFileResult = setup
+        .directory
+        .update(editor, "delete_file", (notes.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(res, DeleteFileResult::Ok));
+
+    // 3. Revoking the folder grant revokes the inherited access
+    let res: Result<(), DirectoryError> = setup
+        .directory
+        .update(owner, "remove_folder_access", (docs.clone(), viewer))
+        .unwrap();
+    assert!(res.is_ok());
+    assert!(!setup.can_read(viewer, &report.file_id));
+}
@@ -0,0 +1,96 @@
+use candid::Principal;
+use directory::{
+    errors::DirectoryError,
+    results::{CreateGroupResult, GetDownloadPlanResult, GetFileMetaResult, UpdateGroupResult},
+    types::{Group, UpdateGroupRequest},
+};
+use shared::types::{FileMeta, FileRole};
+
+use crate::util::{PicCanisterTrait, TestSetup};
+
+#[test]
+fn test_group_access() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    let member = Principal::from_slice(&[2; 29]);
+    let outsider = Principal::from_slice(&[3; 29]);
+
+    let meta = setup.upload_file("plan.txt", b"hello", None);
+    let group = match setup
+        .directory
+        .update(owner, "create_group", ("team".to_string(), vec![member]))
+        .unwrap()
+    {
+        CreateGroupResult::Ok(group) => group,
+        CreateGroupResult::Err(e) => panic!("Create group failed: {:?}", e),
+    };
+
+    // 1. Only the group owner can share a file with the group
+    let res: Result<(), DirectoryError> = setup
+        .directory
+        .update(
+            member,
+            "add_group_access",
+            (
+                meta.file_id.clone(),
+                group.group_id.clone(),
+                FileRole::Reader,
+            ),
+        )
+        .unwrap();
+    assert!(matches!(res, Err(DirectoryError::Unauthorized)));
+    let res: Result<(), DirectoryError> = setup
+        .directory
+        .update(
+            owner,
+            "add_group_access",
+            (
+                meta.file_id.clone(),
+                group.group_id.clone(),
+                FileRole::Reader,
+            ),
+        )
+        .unwrap();
+    assert!(res.is_ok());
+
+    // 2. Members can read the file and see it in their listing
+    assert!(setup.can_read(member, &meta.file_id));
+    assert!(!setup.can_read(outsider, &meta.file_id));
+    let files: Vec<FileMeta> = setup.directory.query(member, "list_files", ()).unwrap();
+    assert_eq!(files.len(), 1);
+    let plan: GetDownloadPlanResult = setup
+        .directory
+        .query(member, "get_download_plan", (meta.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(plan, GetDownloadPlanResult::Ok(_)));
+
+    // 3. Membership changes apply at once
+    let request = UpdateGroupRequest {
+        add_members: Some(vec![outsider]),
+        remove_members: Some(vec![member]),
+        ..Default::default()
+    };
+    let res: UpdateGroupResult = setup
+        .directory
+        .update(owner, "update_group", (group.group_id.clone(), request))
+        .unwrap();
+    assert!(matches!(res, UpdateGroupResult::Ok(ref g) if g.members == vec![outsider]));
+    assert!(!setup.can_read(member, &meta.file_id));
+    assert!(setup.can_read(outsider, &meta.file_id));
+
+    // 4. Deleting the group revokes its access
+    let res: Result<(), DirectoryError> = setup
+        .directory
+        .update(owner, "delete_group", (group.group_id.clone(),))
+        .unwrap();
+    assert!(res.is_ok());
+    assert!(!setup.can_read(outsider, &meta.file_id));
+    let groups: Vec<Group> = setup.directory.query(owner, "list_groups", ()).unwrap();
+    assert!(groups.is_empty());
+
+    let meta: GetFileMetaResult = setup
+        .directory
+        .query(owner, "get_file_meta", (meta.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(meta, GetFileMetaResult::Ok(m) if m.reader_groups.is_none()));
+}
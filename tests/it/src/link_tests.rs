@@ -1,10 +1,15 @@
 use bucket::results::{GetChunkResult, PutChunkResult};
 use candid::Principal;
-use directory::results::{
-    CommitUploadResult, CreateShareLinkResult, GetUploadTokensResult, ReportChunkUploadedResult,
-    ResolveShareLinkResult, StartUploadResult,
+use directory::{
+    errors::DirectoryError,
+    results::{
+        BrowseShareLinkResult, CommitUploadResult, CreateFolderResult, CreateShareLinkResult,
+        GetUploadTokensResult, ListShareLinksResult, ResolveShareLinkResult, StartUploadResult,
+    },
+    types::{BrowseShareLinkRequest, ShareLinkOptions, SharedFolderPage},
 };
 use ic_papi_api::PaymentType;
+use shared::types::{FileId, FolderId};
 
 use crate::util::{PicCanisterTrait, TestSetup};
 
@@ -60,14 +65,7 @@ fn test_link_generation_and_access() {
         )
         .unwrap();
 
-    let _: ReportChunkUploadedResult = setup
-        .directory
-        .update(
-            setup.proxy.canister_id,
-            "report_chunk_uploaded",
-            (session.upload_id.clone(), 0u32),
-        )
-        .unwrap();
+    setup.settle();
 
     let commit_res: CommitUploadResult = setup
         .directory
@@ -102,7 +100,7 @@ fn test_link_generation_and_access() {
     // 3. Resolve Share Link (Anonymous)
     let plan_res: ResolveShareLinkResult = setup
         .directory
-        .query(anon, "resolve_share_link", (link_token.clone(),))
+        .update(anon, "resolve_share_link", (link_token.clone(),))
         .unwrap();
     let plan = match plan_res {
         ResolveShareLinkResult::Ok(p) => p,
@@ -135,10 +133,194 @@ fn test_link_generation_and_access() {
     // 6. Verify link no longer resolves
     let plan_res_fail: ResolveShareLinkResult = setup
         .directory
-        .query(anon, "resolve_share_link", (link_token,))
+        .update(anon, "resolve_share_link", (link_token,))
         .unwrap();
     assert!(matches!(
         plan_res_fail,
         ResolveShareLinkResult::Err(directory::errors::DirectoryError::LinkNotFound)
     ));
 }
+
+#[test]
+fn test_link_limits_and_password() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    let anon = Principal::anonymous();
+    let meta = setup.upload_file("report.pdf", b"hello", None);
+
+    let options = ShareLinkOptions {
+        label: Some("press".to_string()),
+        max_uses: Some(2),
+        password: Some("hunter2".to_string()),
+    };
+    let link = match setup
+        .directory
+        .update(
+            owner,
+            "create_share_link",
+            (
+                meta.file_id.clone(),
+                3_600_000_000_000u64,
+                None::<u32>,
+                Some(options),
+            ),
+        )
+        .unwrap()
+    {
+        CreateShareLinkResult::Ok(link) => link,
+        CreateShareLinkResult::Err(e) => panic!("Create link failed: {:?}", e),
+    };
+    let resolve = |password: Option<&str>| -> ResolveShareLinkResult {
+        setup
+            .directory
+            .update(
+                anon,
+                "resolve_share_link",
+                (link.clone(), password.map(str::to_string)),
+            )
+            .unwrap()
+    };
+
+    // 1. The password is required
+    assert!(matches!(
+        resolve(None),
+        ResolveShareLinkResult::Err(DirectoryError::InvalidLinkPassword)
+    ));
+    assert!(matches!(
+        resolve(Some("hunter3")),
+        ResolveShareLinkResult::Err(DirectoryError::InvalidLinkPassword)
+    ));
+
+    // 2. Each resolution counts until the cap is reached
+    assert!(matches!(
+        resolve(Some("hunter2")),
+        ResolveShareLinkResult::Ok(_)
+    ));
+    assert!(matches!(
+        resolve(Some("hunter2")),
+        ResolveShareLinkResult::Ok(_)
+    ));
+    assert!(matches!(
+        resolve(Some("hunter2")),
+        ResolveShareLinkResult::Err(DirectoryError::LinkExhausted)
+    ));
+
+    // 3. Owners see the link and its stats, other principals cannot list
+    let links = match setup
+        .directory
+        .query(owner, "list_share_links", (meta.file_id.clone(),))
+        .unwrap()
+    {
+        ListShareLinksResult::Ok(links) => links,
+        ListShareLinksResult::Err(e) => panic!("List links failed: {:?}", e),
+    };
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].token, link);
+    assert_eq!(links[0].label.as_deref(), Some("press"));
+    assert_eq!(links[0].created_by, Some(owner));
+    assert!(links[0].password_protected);
+    assert_eq!(links[0].use_count, 2);
+    assert!(links[0].last_used_at_ns.is_some());
+
+    let listed: ListShareLinksResult = setup
+        .directory
+        .query(anon, "list_share_links", (meta.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(
+        listed,
+        ListShareLinksResult::Err(DirectoryError::Unauthorized)
+    ));
+}
+
+#[test]
+fn test_folder_link() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    let anon = Principal::anonymous();
+    let create_folder = |name: &str, parent: Option<FolderId>| match setup
+        .directory
+        .update(owner, "create_folder", (name.to_string(), parent))
+        .unwrap()
+    {
+        CreateFolderResult::Ok(folder) => folder.folder_id,
+        CreateFolderResult::Err(e) => panic!("Create folder failed: {:?}", e),
+    };
+
+    let photos = create_folder("photos", None);
+    let trip = create_folder("trip", Some(photos.clone()));
+    let a = setup.upload_file("a.jpg", b"a", Some(trip.clone()));
+    setup.upload_file("b.jpg", b"b", Some(trip.clone()));
+    let outside = setup.upload_file("taxes.pdf", b"private", None);
+
+    let link = match setup
+        .directory
+        .update(
+            owner,
+            "create_folder_share_link",
+            (
+                photos.clone(),
+                3_600_000_000_000u64,
+                None::<ShareLinkOptions>,
+            ),
+        )
+        .unwrap()
+    {
+        CreateShareLinkResult::Ok(link) => link,
+        CreateShareLinkResult::Err(e) => panic!("Create link failed: {:?}", e),
+    };
+    let browse = |request: BrowseShareLinkRequest| -> SharedFolderPage {
+        match setup
+            .directory
+            .update(anon, "browse_share_link", (link.clone(), request))
+            .unwrap()
+        {
+            BrowseShareLinkResult::Ok(page) => page,
+            BrowseShareLinkResult::Err(e) => panic!("Browse link failed: {:?}", e),
+        }
+    };
+    let resolve = |file_id: Option<FileId>| -> ResolveShareLinkResult {
+        setup
+            .directory
+            .update(
+                anon,
+                "resolve_share_link",
+                (link.clone(), None::<String>, file_id),
+            )
+            .unwrap()
+    };
+
+    // 1. Holders browse the linked folder and its subfolders, one page at a time
+    let page = browse(BrowseShareLinkRequest::default());
+    assert_eq!(page.folders.len(), 1);
+    assert!(page.files.is_empty());
+    assert!(page.next_cursor.is_none());
+
+    let page = browse(BrowseShareLinkRequest {
+        folder: Some(trip.clone()),
+        limit: Some(1),
+        ..Default::default()
+    });
+    assert_eq!(page.files[0].name, "a.jpg");
+    let page = browse(BrowseShareLinkRequest {
+        folder: Some(trip.clone()),
+        limit: Some(1),
+        cursor: page.next_cursor,
+        ..Default::default()
+    });
+    assert_eq!(page.files[0].name, "b.jpg");
+    assert!(page.next_cursor.is_none());
+
+    // 2. Files under the folder can be downloaded, files outside it cannot
+    assert!(matches!(
+        resolve(Some(a.file_id.clone())),
+        ResolveShareLinkResult::Ok(_)
+    ));
+    assert!(matches!(
+        resolve(Some(outside.file_id.clone())),
+        ResolveShareLinkResult::Err(DirectoryError::FileNotFound)
+    ));
+    assert!(matches!(
+        resolve(None),
+        ResolveShareLinkResult::Err(DirectoryError::InvalidRequest(_))
+    ));
+}
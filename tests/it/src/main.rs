@@ -3,12 +3,34 @@ mod acl_tests;
 #[cfg(test)]
 mod bucket_tests;
 #[cfg(test)]
+mod copy_tests;
+#[cfg(test)]
 mod directory_tests;
 #[cfg(test)]
+mod edit_tests;
+#[cfg(test)]
 mod flow_tests;
 #[cfg(test)]
+mod folder_tests;
+#[cfg(test)]
+mod group_tests;
+#[cfg(test)]
 mod link_tests;
+#[cfg(test)]
+mod org_tests;
+#[cfg(test)]
+mod tag_tests;
+#[cfg(test)]
+mod transfer_tests;
+#[cfg(test)]
+mod trash_tests;
+#[cfg(test)]
+mod upload_request_tests;
 mod util;
+#[cfg(test)]
+mod version_tests;
+#[cfg(test)]
+mod visibility_tests;
 
 fn main() {
     println!("Run tests with 'cargo test'");
@@ -0,0 +1,100 @@
+use candid::Principal;
+use directory::{
+    errors::DirectoryError,
+    results::{CreateOrganizationResult, GetFileMetaResult, StartUploadResult},
+    types::{OrgRole, Organization},
+};
+
+use crate::util::{PicCanisterTrait, TestSetup};
+
+#[test]
+fn test_organization_uploads_share_one_account() {
+    let setup = TestSetup::default();
+    let admin = setup.proxy.canister_id;
+    let member = Principal::from_slice(&[2; 29]);
+    let billing = Principal::from_slice(&[3; 29]);
+
+    let org = match setup
+        .directory
+        .update(admin, "create_organization", ("acme".to_string(),))
+        .unwrap()
+    {
+        CreateOrganizationResult::Ok(org) => org,
+        CreateOrganizationResult::Err(e) => panic!("Create organization failed: {:?}", e),
+    };
+    let org_id = org.org_id;
+    for (principal, role) in [(member, OrgRole::Member), (billing, OrgRole::Billing)] {
+        let res: Result<(), DirectoryError> = setup
+            .directory
+            .update(admin, "set_org_member", (org_id, principal, role))
+            .unwrap();
+        assert!(res.is_ok());
+    }
+    let _: Result<(), DirectoryError> = setup
+        .directory
+        .update(Principal::anonymous(), "admin_set_quota", (org_id, 8u64))
+        .unwrap();
+
+    // 1. Uploads on the organization's behalf belong to it and use its quota
+    let start_res = setup.start_upload("notes.txt", "text/plain", 5, Some(org_id), None);
+    let meta = setup.finish_upload(start_res, b"hello");
+    assert_eq!(meta.file_id.owner, org_id);
+    assert_eq!(setup.used_bytes(org_id), 5);
+    assert_eq!(setup.used_bytes(admin), 0);
+
+    let res = setup.start_upload("more.txt", "text/plain", 5, Some(org_id), None);
+    assert!(matches!(
+        res,
+        StartUploadResult::Err(DirectoryError::QuotaExceeded {
+            used: 5,
+            requested: 5,
+            quota: 8
+        })
+    ));
+
+    // 2. Members can use the organization's files; billing members cannot
+    let res: GetFileMetaResult = setup
+        .directory
+        .query(member, "get_file_meta", (meta.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(res, GetFileMetaResult::Ok(_)));
+    let res: GetFileMetaResult = setup
+        .directory
+        .query(billing, "get_file_meta", (meta.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(
+        res,
+        GetFileMetaResult::Err(DirectoryError::Unauthorized)
+    ));
+    let orgs: Vec<Organization> = setup
+        .directory
+        .query(member, "list_organizations", ())
+        .unwrap();
+    assert_eq!(orgs.len(), 1);
+
+    // 3. Only admins manage members, and the last admin cannot leave
+    let res: Result<(), DirectoryError> = setup
+        .directory
+        .update(member, "set_org_member", (org_id, member, OrgRole::Admin))
+        .unwrap();
+    assert!(matches!(res, Err(DirectoryError::Unauthorized)));
+    let res: Result<(), DirectoryError> = setup
+        .directory
+        .update(admin, "remove_org_member", (org_id, admin))
+        .unwrap();
+    assert!(matches!(res, Err(DirectoryError::InvalidRequest(_))));
+
+    let res: Result<(), DirectoryError> = setup
+        .directory
+        .update(admin, "remove_org_member", (org_id, member))
+        .unwrap();
+    assert!(res.is_ok());
+    let res: GetFileMetaResult = setup
+        .directory
+        .query(member, "get_file_meta", (meta.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(
+        res,
+        GetFileMetaResult::Err(DirectoryError::Unauthorized)
+    ));
+}
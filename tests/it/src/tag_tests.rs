@@ -0,0 +1,127 @@
+use candid::Principal;
+use directory::{
+    results::{ListFilesPageResult, UpdateFileResult},
+    types::{FilePage, SearchFilesRequest, TagExpr},
+};
+use shared::types::{FileId, FileRole};
+
+use crate::util::{PicCanisterTrait, TestSetup};
+
+fn tag(setup: &TestSetup, file_id: &FileId, tags: &[&str]) {
+    let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+    let res: UpdateFileResult = setup
+        .directory
+        .update(
+            setup.proxy.canister_id,
+            "add_file_tags",
+            (file_id.clone(), tags),
+        )
+        .unwrap();
+    assert!(matches!(res, UpdateFileResult::Ok(_)), "{:?}", res);
+}
+
+fn search(setup: &TestSetup, caller: Principal, request: SearchFilesRequest) -> FilePage {
+    match setup
+        .directory
+        .query(caller, "search_files", (request,))
+        .unwrap()
+    {
+        ListFilesPageResult::Ok(page) => page,
+        ListFilesPageResult::Err(e) => panic!("Search failed: {:?}", e),
+    }
+}
+
+fn names(page: &FilePage) -> Vec<&str> {
+    page.files.iter().map(|f| f.name.as_str()).collect()
+}
+
+fn tag_expr(tag: &str) -> TagExpr {
+    TagExpr::Tag(tag.to_string())
+}
+
+#[test]
+fn test_tag_search() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+
+    let invoice = setup.upload_file("invoice-march.pdf", b"data", None);
+    let receipt = setup.upload_file("receipt-march.pdf", b"data", None);
+    let photo = setup.upload_file("photo.jpg", b"data", None);
+    tag(&setup, &invoice.file_id, &["finance", "2024"]);
+    tag(&setup, &receipt.file_id, &["finance", "2023"]);
+    tag(&setup, &photo.file_id, &["2024"]);
+
+    // AND
+    let page = search(
+        &setup,
+        owner,
+        SearchFilesRequest {
+            tags: Some(TagExpr::And(vec![tag_expr("finance"), tag_expr("2024")])),
+            ..Default::default()
+        },
+    );
+    assert_eq!(names(&page), vec!["invoice-march.pdf"]);
+
+    // OR, two results per page
+    let request = SearchFilesRequest {
+        tags: Some(TagExpr::Or(vec![tag_expr("2023"), tag_expr("2024")])),
+        limit: Some(2),
+        ..Default::default()
+    };
+    let first = search(&setup, owner, request.clone());
+    assert_eq!(names(&first), vec!["invoice-march.pdf", "photo.jpg"]);
+    let second = search(
+        &setup,
+        owner,
+        SearchFilesRequest {
+            cursor: first.next_cursor,
+            ..request
+        },
+    );
+    assert_eq!(names(&second), vec!["receipt-march.pdf"]);
+    assert!(second.next_cursor.is_none());
+
+    // Tags combined with a name prefix
+    let page = search(
+        &setup,
+        owner,
+        SearchFilesRequest {
+            tags: Some(tag_expr("finance")),
+            name_prefix: Some("receipt".to_string()),
+            ..Default::default()
+        },
+    );
+    assert_eq!(names(&page), vec!["receipt-march.pdf"]);
+}
+
+#[test]
+fn test_tag_search_respects_access() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    let viewer = Principal::from_slice(&[2; 29]);
+
+    let shared = setup.upload_file("shared.txt", b"data", None);
+    let private = setup.upload_file("private.txt", b"data", None);
+    tag(&setup, &shared.file_id, &["team"]);
+    tag(&setup, &private.file_id, &["team"]);
+
+    let _: Result<(), directory::errors::DirectoryError> = setup
+        .directory
+        .update(
+            owner,
+            "add_file_access",
+            (shared.file_id.clone(), viewer, FileRole::Reader),
+        )
+        .unwrap();
+
+    let page = search(
+        &setup,
+        viewer,
+        SearchFilesRequest {
+            owner: Some(owner),
+            tags: Some(tag_expr("team")),
+            ..Default::default()
+        },
+    );
+    assert_eq!(names(&page), vec!["shared.txt"]);
+}
@@ -0,0 +1,168 @@
+use bucket::results::GetChunkResult;
+use candid::Principal;
+use directory::{
+    errors::DirectoryError,
+    results::{
+        CommitUploadResult, CreateShareLinkResult, GetFileMetaResult, ListVersionsResult,
+        ResolveShareLinkResult, StartUploadResult, UpdateFileResult,
+    },
+    types::TransferOffer,
+};
+use ic_papi_api::PaymentType;
+use shared::types::{FileId, FileRole, FolderId};
+
+use crate::util::{PicCanisterTrait, TestSetup};
+
+#[test]
+fn test_transfer_file_ownership() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    let recipient = Principal::from_slice(&[2; 29]);
+    let reader = Principal::from_slice(&[3; 29]);
+    for user in [owner, recipient] {
+        let _: Result<(), DirectoryError> = setup
+            .directory
+            .update(Principal::anonymous(), "admin_set_quota", (user, 1024u64))
+            .unwrap();
+    }
+
+    let meta = setup.upload_file("report.pdf", b"hello", None);
+    setup.upload_version(&meta.file_id, b"hello!!");
+    assert_eq!(setup.used_bytes(owner), 12);
+
+    let link = match setup
+        .directory
+        .update(
+            owner,
+            "create_share_link",
+            (meta.file_id.clone(), 3_600_000_000_000u64),
+        )
+        .unwrap()
+    {
+        CreateShareLinkResult::Ok(link) => link,
+        CreateShareLinkResult::Err(e) => panic!("Create link failed: {:?}", e),
+    };
+    let _: Result<(), DirectoryError> = setup
+        .directory
+        .update(
+            owner,
+            "add_file_access",
+            (meta.file_id.clone(), reader, FileRole::Reader),
+        )
+        .unwrap();
+
+    // 1. The owner offers the file; only the recipient can accept
+    let offered: Result<(), DirectoryError> = setup
+        .directory
+        .update(
+            owner,
+            "offer_file_transfer",
+            (meta.file_id.clone(), recipient),
+        )
+        .unwrap();
+    assert!(offered.is_ok());
+    let offers: Vec<TransferOffer> = setup
+        .directory
+        .query(recipient, "list_file_transfers", ())
+        .unwrap();
+    assert_eq!(offers.len(), 1);
+    let edit = match setup
+        .directory
+        .update_with_cycles(
+            &setup.proxy,
+            Principal::from_slice(&[1; 29]),
+            "start_edit",
+            (meta.file_id.clone(), 7u64, None::<PaymentType>),
+            200_000,
+        )
+        .unwrap()
+    {
+        StartUploadResult::Ok(session) => session,
+        StartUploadResult::Err(e) => panic!("Start edit failed: {:?}", e),
+    };
+
+    let res: UpdateFileResult = setup
+        .directory
+        .update(
+            reader,
+            "accept_file_transfer",
+            (meta.file_id.clone(), None::<FolderId>),
+        )
+        .unwrap();
+    assert!(matches!(
+        res,
+        UpdateFileResult::Err(DirectoryError::Unauthorized)
+    ));
+
+    let moved = match setup
+        .directory
+        .update(
+            recipient,
+            "accept_file_transfer",
+            (meta.file_id.clone(), None::<FolderId>),
+        )
+        .unwrap()
+    {
+        UpdateFileResult::Ok(moved) => moved,
+        UpdateFileResult::Err(e) => panic!("Accept transfer failed: {:?}", e),
+    };
+    let new_id = FileId {
+        owner: recipient,
+        id: meta.file_id.id.clone(),
+    };
+    assert_eq!(moved.file_id, new_id);
+    assert_eq!(moved.readers, vec![reader]);
+
+    // The edit opened under the old id was aborted
+    let res: CommitUploadResult = setup
+        .directory
+        .update(owner, "commit_edit", (edit.upload_id,))
+        .unwrap();
+    assert!(matches!(
+        res,
+        CommitUploadResult::Err(DirectoryError::UploadSessionNotFound)
+    ));
+
+    // 2. Usage, including the version history, moved to the recipient
+    assert_eq!(setup.used_bytes(owner), 0);
+    assert_eq!(setup.used_bytes(recipient), 12);
+    let versions = match setup
+        .directory
+        .query(recipient, "list_versions", (new_id.clone(),))
+        .unwrap()
+    {
+        ListVersionsResult::Ok(versions) => versions,
+        ListVersionsResult::Err(e) => panic!("List versions failed: {:?}", e),
+    };
+    assert_eq!(versions.len(), 2);
+
+    // 3. The old id is gone, but the share link still serves the current content
+    let old: GetFileMetaResult = setup
+        .directory
+        .query(owner, "get_file_meta", (meta.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(
+        old,
+        GetFileMetaResult::Err(DirectoryError::FileNotFound)
+    ));
+
+    let plan = match setup
+        .directory
+        .update(owner, "resolve_share_link", (link,))
+        .unwrap()
+    {
+        ResolveShareLinkResult::Ok(plan) => plan,
+        ResolveShareLinkResult::Err(e) => panic!("Resolve link failed: {:?}", e),
+    };
+    let chunk: GetChunkResult = setup
+        .bucket
+        .query(owner, "get_chunk", (plan.auth[0].token.clone(), 0u32))
+        .unwrap();
+    assert!(matches!(chunk, GetChunkResult::Ok(bytes) if bytes == b"hello!!"));
+
+    let offers: Vec<TransferOffer> = setup
+        .directory
+        .query(recipient, "list_file_transfers", ())
+        .unwrap();
+    assert!(offers.is_empty());
+}
@@ -0,0 +1,203 @@
+use std::time::Duration;
+
+use bucket::{errors::BucketError, results::GetChunkResult};
+use candid::Principal;
+use directory::{
+    errors::DirectoryError,
+    results::{
+        CreateShareLinkResult, DeleteFileResult, GetDownloadPlanResult, ListPendingDeletesResult,
+        ResolveShareLinkResult, UpdateFileResult,
+    },
+    types::PendingDelete,
+};
+use shared::types::{FileMeta, FileStatus};
+
+use crate::util::{PicCanisterTrait, TestSetup};
+
+#[test]
+fn test_trash_and_restore() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+
+    let meta = setup.upload_file("notes.txt", b"data", None);
+    let link: CreateShareLinkResult = setup
+        .directory
+        .update(
+            owner,
+            "create_share_link",
+            (meta.file_id.clone(), 3_600_000_000_000u64),
+        )
+        .unwrap();
+    let CreateShareLinkResult::Ok(link) = link else {
+        panic!("Create link failed: {:?}", link);
+    };
+
+    // 1. Deleting moves the file to the trash
+    let deleted: DeleteFileResult = setup
+        .directory
+        .update(owner, "delete_file", (meta.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(deleted, DeleteFileResult::Ok));
+
+    let files: Vec<FileMeta> = setup.directory.query(owner, "list_files", ()).unwrap();
+    assert!(files.is_empty());
+    let trash: Vec<FileMeta> = setup.directory.query(owner, "list_trash", ()).unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].status, FileStatus::Deleted);
+
+    // 2. Trashed files cannot be downloaded, directly or through links
+    let plan: GetDownloadPlanResult = setup
+        .directory
+        .query(owner, "get_download_plan", (meta.file_id.clone(),))
+        .unwrap();
+    assert!(matches!(
+        plan,
+        GetDownloadPlanResult::Err(DirectoryError::FileNotFound)
+    ));
+    let resolved: ResolveShareLinkResult = setup
+        .directory
+        .update(owner, "resolve_share_link", (link,))
+        .unwrap();
+    assert!(matches!(
+        resolved,
+        ResolveShareLinkResult::Err(DirectoryError::FileNotFound)
+    ));
+
+    // 3. The name is free again, so restoring picks a numbered one
+    setup.upload_file("notes.txt", b"other", None);
+    let restored: UpdateFileResult = setup
+        .directory
+        .update(owner, "restore_file", (meta.file_id.clone(),))
+        .unwrap();
+    match restored {
+        UpdateFileResult::Ok(restored) => {
+            assert_eq!(restored.name, "notes (1).txt");
+            assert_eq!(restored.status, FileStatus::Ready);
+        }
+        UpdateFileResult::Err(e) => panic!("Restore failed: {:?}", e),
+    }
+    let trash: Vec<FileMeta> = setup.directory.query(owner, "list_trash", ()).unwrap();
+    assert!(trash.is_empty());
+}
+
+#[test]
+fn test_empty_trash_deletes_chunks() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+
+    let meta = setup.upload_file("old.bin", b"bytes", None);
+    let plan = match setup
+        .directory
+        .query(owner, "get_download_plan", (meta.file_id.clone(),))
+        .unwrap()
+    {
+        GetDownloadPlanResult::Ok(plan) => plan,
+        GetDownloadPlanResult::Err(e) => panic!("Get download plan failed: {:?}", e),
+    };
+
+    let _: DeleteFileResult = setup
+        .directory
+        .update(owner, "delete_file", (meta.file_id.clone(),))
+        .unwrap();
+    let purged: u32 = setup.directory.update(owner, "empty_trash", ()).unwrap();
+    assert_eq!(purged, 1);
+
+    let trash: Vec<FileMeta> = setup.directory.query(owner, "list_trash", ()).unwrap();
+    assert!(trash.is_empty());
+
+    // The chunks are gone from the bucket as well
+    let chunk: GetChunkResult = setup
+        .bucket
+        .query(owner, "get_chunk", (plan.auth[0].token.clone(), 0u32))
+        .unwrap();
+    assert!(matches!(
+        chunk,
+        GetChunkResult::Err(BucketError::ChunkNotFound)
+    ));
+}
+
+fn pending_deletes(setup: &TestSetup) -> Vec<PendingDelete> {
+    match setup
+        .directory
+        .query(Principal::anonymous(), "admin_list_pending_deletes", ())
+        .unwrap()
+    {
+        ListPendingDeletesResult::Ok(pending) => pending,
+        ListPendingDeletesResult::Err(e) => panic!("List pending deletes failed: {:?}", e),
+    }
+}
+
+#[test]
+fn test_failed_chunk_deletion_is_retried() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+
+    let meta = setup.upload_file("old.bin", b"bytes", None);
+    let plan = match setup
+        .directory
+        .query(owner, "get_download_plan", (meta.file_id.clone(),))
+        .unwrap()
+    {
+        GetDownloadPlanResult::Ok(plan) => plan,
+        GetDownloadPlanResult::Err(e) => panic!("Get download plan failed: {:?}", e),
+    };
+    let link: CreateShareLinkResult = setup
+        .directory
+        .update(
+            owner,
+            "create_share_link",
+            (meta.file_id.clone(), 3_600_000_000_000u64),
+        )
+        .unwrap();
+    let CreateShareLinkResult::Ok(link) = link else {
+        panic!("Create link failed: {:?}", link);
+    };
+
+    // 1. The bucket is down, so the deletion stays queued
+    setup
+        .pic
+        .stop_canister(setup.bucket.canister_id, None)
+        .unwrap();
+    let _: DeleteFileResult = setup
+        .directory
+        .update(owner, "delete_file", (meta.file_id.clone(),))
+        .unwrap();
+    let purged: u32 = setup.directory.update(owner, "empty_trash", ()).unwrap();
+    assert_eq!(purged, 1);
+
+    let pending = pending_deletes(&setup);
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].attempts, 1);
+    assert!(pending[0].last_error.is_some());
+
+    // 2. The share link went with the file
+    let resolved: ResolveShareLinkResult = setup
+        .directory
+        .update(owner, "resolve_share_link", (link,))
+        .unwrap();
+    assert!(matches!(
+        resolved,
+        ResolveShareLinkResult::Err(DirectoryError::LinkNotFound)
+    ));
+
+    // 3. Once the bucket is back and the retry delay has passed, the chunks are deleted
+    setup
+        .pic
+        .start_canister(setup.bucket.canister_id, None)
+        .unwrap();
+    setup.pic.advance_time(Duration::from_secs(120));
+    let _: () = setup
+        .directory
+        .update(owner, "process_pending_deletes", ())
+        .unwrap();
+    assert!(pending_deletes(&setup).is_empty());
+
+    let chunk: GetChunkResult = setup
+        .bucket
+        .query(owner, "get_chunk", (plan.auth[0].token.clone(), 0u32))
+        .unwrap();
+    assert!(matches!(
+        chunk,
+        GetChunkResult::Err(BucketError::ChunkNotFound)
+    ));
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use candid::Principal;
+use directory::{
+    errors::DirectoryError,
+    results::{CommitUploadResult, CreateFolderResult, CreateShareLinkResult, StartUploadResult},
+    types::{UploadRequest, UploadRequestOptions},
+};
+use shared::types::FolderId;
+
+use crate::util::{started, PicCanisterTrait, TestSetup};
+
+#[test]
+fn test_upload_request() {
+    let setup = TestSetup::default();
+    let owner = Principal::from_slice(&[2; 29]);
+
+    let inbox = match setup
+        .directory
+        .update(
+            owner,
+            "create_folder",
+            ("inbox".to_string(), None::<FolderId>),
+        )
+        .unwrap()
+    {
+        CreateFolderResult::Ok(folder) => folder.folder_id,
+        CreateFolderResult::Err(e) => panic!("Create folder failed: {:?}", e),
+    };
+    let options = UploadRequestOptions {
+        max_files: Some(1),
+        allowed_mime_types: Some(vec!["text/*".to_string()]),
+        ..Default::default()
+    };
+    let request = match setup
+        .directory
+        .update(
+            owner,
+            "create_upload_request",
+            (Some(inbox.clone()), 3_600_000_000_000u64, Some(options)),
+        )
+        .unwrap()
+    {
+        CreateShareLinkResult::Ok(token) => token,
+        CreateShareLinkResult::Err(e) => panic!("Create upload request failed: {:?}", e),
+    };
+
+    // 1. Only allowed MIME types can be uploaded
+    let res = setup.start_upload("photo.png", "image/png", 5, None, Some(&request));
+    assert!(matches!(
+        res,
+        StartUploadResult::Err(DirectoryError::InvalidRequest(_))
+    ));
+
+    // 2. The outsider's upload lands in the owner's folder and counts towards their usage
+    let res = setup.start_upload("scan.txt", "text/plain", 5, None, Some(&request));
+    let meta = setup.finish_upload(res, b"hello");
+    assert_eq!(meta.file_id.owner, owner);
+    assert_eq!(meta.parent, Some(inbox));
+    assert_eq!(setup.used_bytes(owner), 5);
+
+    // 3. The request is used up after one file
+    let res = setup.start_upload("scan2.txt", "text/plain", 5, None, Some(&request));
+    assert!(matches!(
+        res,
+        StartUploadResult::Err(DirectoryError::LinkExhausted)
+    ));
+    let requests: Vec<UploadRequest> = setup
+        .directory
+        .query(owner, "list_upload_requests", ())
+        .unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].uploaded_files, 1);
+    assert_eq!(requests[0].uploaded_bytes, 5);
+
+    // 4. Only the owner can revoke it
+    let res: Result<(), DirectoryError> = setup
+        .directory
+        .update(
+            setup.proxy.canister_id,
+            "revoke_upload_request",
+            (request.clone(),),
+        )
+        .unwrap();
+    assert!(matches!(res, Err(DirectoryError::Unauthorized)));
+    let res: Result<(), DirectoryError> = setup
+        .directory
+        .update(owner, "revoke_upload_request", (request.clone(),))
+        .unwrap();
+    assert!(res.is_ok());
+    let res = setup.start_upload("scan3.txt", "text/plain", 5, None, Some(&request));
+    assert!(matches!(
+        res,
+        StartUploadResult::Err(DirectoryError::LinkNotFound)
+    ));
+}
+
+#[test]
+fn test_upload_request_sessions() {
+    let setup = TestSetup::default();
+    let owner = Principal::from_slice(&[2; 29]);
+    let request = match setup
+        .directory
+        .update(
+            owner,
+            "create_upload_request",
+            (
+                None::<FolderId>,
+                3_600_000_000_000u64,
+                None::<UploadRequestOptions>,
+            ),
+        )
+        .unwrap()
+    {
+        CreateShareLinkResult::Ok(token) => token,
+        CreateShareLinkResult::Err(e) => panic!("Create upload request failed: {:?}", e),
+    };
+    let session = started(setup.start_upload("scan.txt", "text/plain", 5, None, Some(&request)));
+    setup.put_chunks(&session, &[(0, b"hello")]);
+
+    // 1. The upload id is random, not derived from the file id
+    assert_ne!(session.upload_id, session.file_id.id);
+    assert_eq!(session.upload_id.len(), 32);
+
+    // 2. Nobody but the uploader can commit the session
+    let commit = |caller: Principal| -> CommitUploadResult {
+        setup
+            .directory
+            .update(
+                caller,
+                "commit_upload",
+                (session.upload_id.clone(), None::<Vec<u8>>),
+            )
+            .unwrap()
+    };
+    assert!(matches!(
+        commit(Principal::anonymous()),
+        CommitUploadResult::Err(DirectoryError::Unauthorized)
+    ));
+
+    // 3. Once the request has expired, its uploads can no longer be committed
+    setup.pic.advance_time(Duration::from_secs(2 * 3600));
+    assert!(matches!(
+        commit(setup.proxy.canister_id),
+        CommitUploadResult::Err(DirectoryError::LinkExpired)
+    ));
+}
@@ -4,13 +4,23 @@ use std::{
     sync::Arc,
 };
 
-use bucket::config::{Args as BucketArgs, InitArgs as BucketInitArgs};
+use bucket::{
+    config::{Args as BucketArgs, InitArgs as BucketInitArgs},
+    results::{GetChunkResult, PutChunkResult},
+};
 use candid::{decode_one, CandidType, Deserialize, Principal};
 use directory::{
     config::{Args as DirectoryArgs, InitArgs as DirectoryInitArgs},
-    results::ProvisionBucketResult,
+    errors::DirectoryError,
+    results::{
+        CommitUploadResult, GetDownloadPlanResult, GetFileMetaResult, GetUploadTokensResult,
+        ProvisionBucketResult, StartUploadResult,
+    },
+    types::UserState,
 };
+use ic_papi_api::PaymentType;
 use pocket_ic::{PocketIc, WasmResult};
+use shared::types::{FileId, FileMeta, FolderId, UploadSession, UserId};
 
 /// Common methods for interacting with a canister using `PocketIc`.
 pub trait PicCanisterTrait {
@@ -291,17 +301,7 @@ impl Default for TestSetup {
             .with_cycles(1_000_000_000_000_000)
             .deploy_to(pic.clone());
 
-        // 2. Deploy Bucket
-        let bucket_init_args = (BucketArgs::Init(BucketInitArgs {
-            admins: vec![Principal::anonymous()],
-            shared_secret: vec![0; 32],
-        }),);
-        let bucket = PicCanisterBuilder::default()
-            .with_wasm(&PicCanister::cargo_wasm_path("bucket"))
-            .with_arg(bucket_init_args)
-            .deploy_to(pic.clone());
-
-        // 3. Deploy Directory
+        // 2. Deploy Directory
         let directory_init_args = (DirectoryArgs::Init(DirectoryInitArgs {
             rate_per_gb_per_month: 100_000_000, // 0.1 tokens per GB
             admins: vec![Principal::anonymous()],
@@ -312,6 +312,9 @@ impl Default for TestSetup {
             .with_arg(directory_init_args)
             .deploy_to(pic.clone());
 
+        // 3. Deploy Bucket
+        let bucket = deploy_bucket(pic.clone(), directory.canister_id);
+
         // 4. Provision bucket in directory
         let _: ProvisionBucketResult = directory
             .update(
@@ -329,3 +332,247 @@ impl Default for TestSetup {
         }
     }
 }
+
+fn deploy_bucket(pic: Arc<PocketIc>, directory_id: Principal) -> PicCanister {
+    let bucket_init_args = (BucketArgs::Init(BucketInitArgs {
+        admins: vec![Principal::anonymous()],
+        shared_secret: vec![0; 32],
+        directory_id,
+    }),);
+    PicCanisterBuilder::default()
+        .with_wasm(&PicCanister::cargo_wasm_path("bucket"))
+        .with_arg(bucket_init_args)
+        .deploy_to(pic)
+}
+
+impl TestSetup {
+    /// Deploys another bucket sharing the directory's secret and provisions it.
+    #[allow(dead_code)]
+    pub fn add_bucket(&self) -> PicCanister {
+        let bucket = deploy_bucket(self.pic.clone(), self.directory.canister_id);
+        let _: ProvisionBucketResult = self
+            .directory
+            .update(
+                Principal::anonymous(),
+                "provision_bucket",
+                (bucket.canister_id,),
+            )
+            .unwrap();
+        bucket
+    }
+
+    /// Runs the full upload flow for `data` and returns the committed file.
+    ///
+    /// Directory calls go through the proxy, so the file is owned by the proxy canister.
+    #[allow(dead_code)]
+    pub fn upload_file(&self, name: &str, data: &[u8], parent: Option<FolderId>) -> FileMeta {
+        let caller = Principal::from_slice(&[1; 29]);
+
+        let start_res: StartUploadResult = self
+            .directory
+            .update_with_cycles(
+                &self.proxy,
+                caller,
+                "start_upload",
+                (
+                    name.to_string(),
+                    "application/octet-stream".to_string(),
+                    data.len() as u64,
+                    None::<PaymentType>,
+                    parent,
+                ),
+                200_000,
+            )
+            .unwrap();
+        self.finish_upload(start_res, data)
+    }
+
+    /// Starts an upload through the proxy into the root folder. The file is stored for `owner`
+    /// when given, and goes through the upload request `request` when given.
+    #[allow(dead_code)]
+    pub fn start_upload(
+        &self,
+        name: &str,
+        mime: &str,
+        size_bytes: u64,
+        owner: Option<UserId>,
+        request: Option<&[u8]>,
+    ) -> StartUploadResult {
+        self.directory
+            .update_with_cycles(
+                &self.proxy,
+                Principal::from_slice(&[1; 29]),
+                "start_upload",
+                (
+                    name.to_string(),
+                    mime.to_string(),
+                    size_bytes,
+                    None::<PaymentType>,
+                    None::<FolderId>,
+                    owner,
+                    request.map(<[u8]>::to_vec),
+                ),
+                200_000,
+            )
+            .unwrap()
+    }
+
+    /// Downloads the first chunk of a file's current version, or of `version`, from the bucket
+    /// its download plan points to.
+    #[allow(dead_code)]
+    pub fn download(&self, file_id: &FileId, version: Option<u32>) -> Vec<u8> {
+        let owner = self.proxy.canister_id;
+        let plan = match self
+            .directory
+            .query(owner, "get_download_plan", (file_id.clone(), version))
+            .unwrap()
+        {
+            GetDownloadPlanResult::Ok(plan) => plan,
+            GetDownloadPlanResult::Err(e) => panic!("Get download plan failed: {:?}", e),
+        };
+        let auth = plan.auth[0].clone();
+        let bucket = PicCanister {
+            pic: self.pic.clone(),
+            canister_id: auth.bucket_id,
+        };
+        match bucket
+            .query(owner, "get_chunk", (auth.token, 0u32))
+            .unwrap()
+        {
+            GetChunkResult::Ok(bytes) => bytes,
+            GetChunkResult::Err(e) => panic!("Get chunk failed: {:?}", e),
+        }
+    }
+
+    /// Whether `user` can read `file_id`. Errors other than `Unauthorized` fail the test.
+    #[allow(dead_code)]
+    pub fn can_read(&self, user: Principal, file_id: &FileId) -> bool {
+        let res: GetFileMetaResult = self
+            .directory
+            .query(user, "get_file_meta", (file_id.clone(),))
+            .unwrap();
+        match res {
+            GetFileMetaResult::Ok(_) => true,
+            GetFileMetaResult::Err(DirectoryError::Unauthorized) => false,
+            GetFileMetaResult::Err(e) => panic!("Get file meta failed: {:?}", e),
+        }
+    }
+
+    /// Bytes counted against `user`'s quota.
+    #[allow(dead_code)]
+    pub fn used_bytes(&self, user: Principal) -> u64 {
+        let usage: UserState = self
+            .directory
+            .query(user, "get_usage", (Some(user),))
+            .unwrap();
+        usage.used_bytes
+    }
+
+    /// Uploads `data` as the new current version of `file_id` and returns the updated file.
+    #[allow(dead_code)]
+    pub fn upload_version(&self, file_id: &FileId, data: &[u8]) -> FileMeta {
+        let caller = Principal::from_slice(&[1; 29]);
+
+        let start_res: StartUploadResult = self
+            .directory
+            .update_with_cycles(
+                &self.proxy,
+                caller,
+                "start_version_upload",
+                (
+                    file_id.clone(),
+                    "application/octet-stream".to_string(),
+                    data.len() as u64,
+                    None::<PaymentType>,
+                ),
+                200_000,
+            )
+            .unwrap();
+        self.finish_upload(start_res, data)
+    }
+
+    /// Puts the given chunks of a started session into the bucket and lets the bucket report them.
+    #[allow(dead_code)]
+    pub fn put_chunks(&self, session: &UploadSession, chunks: &[(u32, &[u8])]) {
+        if chunks.is_empty() {
+            return;
+        }
+        let caller = Principal::from_slice(&[1; 29]);
+
+        let indexes: Vec<u32> = chunks.iter().map(|(index, _)| *index).collect();
+        let token_res: GetUploadTokensResult = self
+            .directory
+            .update(
+                self.proxy.canister_id,
+                "get_upload_tokens",
+                (session.upload_id.clone(), indexes),
+            )
+            .unwrap();
+        let token = match token_res {
+            GetUploadTokensResult::Ok(t) => t[0].clone(),
+            GetUploadTokensResult::Err(e) => panic!("Get tokens failed: {:?}", e),
+        };
+
+        for (index, chunk) in chunks {
+            let put_res: PutChunkResult = self
+                .bucket
+                .update_with_cycles(
+                    &self.proxy,
+                    caller,
+                    "put_chunk",
+                    (token.clone(), *index, chunk.to_vec(), None::<PaymentType>),
+                    100_000,
+                )
+                .unwrap();
+            assert!(
+                matches!(put_res, PutChunkResult::Ok(_)),
+                "Put chunk failed: {:?}",
+                put_res
+            );
+        }
+        self.settle();
+    }
+
+    /// Lets calls the canisters made on their own, like the buckets' chunk reports, complete.
+    #[allow(dead_code)]
+    pub fn settle(&self) {
+        for _ in 0..3 {
+            self.pic.tick();
+        }
+    }
+
+    /// Puts the chunks of a started upload, reports them and commits the session.
+    #[allow(dead_code)]
+    pub fn finish_upload(&self, start_res: StartUploadResult, data: &[u8]) -> FileMeta {
+        let session = started(start_res);
+
+        let chunks: Vec<(u32, &[u8])> = data
+            .chunks(session.chunk_size as usize)
+            .enumerate()
+            .map(|(index, chunk)| (index as u32, chunk))
+            .collect();
+        self.put_chunks(&session, &chunks);
+
+        let commit_res: CommitUploadResult = self
+            .directory
+            .update(
+                self.proxy.canister_id,
+                "commit_upload",
+                (session.upload_id.clone(),),
+            )
+            .unwrap();
+        match commit_res {
+            CommitUploadResult::Ok(meta) => meta,
+            CommitUploadResult::Err(e) => panic!("Commit failed: {:?}", e),
+        }
+    }
+}
+
+/// The session of a started upload. Failing to start fails the test.
+#[allow(dead_code)]
+pub fn started(start_res: StartUploadResult) -> UploadSession {
+    match start_res {
+        StartUploadResult::Ok(session) => session,
+        StartUploadResult::Err(e) => panic!("Start upload failed: {:?}", e),
+    }
+}
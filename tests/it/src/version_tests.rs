@@ -0,0 +1,97 @@
+use candid::Principal;
+use directory::{
+    errors::DirectoryError,
+    results::{GetDownloadPlanResult, ListVersionsResult, PruneVersionsResult, UpdateFileResult},
+};
+
+use crate::util::{PicCanisterTrait, TestSetup};
+
+#[test]
+fn test_versions_restore_and_prune() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+
+    // 1. Upload a file, then a second version of it
+    let meta = setup.upload_file("notes.txt", b"first", None);
+    let file_id = meta.file_id.clone();
+    let meta = setup.upload_version(&file_id, b"second!");
+    assert_eq!(meta.file_id, file_id);
+    assert_eq!(meta.version(), 2);
+    assert_eq!(meta.size_bytes, 7);
+
+    // 2. Both versions are listed, newest first, and stay downloadable
+    let versions = match setup
+        .directory
+        .query(owner, "list_versions", (file_id.clone(),))
+        .unwrap()
+    {
+        ListVersionsResult::Ok(versions) => versions,
+        ListVersionsResult::Err(e) => panic!("List versions failed: {:?}", e),
+    };
+    let numbers: Vec<u32> = versions.iter().map(|v| v.version).collect();
+    assert_eq!(numbers, vec![2, 1]);
+    assert_eq!(setup.download(&file_id, None), b"second!");
+    assert_eq!(setup.download(&file_id, Some(1)), b"first");
+
+    let missing: GetDownloadPlanResult = setup
+        .directory
+        .query(owner, "get_download_plan", (file_id.clone(), Some(7u32)))
+        .unwrap();
+    assert!(matches!(
+        missing,
+        GetDownloadPlanResult::Err(DirectoryError::VersionNotFound(7))
+    ));
+
+    // 3. Restoring version 1 makes its content current as version 3
+    let restored = match setup
+        .directory
+        .update(owner, "restore_version", (file_id.clone(), 1u32))
+        .unwrap()
+    {
+        UpdateFileResult::Ok(meta) => meta,
+        UpdateFileResult::Err(e) => panic!("Restore failed: {:?}", e),
+    };
+    assert_eq!(restored.version(), 3);
+    assert_eq!(setup.download(&file_id, None), b"first");
+
+    // 4. Pruning down to the newest past version keeps version 2 only
+    let pruned: PruneVersionsResult = setup
+        .directory
+        .update(
+            owner,
+            "prune_versions",
+            (file_id.clone(), Some(1u32), None::<u64>),
+        )
+        .unwrap();
+    assert!(matches!(pruned, PruneVersionsResult::Ok(ref v) if v == &vec![1]));
+    assert_eq!(setup.download(&file_id, None), b"first");
+    assert_eq!(setup.download(&file_id, Some(2)), b"second!");
+}
+
+#[test]
+fn test_versions_count_towards_quota() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+
+    // Usage is tracked once the account exists
+    let set_quota: Result<(), DirectoryError> = setup
+        .directory
+        .update(Principal::anonymous(), "admin_set_quota", (owner, 1024u64))
+        .unwrap();
+    assert!(set_quota.is_ok());
+
+    let meta = setup.upload_file("data.bin", &[1; 100], None);
+    setup.upload_version(&meta.file_id, &[2; 50]);
+    assert_eq!(setup.used_bytes(owner), 150);
+
+    let pruned: PruneVersionsResult = setup
+        .directory
+        .update(
+            owner,
+            "prune_versions",
+            (meta.file_id.clone(), Some(0u32), None::<u64>),
+        )
+        .unwrap();
+    assert!(matches!(pruned, PruneVersionsResult::Ok(_)));
+    assert_eq!(setup.used_bytes(owner), 50);
+}
@@ -0,0 +1,126 @@
+use bucket::results::GetChunkResult;
+use candid::Principal;
+use directory::{
+    errors::DirectoryError,
+    results::{DeleteFileResult, GetDownloadPlanResult, GetFileMetaResult, UpdateFileResult},
+};
+use shared::types::{FileId, FileMeta, FileRole, Visibility};
+
+use crate::util::{PicCanisterTrait, TestSetup};
+
+fn set_visibility(
+    setup: &TestSetup,
+    caller: Principal,
+    file_id: &FileId,
+    visibility: Visibility,
+) -> UpdateFileResult {
+    setup
+        .directory
+        .update(caller, "set_file_visibility", (file_id.clone(), visibility))
+        .unwrap()
+}
+
+fn anonymous_plan(setup: &TestSetup, file_id: &FileId) -> GetDownloadPlanResult {
+    setup
+        .directory
+        .query(
+            Principal::anonymous(),
+            "get_download_plan",
+            (file_id.clone(), None::<u32>),
+        )
+        .unwrap()
+}
+
+#[test]
+fn test_public_and_unlisted_files() {
+    let setup = TestSetup::default();
+    let owner = setup.proxy.canister_id;
+    let anon = Principal::anonymous();
+
+    let asset = setup.upload_file("logo.png", b"logo", None);
+    let draft = setup.upload_file("draft.png", b"draft", None);
+    assert!(matches!(
+        anonymous_plan(&setup, &asset.file_id),
+        GetDownloadPlanResult::Err(DirectoryError::Unauthorized)
+    ));
+
+    // 1. Only the owner changes visibility
+    let res = set_visibility(&setup, anon, &asset.file_id, Visibility::Public);
+    assert!(matches!(
+        res,
+        UpdateFileResult::Err(DirectoryError::Unauthorized)
+    ));
+    let res = set_visibility(&setup, owner, &asset.file_id, Visibility::Public);
+    assert!(matches!(res, UpdateFileResult::Ok(ref m) if m.visibility == Some(Visibility::Public)));
+    set_visibility(&setup, owner, &draft.file_id, Visibility::Unlisted);
+
+    // 2. Anyone can download public and unlisted files
+    let plan = match anonymous_plan(&setup, &asset.file_id) {
+        GetDownloadPlanResult::Ok(plan) => plan,
+        GetDownloadPlanResult::Err(e) => panic!("Get download plan failed: {:?}", e),
+    };
+    let chunk: GetChunkResult = setup
+        .bucket
+        .query(anon, "get_chunk", (plan.auth[0].token.clone(), 0u32))
+        .unwrap();
+    assert!(matches!(chunk, GetChunkResult::Ok(bytes) if bytes == b"logo"));
+    assert!(matches!(
+        anonymous_plan(&setup, &draft.file_id),
+        GetDownloadPlanResult::Ok(_)
+    ));
+
+    // 3. Callers without a grant do not see who has access, nor the tags
+    let reader = Principal::from_slice(&[7; 29]);
+    let res: DeleteFileResult = setup
+        .directory
+        .update(
+            owner,
+            "add_file_access",
+            (asset.file_id.clone(), reader, FileRole::Reader),
+        )
+        .unwrap();
+    assert!(matches!(res, DeleteFileResult::Ok));
+    let _: UpdateFileResult = setup
+        .directory
+        .update(
+            owner,
+            "add_file_tags",
+            (asset.file_id.clone(), vec!["brand".to_string()]),
+        )
+        .unwrap();
+    let meta_for = |caller: Principal| match setup
+        .directory
+        .query(caller, "get_file_meta", (asset.file_id.clone(),))
+        .unwrap()
+    {
+        GetFileMetaResult::Ok(meta) => meta,
+        GetFileMetaResult::Err(e) => panic!("Get file meta failed: {:?}", e),
+    };
+    let redacted = meta_for(anon);
+    assert!(redacted.readers.is_empty());
+    assert!(redacted.tags.is_none());
+    let full = meta_for(reader);
+    assert_eq!(full.readers, vec![reader]);
+    assert_eq!(full.tags, Some(vec!["brand".to_string()]));
+
+    // 4. Only public files are listed
+    let files: Vec<FileMeta> = setup
+        .directory
+        .query(anon, "list_public_files", (owner,))
+        .unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].file_id, asset.file_id);
+    assert!(files[0].readers.is_empty());
+
+    // 5. Making the file private again withdraws it
+    set_visibility(&setup, owner, &asset.file_id, Visibility::Private);
+    assert!(matches!(
+        anonymous_plan(&setup, &asset.file_id),
+        GetDownloadPlanResult::Err(DirectoryError::Unauthorized)
+    ));
+    let files: Vec<FileMeta> = setup
+        .directory
+        .query(anon, "list_public_files", (owner,))
+        .unwrap();
+    assert!(files.is_empty());
+}